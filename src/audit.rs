@@ -0,0 +1,253 @@
+// ---------------------------------------------------------------------------
+// Tool-call logging & audit trail
+// ---------------------------------------------------------------------------
+//
+// Every tool call produces one structured event (tool, summarized params,
+// duration, result count, error). It goes to
+//   - `tracing` (target `obsidian_mcp::audit`),
+//   - the client as a `notifications/message`, once it has opted in with
+//     `logging/setLevel`,
+//   - optionally a JSON-lines file (`OBSIDIAN_AUDIT_LOG`).
+
+use crate::guard::SecretGuard;
+use rmcp::model::{CallToolResult, JsonObject, LoggingLevel};
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Longest string parameter kept verbatim in a summary.
+const MAX_PARAM_CHARS: usize = 80;
+
+/// Parameters holding note or file bodies (also inside canvas nodes); only
+/// their size is logged.
+const CONTENT_PARAMS: &[&str] = &["content", "content_base64", "text"];
+
+tokio::task_local! {
+    static RESULT_COUNT: Cell<Option<usize>>;
+}
+
+/// Run a tool call with a slot for `record_count`.
+pub async fn with_count<F: std::future::Future>(fut: F) -> (F::Output, Option<usize>) {
+    RESULT_COUNT
+        .scope(Cell::new(None), async move {
+            let out = fut.await;
+            (out, RESULT_COUNT.with(|c| c.get()))
+        })
+        .await
+}
+
+/// Called by list-like tools to report how many items they returned.
+pub fn record_count(n: usize) {
+    let _ = RESULT_COUNT.try_with(|c| c.set(Some(n)));
+}
+
+pub fn rank(level: &LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+/// Parameters as they are logged: note bodies cut down to their size, and
+/// other strings run through `secrets` and shortened if long.
+pub fn summarize_params(args: Option<&JsonObject>, secrets: &SecretGuard) -> Value {
+    match args {
+        Some(args) => summarize_object(args, secrets),
+        None => json!({}),
+    }
+}
+
+fn summarize_object(object: &JsonObject, secrets: &SecretGuard) -> Value {
+    let summarize_field = |(key, value): (&String, &Value)| {
+        let value = match value {
+            Value::String(s) if CONTENT_PARAMS.contains(&key.as_str()) => {
+                Value::String(format!("（共 {} 字）", s.chars().count()))
+            }
+            other => summarize(other, secrets),
+        };
+        (key.clone(), value)
+    };
+    Value::Object(object.iter().map(summarize_field).collect())
+}
+
+fn summarize(value: &Value, secrets: &SecretGuard) -> Value {
+    match value {
+        Value::String(s) => {
+            let chars = s.chars().count();
+            let s = secrets.redact(s);
+            if chars > MAX_PARAM_CHARS {
+                let head: String = s.chars().take(MAX_PARAM_CHARS).collect();
+                Value::String(format!("{head}…（共 {chars} 字）"))
+            } else {
+                Value::String(s.into_owned())
+            }
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|v| summarize(v, secrets)).collect()),
+        Value::Object(object) => summarize_object(object, secrets),
+        other => other.clone(),
+    }
+}
+
+pub struct ToolEvent<'a> {
+    pub client: &'a str,
+    pub token: &'a str,
+    pub tool: &'a str,
+    pub params: Value,
+    pub duration: Duration,
+    pub result_count: Option<usize>,
+    pub error: Option<String>,
+}
+
+impl ToolEvent<'_> {
+    pub fn from_result<'a>(
+        client: &'a str,
+        token: &'a str,
+        tool: &'a str,
+        params: Value,
+        duration: Duration,
+        result_count: Option<usize>,
+        result: &Result<CallToolResult, rmcp::Error>,
+    ) -> ToolEvent<'a> {
+        let error = match result {
+            Ok(r) if r.is_error == Some(true) => Some(
+                r.content
+                    .iter()
+                    .filter_map(|c| c.as_text().map(|t| t.text.clone()))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            Ok(_) => None,
+            Err(e) => Some(e.message.to_string()),
+        };
+        ToolEvent { client, token, tool, params, duration, result_count, error }
+    }
+
+    pub fn level(&self) -> LoggingLevel {
+        if self.error.is_some() {
+            LoggingLevel::Error
+        } else {
+            LoggingLevel::Info
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "time": chrono::Local::now().to_rfc3339(),
+            "client": self.client,
+            "token": self.token,
+            "tool": self.tool,
+            "params": self.params,
+            "duration_ms": self.duration.as_millis() as u64,
+            "result_count": self.result_count,
+            "error": self.error,
+        })
+    }
+
+    pub fn trace(&self) {
+        let duration_ms = self.duration.as_millis() as u64;
+        let params = self.params.to_string();
+        match &self.error {
+            None => tracing::info!(
+                target: "obsidian_mcp::audit",
+                client = self.client, token = self.token, tool = self.tool, %params,
+                duration_ms, result_count = ?self.result_count, "tool call"
+            ),
+            Some(error) => tracing::warn!(
+                target: "obsidian_mcp::audit",
+                client = self.client, token = self.token, tool = self.tool, %params,
+                duration_ms, %error, "tool call failed"
+            ),
+        }
+    }
+}
+
+/// Append-only JSON-lines audit file, shared by all sessions.
+pub struct AuditLog {
+    file: Mutex<std::fs::File>,
+}
+
+impl AuditLog {
+    /// Open the file named by `OBSIDIAN_AUDIT_LOG`, if set.
+    pub fn from_env() -> std::io::Result<Option<Self>> {
+        match std::env::var("OBSIDIAN_AUDIT_LOG") {
+            Ok(path) if !path.trim().is_empty() => Self::open(Path::new(path.trim())).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    pub fn write(&self, event: &Value) {
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+        if let Err(e) = writeln!(file, "{event}") {
+            tracing::warn!("failed to write audit log: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::{SecretMode, SecretsConfig};
+
+    fn guard(mode: SecretMode) -> SecretGuard {
+        SecretGuard::new(&SecretsConfig { mode, ..SecretsConfig::default() }).unwrap()
+    }
+
+    fn summary(args: Value, mode: SecretMode) -> Value {
+        summarize_params(args.as_object(), &guard(mode))
+    }
+
+    #[test]
+    fn note_bodies_are_logged_by_size() {
+        let key = "sk-abcdefghijklmnopqrstuvwxyz";
+        let args = json!({
+            "path": "tech/a.md",
+            "content": format!("api_key = {key}"),
+            "nodes": [{ "id": "n1", "type": "text", "text": "short secret" }],
+        });
+        let logged = summary(args, SecretMode::Redact);
+        assert_eq!(logged["path"], "tech/a.md");
+        assert_eq!(logged["content"], "（共 39 字）");
+        assert_eq!(logged["nodes"][0]["text"], "（共 12 字）");
+        assert_eq!(logged["nodes"][0]["id"], "n1");
+    }
+
+    #[test]
+    fn other_strings_are_redacted_even_in_block_mode() {
+        let args = json!({ "keyword": "password = hunter2hunter2", "tags": ["sk-abcdefghijklmnopqrstuvwxyz"] });
+        for mode in [SecretMode::Redact, SecretMode::Block] {
+            let logged = summary(args.clone(), mode).to_string();
+            assert!(!logged.contains("hunter2hunter2"), "{logged}");
+            assert!(!logged.contains("sk-abcdefghijklmnopqrstuvwxyz"), "{logged}");
+        }
+        let logged = summary(args, SecretMode::Off).to_string();
+        assert!(logged.contains("hunter2hunter2"));
+    }
+
+    #[test]
+    fn long_strings_are_shortened_after_redaction() {
+        let long = format!("{} token: {}", "x".repeat(70), "abcdefghijklmnop");
+        let logged = summary(json!({ "keyword": long }), SecretMode::Redact);
+        let logged = logged["keyword"].as_str().unwrap();
+        assert!(!logged.contains("abcdefgh"));
+        assert!(logged.ends_with("…（共 94 字）"), "{logged}");
+    }
+}
@@ -0,0 +1,186 @@
+// ---------------------------------------------------------------------------
+// Bearer tokens & scopes
+// ---------------------------------------------------------------------------
+//
+// The HTTP transport can require `Authorization: Bearer <token>`. Tokens are
+// listed in a TOML file (`OBSIDIAN_TOKENS_FILE` / `--tokens`):
+//
+//   [[tokens]]
+//   name = "reader"
+//   token = "..."
+//   access = "read"                # read | write
+//   directories = ["tech", "ai"]   # optional, default: the whole vault
+//
+// Each token maps to a `Scope` that the tool handlers enforce. A tokens file
+// without tokens is an error rather than "no authentication". stdio clients
+// always get full access.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    /// Token name, for logs.
+    pub name: String,
+    pub write: bool,
+    /// Vault-relative directories the token may touch; empty = everything.
+    pub directories: Vec<String>,
+}
+
+impl Scope {
+    pub fn full() -> Self {
+        Self {
+            name: "local".to_string(),
+            write: true,
+            directories: Vec::new(),
+        }
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        !self.directories.is_empty()
+    }
+
+    /// Whether the file or directory `rel_path` is inside the scope.
+    pub fn allows(&self, rel_path: &str) -> bool {
+        let rel = rel_path.trim_matches('/');
+        self.directories.is_empty()
+            || self
+                .directories
+                .iter()
+                .any(|d| rel == d || rel.starts_with(&format!("{d}/")))
+    }
+
+    /// Like `allows`, but also true for the parents of allowed directories,
+    /// so they can be shown in listings.
+    pub fn allows_prefix(&self, rel_path: &str) -> bool {
+        let rel = rel_path.trim_matches('/');
+        self.allows(rel)
+            || rel.is_empty()
+            || self.directories.iter().any(|d| d.starts_with(&format!("{rel}/")))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenEntry {
+    name: String,
+    token: String,
+    access: Access,
+    #[serde(default)]
+    directories: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenFile {
+    #[serde(default)]
+    tokens: Vec<TokenEntry>,
+}
+
+/// Which configured token authenticated a request. HTTP sessions stay bound
+/// to the token that opened them, even if another token has the same scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenId(usize);
+
+#[derive(Debug, Default, Clone)]
+pub struct TokenStore {
+    tokens: HashMap<String, (TokenId, Scope)>,
+}
+
+impl TokenStore {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read tokens file {}: {e}", path.display()))?;
+        let file: TokenFile = toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("invalid tokens file {}: {e}", path.display()))?;
+        if file.tokens.is_empty() {
+            anyhow::bail!("tokens file {} lists no tokens", path.display());
+        }
+        Self::from_entries(file.tokens)
+    }
+
+    fn from_entries(entries: Vec<TokenEntry>) -> anyhow::Result<Self> {
+        let mut tokens = HashMap::new();
+        for (i, entry) in entries.into_iter().enumerate() {
+            if entry.token.len() < 16 {
+                anyhow::bail!("token '{}' is too short (at least 16 characters)", entry.name);
+            }
+            let scope = Scope {
+                name: entry.name,
+                write: matches!(entry.access, Access::Write),
+                directories: entry
+                    .directories
+                    .iter()
+                    .map(|d| d.trim().trim_matches('/').to_string())
+                    .filter(|d| !d.is_empty())
+                    .collect(),
+            };
+            if tokens.insert(entry.token, (TokenId(i), scope)).is_some() {
+                anyhow::bail!("duplicate token in tokens file");
+            }
+        }
+        Ok(Self { tokens })
+    }
+
+    /// Token and scope for an `Authorization` header value.
+    pub fn authenticate(&self, header: &str) -> Option<(TokenId, &Scope)> {
+        let presented = header.strip_prefix("Bearer ")?.trim();
+        // compare against every token in constant time
+        let mut found = None;
+        for (token, (id, scope)) in &self.tokens {
+            if constant_time_eq(token.as_bytes(), presented.as_bytes()) {
+                found = Some((*id, scope));
+            }
+        }
+        found
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, token: &str, directories: &[&str]) -> TokenEntry {
+        TokenEntry {
+            name: name.to_string(),
+            token: token.to_string(),
+            access: Access::Write,
+            directories: directories.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn load_rejects_a_file_without_tokens() {
+        let path = std::env::temp_dir().join(format!("obsidian-mcp-tokens-{}.toml", std::process::id()));
+        std::fs::write(&path, "# no tokens yet\n").unwrap();
+        assert!(TokenStore::load(&path).is_err());
+        std::fs::write(&path, "tokens = []\n").unwrap();
+        assert!(TokenStore::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tokens_with_the_same_scope_have_distinct_ids() {
+        let store = TokenStore::from_entries(vec![
+            entry("alice", "aaaaaaaaaaaaaaaaaaaa", &["tech"]),
+            entry("alice", "bbbbbbbbbbbbbbbbbbbb", &["tech"]),
+        ])
+        .unwrap();
+        let (a, scope_a) = store.authenticate("Bearer aaaaaaaaaaaaaaaaaaaa").unwrap();
+        let (b, scope_b) = store.authenticate("Bearer bbbbbbbbbbbbbbbbbbbb").unwrap();
+        assert_eq!(scope_a, scope_b);
+        assert_ne!(a, b);
+        assert!(store.authenticate("Bearer cccccccccccccccccccc").is_none());
+        assert!(store.authenticate("aaaaaaaaaaaaaaaaaaaa").is_none());
+    }
+}
@@ -0,0 +1,448 @@
+// ---------------------------------------------------------------------------
+// JSON Canvas
+// ---------------------------------------------------------------------------
+//
+// Obsidian's .canvas files (https://jsoncanvas.org, spec 1.0):
+//   { "nodes": [...], "edges": [...] }
+//   node   id, type, x, y, width, height, color?
+//          text   text            markdown
+//          file   file, subpath?  vault path, subpath like "#heading"
+//          link   url
+//          group  label?, background?, backgroundStyle? (cover|ratio|repeat)
+//   edge   id, fromNode, fromSide?, fromEnd?, toNode, toSide?, toEnd?,
+//          color?, label?    sides: top|right|bottom|left, ends: none|arrow
+//   color  "1"–"6" (preset) or "#RRGGBB"
+//
+// The typed model is used for reading and validation. Changes to existing
+// files are applied to the raw JSON, so fields Obsidian (or a plugin) added
+// beyond the spec and the key order survive, and the file is written back
+// tab-indented as Obsidian does.
+
+use crate::links::{self, LinkKind};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Canvas {
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "Canvas 节点，坐标与尺寸为整数像素")]
+pub struct Node {
+    #[schemars(description = "唯一 ID（节点与连线之间也不能重复）")]
+    pub id: String,
+    #[serde(flatten)]
+    pub kind: NodeKind,
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64,
+    #[schemars(description = "可选。预设颜色 \"1\"–\"6\"（红、橙、黄、绿、青、紫）或 \"#RRGGBB\"")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NodeKind {
+    /// Markdown text card.
+    Text { text: String },
+    /// A vault file; `subpath` (`#heading` / `#^block`) narrows it.
+    File {
+        file: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        subpath: Option<String>,
+    },
+    Link { url: String },
+    Group {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        background: Option<String>,
+        #[serde(default, rename = "backgroundStyle", skip_serializing_if = "Option::is_none")]
+        background_style: Option<BackgroundStyle>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundStyle {
+    Cover,
+    Ratio,
+    Repeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum End {
+    None,
+    Arrow,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(description = "Canvas 连线")]
+pub struct Edge {
+    pub id: String,
+    pub from_node: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_side: Option<Side>,
+    #[schemars(description = "可选。起点端样式，默认 none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_end: Option<End>,
+    pub to_node: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_side: Option<Side>,
+    #[schemars(description = "可选。终点端样式，默认 arrow")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_end: Option<End>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl Canvas {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if text.trim().is_empty() {
+            // Obsidian creates new canvases as empty files
+            return Ok(Self::default());
+        }
+        serde_json::from_str(text).map_err(|e| format!("不是有效的 JSON Canvas：{e}"))
+    }
+
+    /// Spec violations: duplicate ids, edges to missing nodes, bad colors,
+    /// sizes or subpaths. Empty when the canvas is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut ids = HashSet::new();
+        for id in self.nodes.iter().map(|n| &n.id).chain(self.edges.iter().map(|e| &e.id)) {
+            if id.trim().is_empty() {
+                errors.push("存在空的 id".to_string());
+            } else if !ids.insert(id.as_str()) {
+                errors.push(format!("id 重复：{id}"));
+            }
+        }
+        for node in &self.nodes {
+            if node.width <= 0 || node.height <= 0 {
+                errors.push(format!("节点 {} 的 width / height 必须为正数", node.id));
+            }
+            if let Some(color) = &node.color {
+                if !valid_color(color) {
+                    errors.push(format!("节点 {} 的颜色无效：{color}（应为 \"1\"–\"6\" 或 \"#RRGGBB\"）", node.id));
+                }
+            }
+            match &node.kind {
+                NodeKind::File { file, subpath } => {
+                    if file.trim().is_empty() {
+                        errors.push(format!("文件节点 {} 缺少 file", node.id));
+                    }
+                    if subpath.as_ref().is_some_and(|s| !s.starts_with('#')) {
+                        errors.push(format!("文件节点 {} 的 subpath 必须以 # 开头", node.id));
+                    }
+                }
+                NodeKind::Link { url } if url.trim().is_empty() => {
+                    errors.push(format!("链接节点 {} 缺少 url", node.id));
+                }
+                _ => {}
+            }
+        }
+        let nodes: HashSet<&str> = self.nodes.iter().map(|n| n.id.as_str()).collect();
+        for edge in &self.edges {
+            for end in [&edge.from_node, &edge.to_node] {
+                if !nodes.contains(end.as_str()) {
+                    errors.push(format!("连线 {} 指向不存在的节点：{end}", edge.id));
+                }
+            }
+            if let Some(color) = &edge.color {
+                if !valid_color(color) {
+                    errors.push(format!("连线 {} 的颜色无效：{color}", edge.id));
+                }
+            }
+        }
+        errors
+    }
+
+    /// Vault paths of the file nodes, and the link targets (kind, target) in
+    /// text nodes.
+    pub fn references(&self) -> (Vec<&str>, Vec<(LinkKind, String)>) {
+        let mut files = Vec::new();
+        let mut text_links = Vec::new();
+        for node in &self.nodes {
+            match &node.kind {
+                NodeKind::File { file, .. } => files.push(file.as_str()),
+                NodeKind::Text { text } => {
+                    for link in links::scan_links(text) {
+                        text_links.push((link.kind, text[link.target].to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        (files, text_links)
+    }
+
+    /// Readable outline: nodes nested by group, in reading order, then edges.
+    pub fn outline(&self, rel_path: &str) -> String {
+        let count = |pred: fn(&NodeKind) -> bool| self.nodes.iter().filter(|n| pred(&n.kind)).count();
+        let mut out = format!(
+            "## 🗺️ `{rel_path}`\n\n共 {} 个节点（文本 {}、文件 {}、链接 {}、分组 {}），{} 条连线\n",
+            self.nodes.len(),
+            count(|k| matches!(k, NodeKind::Text { .. })),
+            count(|k| matches!(k, NodeKind::File { .. })),
+            count(|k| matches!(k, NodeKind::Link { .. })),
+            count(|k| matches!(k, NodeKind::Group { .. })),
+            self.edges.len(),
+        );
+        if self.nodes.is_empty() {
+            out.push_str("\n（空白画布）\n");
+            return out;
+        }
+
+        // each node belongs to the smallest group that contains it
+        let parent: Vec<Option<usize>> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                self.nodes
+                    .iter()
+                    .enumerate()
+                    .filter(|&(g, group)| g != i && matches!(group.kind, NodeKind::Group { .. }) && contains(group, node))
+                    .min_by_key(|(_, group)| group.width * group.height)
+                    .map(|(g, _)| g)
+            })
+            .collect();
+        let mut children: HashMap<Option<usize>, Vec<usize>> = HashMap::new();
+        for (i, p) in parent.iter().enumerate() {
+            children.entry(*p).or_default().push(i);
+        }
+        for list in children.values_mut() {
+            list.sort_by_key(|&i| (self.nodes[i].y, self.nodes[i].x));
+        }
+
+        out.push_str("\n### 节点\n\n");
+        self.outline_nodes(&children, None, 0, &mut out);
+
+        if !self.edges.is_empty() {
+            out.push_str("\n### 连线\n\n");
+            for edge in &self.edges {
+                let arrow = match (edge.from_end.unwrap_or(End::None), edge.to_end.unwrap_or(End::Arrow)) {
+                    (End::Arrow, End::Arrow) => "↔",
+                    (End::None, End::Arrow) => "→",
+                    (End::Arrow, End::None) => "←",
+                    (End::None, End::None) => "—",
+                };
+                out.push_str(&format!(
+                    "- {} {arrow} {}{}\n",
+                    self.node_name(&edge.from_node),
+                    self.node_name(&edge.to_node),
+                    edge.label.as_ref().map(|l| format!("：{l}")).unwrap_or_default(),
+                ));
+            }
+        }
+        out
+    }
+
+    fn outline_nodes(&self, children: &HashMap<Option<usize>, Vec<usize>>, parent: Option<usize>, depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+        for &i in children.get(&parent).map(Vec::as_slice).unwrap_or_default() {
+            let node = &self.nodes[i];
+            let line = match &node.kind {
+                NodeKind::Text { text } => {
+                    let mut lines = text.trim().lines();
+                    let mut s = format!("📝 `{}` {}", node.id, lines.next().unwrap_or(""));
+                    for l in lines {
+                        s.push_str(&format!("\n{indent}  {l}"));
+                    }
+                    s
+                }
+                NodeKind::File { file, subpath } => {
+                    format!("📄 `{}` 文件 `{file}{}`", node.id, subpath.as_deref().unwrap_or(""))
+                }
+                NodeKind::Link { url } => format!("🔗 `{}` 链接 {url}", node.id),
+                NodeKind::Group { label, .. } => {
+                    format!("📦 `{}` 分组「{}」", node.id, label.as_deref().unwrap_or("未命名"))
+                }
+            };
+            out.push_str(&format!("{indent}- {line}\n"));
+            if matches!(node.kind, NodeKind::Group { .. }) {
+                self.outline_nodes(children, Some(i), depth + 1, out);
+            }
+        }
+    }
+
+    /// `id` plus a short description, for edge listings.
+    fn node_name(&self, id: &str) -> String {
+        let Some(node) = self.nodes.iter().find(|n| n.id == id) else {
+            return format!("`{id}`（不存在）");
+        };
+        let what = match &node.kind {
+            NodeKind::Text { text } => {
+                let first = text.trim().lines().next().unwrap_or("");
+                let short: String = first.chars().take(30).collect();
+                if short.len() < first.len() {
+                    format!("{short}…")
+                } else {
+                    short
+                }
+            }
+            NodeKind::File { file, .. } => file.clone(),
+            NodeKind::Link { url } => url.clone(),
+            NodeKind::Group { label, .. } => label.clone().unwrap_or_else(|| "分组".to_string()),
+        };
+        format!("`{id}`「{what}」")
+    }
+}
+
+fn contains(group: &Node, node: &Node) -> bool {
+    node.x >= group.x
+        && node.y >= group.y
+        && node.x + node.width <= group.x + group.width
+        && node.y + node.height <= group.y + group.height
+}
+
+fn valid_color(color: &str) -> bool {
+    matches!(color, "1" | "2" | "3" | "4" | "5" | "6")
+        || (color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Number of nodes plus edges in a raw canvas object.
+pub fn item_count(map: &serde_json::Map<String, Value>) -> usize {
+    ["nodes", "edges"]
+        .iter()
+        .filter_map(|key| map.get(*key).and_then(Value::as_array))
+        .map(Vec::len)
+        .sum()
+}
+
+/// Serialize like Obsidian: tab-indented JSON.
+pub fn to_string(value: &Value) -> String {
+    let mut buf = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"\t");
+    let mut ser = serde_json::Serializer::with_formatter(&mut buf, formatter);
+    value.serialize(&mut ser).expect("JSON value serializes");
+    String::from_utf8(buf).expect("serde_json writes UTF-8")
+}
+
+/// Rewrite the references a canvas (raw JSON) holds to the note `old_rel`
+/// after it moved to `new_rel`: file nodes pointing at it, and links in text
+/// nodes (via `rewrite`, which returns the new text and the number of links
+/// changed). Returns the new file content and the number of references
+/// changed.
+pub fn retarget(content: &str, old_rel: &str, new_rel: &str, rewrite: impl Fn(&str) -> (String, usize)) -> Option<(String, usize)> {
+    let mut value: Value = serde_json::from_str(content).ok()?;
+    let mut count = 0;
+    for node in value.get_mut("nodes")?.as_array_mut()? {
+        match node.get("type").and_then(Value::as_str) {
+            Some("file") if node.get("file").and_then(Value::as_str) == Some(old_rel) => {
+                node["file"] = Value::String(new_rel.to_string());
+                count += 1;
+            }
+            Some("text") => {
+                if let Some(text) = node.get("text").and_then(Value::as_str) {
+                    let (updated, n) = rewrite(text);
+                    if n > 0 {
+                        node["text"] = Value::String(updated);
+                        count += n;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    (count > 0).then(|| (to_string(&value), count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = r##"{
+	"nodes": [
+		{"id": "t", "type": "text", "text": "see [[a]]", "x": 0, "y": 0, "width": 200, "height": 80, "color": "1"},
+		{"id": "f", "type": "file", "file": "tech/a.md", "subpath": "#部署", "x": 300, "y": 0, "width": 200, "height": 80},
+		{"id": "g", "type": "group", "label": "G", "x": -20, "y": -20, "width": 600, "height": 200, "color": "#FF00aa"}
+	],
+	"edges": [
+		{"id": "e", "fromNode": "t", "toNode": "f", "toEnd": "arrow", "custom": true}
+	]
+}"##;
+
+    #[test]
+    fn valid_canvases_have_no_problems() {
+        assert!(Canvas::parse(VALID).unwrap().validate().is_empty());
+        assert!(Canvas::parse("").unwrap().validate().is_empty());
+        assert!(Canvas::parse("{\"nodes\": [}").is_err());
+    }
+
+    #[test]
+    fn validate_reports_spec_violations() {
+        let canvas = r##"{
+	"nodes": [
+		{"id": "a", "type": "text", "text": "", "x": 0, "y": 0, "width": 0, "height": 10, "color": "7"},
+		{"id": "a", "type": "file", "file": " ", "subpath": "部署", "x": 0, "y": 0, "width": 10, "height": 10},
+		{"id": " ", "type": "link", "url": "", "x": 0, "y": 0, "width": 10, "height": 10, "color": "#12345"}
+	],
+	"edges": [
+		{"id": "e", "fromNode": "a", "toNode": "missing", "color": "red"}
+	]
+}"##;
+        let problems = Canvas::parse(canvas).unwrap().validate();
+        assert_eq!(
+            problems,
+            [
+                "id 重复：a",
+                "存在空的 id",
+                "节点 a 的 width / height 必须为正数",
+                "节点 a 的颜色无效：7（应为 \"1\"–\"6\" 或 \"#RRGGBB\"）",
+                "文件节点 a 缺少 file",
+                "文件节点 a 的 subpath 必须以 # 开头",
+                "节点   的颜色无效：#12345（应为 \"1\"–\"6\" 或 \"#RRGGBB\"）",
+                "链接节点   缺少 url",
+                "连线 e 指向不存在的节点：missing",
+                "连线 e 的颜色无效：red",
+            ]
+        );
+    }
+
+    #[test]
+    fn retarget_updates_file_nodes_and_text_links_only() {
+        let rewrite = |text: &str| match text.contains("[[a]]") {
+            true => (text.replace("[[a]]", "[[b]]"), 1),
+            false => (text.to_string(), 0),
+        };
+        let (updated, count) = retarget(VALID, "tech/a.md", "ai/b.md", rewrite).unwrap();
+        assert_eq!(count, 2);
+        let value: Value = serde_json::from_str(&updated).unwrap();
+        assert_eq!(value["nodes"][0]["text"], "see [[b]]");
+        assert_eq!(value["nodes"][1]["file"], "ai/b.md");
+        assert_eq!(value["nodes"][1]["subpath"], "#部署");
+        assert_eq!(value["edges"][0]["custom"], true);
+        assert!(updated.contains("\n\t\"nodes\""), "{updated}");
+        let keys: Vec<_> = value["nodes"][0].as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys, ["id", "type", "text", "x", "y", "width", "height", "color"]);
+    }
+
+    #[test]
+    fn retarget_leaves_unrelated_canvases_alone() {
+        let untouched = |text: &str| (text.to_string(), 0);
+        assert!(retarget(VALID, "tech/other.md", "ai/b.md", untouched).is_none());
+        assert!(retarget("not json", "tech/a.md", "ai/b.md", untouched).is_none());
+        assert!(retarget("{}", "tech/a.md", "ai/b.md", untouched).is_none());
+    }
+}
@@ -0,0 +1,126 @@
+// ---------------------------------------------------------------------------
+// Argument completion
+// ---------------------------------------------------------------------------
+//
+// `completion/complete` for prompt and resource-template arguments, backed by
+// the vault index:
+//   path       note paths (plus attachments for obsidian://note/{path})
+//   tag(s)     tag_map keys
+//   directory  configured directories (plus sub-directories for obsidian://dir/{directory})
+//
+// Candidates are ranked prefix match > path-segment prefix > substring >
+// fuzzy (characters in order), case-insensitively.
+
+use crate::auth::Scope;
+use crate::config::Config;
+use crate::resources::{self, DIR_PREFIX, NOTE_PREFIX, TAG_PREFIX};
+use crate::VaultIndex;
+use rmcp::model::*;
+use std::collections::BTreeSet;
+
+/// The spec caps a completion response at 100 values.
+const MAX_VALUES: usize = 100;
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Note,
+    NoteOrAttachment,
+    Tag,
+    Directory,
+    VaultDirectory,
+}
+
+fn kind_of(reference: &Reference, argument: &str) -> Option<Kind> {
+    match reference {
+        // prompt arguments share names with the tool parameters they feed
+        Reference::Prompt(_) => match argument {
+            "path" => Some(Kind::Note),
+            "tag" | "tags" => Some(Kind::Tag),
+            "directory" => Some(Kind::Directory),
+            _ => None,
+        },
+        Reference::Resource(r) => {
+            let uri = r.uri.as_str();
+            match argument {
+                "path" if uri.starts_with(NOTE_PREFIX) => Some(Kind::NoteOrAttachment),
+                "tag" if uri.starts_with(TAG_PREFIX) => Some(Kind::Tag),
+                "directory" if uri.starts_with(DIR_PREFIX) => Some(Kind::VaultDirectory),
+                _ => None,
+            }
+        }
+    }
+}
+
+fn candidates(index: &VaultIndex, config: &Config, scope: &Scope, kind: Kind) -> Vec<String> {
+    let notes = || index.entries.iter().map(|e| e.rel_path.clone());
+    let directories = || {
+        config
+            .directory_names()
+            .into_iter()
+            .filter(|d| scope.allows(d))
+            .map(str::to_string)
+    };
+    match kind {
+        Kind::Note => notes().collect(),
+        Kind::NoteOrAttachment => notes().chain(resources::attachment_paths(config, scope)).collect(),
+        Kind::Tag => index.tag_map.keys().cloned().collect(),
+        Kind::Directory => directories().collect(),
+        Kind::VaultDirectory => {
+            let mut dirs: BTreeSet<String> = directories().collect();
+            for e in &index.entries {
+                let mut dir = e.rel_path.as_str();
+                while let Some(pos) = dir.rfind('/') {
+                    dir = &dir[..pos];
+                    dirs.insert(dir.to_string());
+                }
+            }
+            dirs.into_iter().collect()
+        }
+    }
+}
+
+/// Lower is better; `None` when `candidate` does not match at all.
+fn score(candidate: &str, query: &str) -> Option<u8> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let c = candidate.to_lowercase();
+    if c.starts_with(query) {
+        return Some(0);
+    }
+    if c.split(['/', '-', '_', ' ']).any(|seg| seg.starts_with(query)) {
+        return Some(1);
+    }
+    if c.contains(query) {
+        return Some(2);
+    }
+    let mut chars = c.chars();
+    query
+        .chars()
+        .all(|q| chars.by_ref().any(|ch| ch == q))
+        .then_some(3)
+}
+
+pub fn complete(index: &VaultIndex, config: &Config, scope: &Scope, request: &CompleteRequestParam) -> CompleteResult {
+    let values = match kind_of(&request.r#ref, &request.argument.name) {
+        Some(kind) => {
+            let query = request.argument.value.trim().trim_start_matches('/').to_lowercase();
+            let mut ranked: Vec<(u8, String)> = candidates(index, config, scope, kind)
+                .into_iter()
+                .filter_map(|c| score(&c, &query).map(|s| (s, c)))
+                .collect();
+            ranked.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.len().cmp(&b.1.len())).then(a.1.cmp(&b.1)));
+            ranked.into_iter().map(|(_, c)| c).collect()
+        }
+        None => Vec::new(),
+    };
+
+    let total = values.len();
+    CompleteResult {
+        completion: CompletionInfo {
+            values: values.into_iter().take(MAX_VALUES).collect(),
+            total: u32::try_from(total).ok(),
+            has_more: Some(total > MAX_VALUES),
+        },
+    }
+}
@@ -0,0 +1,606 @@
+// ---------------------------------------------------------------------------
+// Configuration file
+// ---------------------------------------------------------------------------
+//
+// Vault conventions come from a TOML file, looked up in this order:
+//   1. --config <path>
+//   2. OBSIDIAN_CONFIG
+//   3. <vault>/.obsidian-mcp.toml   (vault from OBSIDIAN_VAULT_ROOT)
+// Without a file the built-in conventions of write-note-tips.md apply.
+//
+//   vault = "/path/to/vault"               # OBSIDIAN_VAULT_ROOT wins if set
+//   tips_file = "tips.md"                  # relative to the config file
+//   statuses = ["active", "archived", "draft"]
+//   ignore = ["templates", "*.excalidraw.md"]   # gitignore syntax
+//   journal_directory = "journal"          # for the daily_journal prompt
+//   enabled_tools = ["read_note", "query_note"]   # OBSIDIAN_ENABLED_TOOLS wins if set
+//
+//   [filenames]
+//   lowercase = true
+//   ascii_only = true
+//   allow_spaces = false
+//   max_length = 80                        # optional
+//
+//   [[directories]]
+//   name = "tech"
+//   description = "技术知识"
+//
+// Relative paths are resolved against the config file's directory. The
+// vault's .obsidian/ settings and .obsidianmcpignore are read as well (see
+// `obsidian` and `filter`).
+//
+// One server can also serve several vaults. Each `[[vaults]]` entry names a
+// vault; its conventions come from `config` (default: the vault's own
+// .obsidian-mcp.toml, whose `vault` key is then ignored) or, without one,
+// from the top-level settings of this file:
+//
+//   [[vaults]]
+//   name = "personal"
+//   path = "/path/to/personal"
+//
+//   [[vaults]]
+//   name = "team"
+//   path = "/srv/team-vault"
+//   config = "team.toml"                   # optional
+//
+// The first vault is the default one for tools called without `vault`, and
+// the one exposed as resources and prompts. `enabled_tools` applies to the
+// whole server and is only read from this file. Single-vault configs name their
+// vault with `name` (default: the vault folder's name).
+
+use anyhow::{bail, Context};
+use crate::filter::VaultFilter;
+use crate::guard::{ProtectedPaths, ProtectedPathsConfig, SecretGuard, SecretsConfig};
+use crate::obsidian::AppSettings;
+use rmcp::model::ErrorData;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+const DEFAULT_TIPS: &str = include_str!("../write-note-tips.md");
+
+/// Name of the config file looked up in the vault root.
+pub const VAULT_CONFIG_FILE: &str = ".obsidian-mcp.toml";
+
+const DEFAULT_DIRECTORIES: &[(&str, &str)] = &[
+    ("tech", "技术知识（Docker、Linux、Git、Rust、部署等），按主题合并"),
+    ("ai", "AI 工程（MCP 开发、AI 工具、Prompt）"),
+    ("projects", "项目实践文档，按项目名建子目录"),
+    ("methods", "学习方法论、思维模型、工作流"),
+    ("career", "简历、面试、心态管理"),
+    ("ideas", "项目构想和设计草案"),
+    ("cheatsheet", "速查手册（命令、路径、代码片段）"),
+    ("journal", "工作日志，文件名 YYYY-MM-DD-主题"),
+];
+
+const DEFAULT_STATUSES: &[&str] = &["active", "archived", "draft"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Directory {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct FilenameRules {
+    pub lowercase: bool,
+    pub ascii_only: bool,
+    pub allow_spaces: bool,
+    pub max_length: Option<usize>,
+}
+
+impl Default for FilenameRules {
+    fn default() -> Self {
+        Self {
+            lowercase: true,
+            ascii_only: true,
+            allow_spaces: false,
+            max_length: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VaultEntry {
+    name: String,
+    path: PathBuf,
+    config: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    name: Option<String>,
+    vault: Option<PathBuf>,
+    vaults: Option<Vec<VaultEntry>>,
+    tips_file: Option<PathBuf>,
+    directories: Option<Vec<Directory>>,
+    statuses: Option<Vec<String>>,
+    journal_directory: Option<String>,
+    enabled_tools: Option<Vec<String>>,
+    #[serde(default)]
+    filenames: FilenameRules,
+    #[serde(default)]
+    ignore: Vec<String>,
+    #[serde(default)]
+    secrets: SecretsConfig,
+    #[serde(default)]
+    protected_paths: ProtectedPathsConfig,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    /// Name clients pass as `vault` to pick this vault.
+    pub name: String,
+    pub vault_root: PathBuf,
+    pub directories: Vec<Directory>,
+    pub statuses: Vec<String>,
+    /// Where the `daily_journal` prompt writes; one of `directories`.
+    /// `None` if not configured and there is no `journal` directory.
+    pub journal_directory: Option<String>,
+    /// Tools to register (see `toolset`); `None` = all of them.
+    pub enabled_tools: Option<Vec<String>>,
+    pub filenames: FilenameRules,
+    pub ignore_patterns: Vec<String>,
+    /// Settings Obsidian keeps in the vault's .obsidian/ folder.
+    pub obsidian: AppSettings,
+    filter: VaultFilter,
+    /// Redacts or blocks secrets in everything handed out to clients.
+    pub secrets: SecretGuard,
+    pub protected: ProtectedPaths,
+    /// Contents of the tips document returned by `write_note_tips`.
+    pub tips: String,
+    /// The file the settings were read from; `None` = built-in defaults.
+    pub source: Option<PathBuf>,
+}
+
+impl Config {
+    /// Find and load the config file (see the module comment). Returns the
+    /// configured vaults, the default one first.
+    pub fn load(explicit: Option<&Path>) -> anyhow::Result<Vec<Self>> {
+        let env_vault = std::env::var("OBSIDIAN_VAULT_ROOT").ok().filter(|v| !v.trim().is_empty());
+        let path = match explicit {
+            Some(path) => Some(path.to_path_buf()),
+            None => match std::env::var("OBSIDIAN_CONFIG").ok().filter(|v| !v.trim().is_empty()) {
+                Some(path) => Some(PathBuf::from(path)),
+                None => env_vault
+                    .as_ref()
+                    .map(|vault| Path::new(vault).join(VAULT_CONFIG_FILE))
+                    .filter(|p| p.is_file()),
+            },
+        };
+
+        let (mut file, base) = match &path {
+            Some(path) => (read_file(path)?, path.parent().map(Path::to_path_buf).unwrap_or_default()),
+            None => (ConfigFile::default(), PathBuf::new()),
+        };
+        let context = || match &path {
+            Some(path) => format!("invalid config file {}", path.display()),
+            None => "invalid configuration".to_string(),
+        };
+
+        let Some(entries) = file.vaults.take() else {
+            let vault_root = match (env_vault, &file.vault) {
+                (Some(vault), _) => PathBuf::from(vault),
+                (None, Some(vault)) => base.join(vault),
+                (None, None) => bail!(
+                    "no vault configured: set OBSIDIAN_VAULT_ROOT or `vault` in a config file (--config / OBSIDIAN_CONFIG)"
+                ),
+            };
+            let name = match &file.name {
+                Some(name) => name.clone(),
+                None => std::fs::canonicalize(&vault_root)
+                    .ok()
+                    .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+                    .unwrap_or_else(|| "default".to_string()),
+            };
+            let config = Self::from_file(file, &base, name, vault_root, path.clone()).with_context(context)?;
+            return Ok(vec![config]);
+        };
+
+        if file.vault.is_some() || file.name.is_some() {
+            bail!("{}: `vault` / `name` cannot be combined with [[vaults]]", context());
+        }
+        if env_vault.is_some() {
+            bail!("{}: OBSIDIAN_VAULT_ROOT cannot be combined with [[vaults]]", context());
+        }
+        if entries.is_empty() {
+            bail!("{}: [[vaults]] must list at least one vault", context());
+        }
+
+        let mut seen = HashSet::new();
+        let mut vaults = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let name = entry.name.as_str();
+            if name.is_empty() || name.contains(char::is_whitespace) || name.contains(['/', '\\']) {
+                bail!("{}: vault name '{name}' must be a single word without '/'", context());
+            }
+            if !seen.insert(entry.name.clone()) {
+                bail!("{}: vault '{name}' is listed twice", context());
+            }
+            let vault_root = base.join(&entry.path);
+            let own = match &entry.config {
+                Some(config) => Some(base.join(config)),
+                None => Some(vault_root.join(VAULT_CONFIG_FILE)).filter(|p| p.is_file()),
+            };
+            let config = match own {
+                Some(own) => {
+                    let mut vault_file = read_file(&own)?;
+                    if vault_file.vaults.is_some() {
+                        bail!("invalid config file {}: [[vaults]] is only allowed in the main config file", own.display());
+                    }
+                    if vault_file.enabled_tools.is_some() {
+                        bail!("invalid config file {}: `enabled_tools` is only allowed in the main config file", own.display());
+                    }
+                    vault_file.vault = None;
+                    let vault_base = own.parent().map(Path::to_path_buf).unwrap_or_default();
+                    Self::from_file(vault_file, &vault_base, entry.name.clone(), vault_root, Some(own.clone()))
+                        .with_context(|| format!("invalid config file {}", own.display()))?
+                }
+                None => Self::from_file(file.clone(), &base, entry.name.clone(), vault_root, path.clone())
+                    .with_context(|| format!("{}: vault '{name}'", context()))?,
+            };
+            vaults.push(Self { enabled_tools: file.enabled_tools.clone(), ..config });
+        }
+        Ok(vaults)
+    }
+
+    fn from_file(
+        file: ConfigFile,
+        base: &Path,
+        name: String,
+        vault_root: PathBuf,
+        source: Option<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        if !vault_root.is_dir() {
+            bail!("vault directory {} does not exist", vault_root.display());
+        }
+
+        let directories = file.directories.unwrap_or_else(|| {
+            DEFAULT_DIRECTORIES
+                .iter()
+                .map(|(name, description)| Directory {
+                    name: name.to_string(),
+                    description: description.to_string(),
+                })
+                .collect()
+        });
+        if directories.is_empty() {
+            bail!("`directories` must list at least one directory");
+        }
+        let mut seen = HashSet::new();
+        for dir in &directories {
+            let name = dir.name.as_str();
+            if name.trim().is_empty() || name != name.trim() {
+                bail!("directory name '{name}' must not be empty or padded with spaces");
+            }
+            if name.contains(['/', '\\']) || name.starts_with('.') {
+                bail!("directory name '{name}' must be a single top-level folder (no '/', not starting with '.')");
+            }
+            if !seen.insert(name) {
+                bail!("directory '{name}' is listed twice");
+            }
+        }
+
+        let journal_directory = match file.journal_directory {
+            Some(dir) => {
+                if !directories.iter().any(|d| d.name == dir) {
+                    bail!("`journal_directory` '{dir}' must be one of `directories`");
+                }
+                Some(dir)
+            }
+            None => directories.iter().any(|d| d.name == "journal").then(|| "journal".to_string()),
+        };
+
+        let statuses = file
+            .statuses
+            .unwrap_or_else(|| DEFAULT_STATUSES.iter().map(|s| s.to_string()).collect());
+        if statuses.is_empty() {
+            bail!("`statuses` must list at least one status");
+        }
+        let mut seen = HashSet::new();
+        for status in &statuses {
+            if status.is_empty() || status.contains(char::is_whitespace) {
+                bail!("status '{status}' must be a single word");
+            }
+            if !seen.insert(status) {
+                bail!("status '{status}' is listed twice");
+            }
+        }
+
+        if file.filenames.max_length == Some(0) {
+            bail!("`filenames.max_length` must be at least 1");
+        }
+
+        let obsidian = AppSettings::load(&vault_root);
+        let filter = VaultFilter::new(&vault_root, &file.ignore, &obsidian)?;
+        let secrets = SecretGuard::new(&file.secrets)?;
+        let protected = ProtectedPaths::new(&vault_root, &file.protected_paths)?;
+
+        let tips = match &file.tips_file {
+            Some(tips_file) => {
+                let path = base.join(tips_file);
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("cannot read tips file {}", path.display()))?
+            }
+            None => DEFAULT_TIPS.to_string(),
+        };
+
+        Ok(Self {
+            name,
+            vault_root,
+            directories,
+            statuses,
+            journal_directory,
+            enabled_tools: file.enabled_tools,
+            filenames: file.filenames,
+            ignore_patterns: file.ignore,
+            obsidian,
+            filter,
+            secrets,
+            protected,
+            tips,
+            source,
+        })
+    }
+
+    /// Settings given as TOML text, for a vault without a config file.
+    #[cfg(test)]
+    pub fn from_toml(vault_root: &Path, text: &str) -> anyhow::Result<Self> {
+        let file: ConfigFile = toml::from_str(text)?;
+        Self::from_file(file, vault_root, "test".to_string(), vault_root.to_path_buf(), None)
+    }
+
+    /// Whether `rel_path` (a folder if `is_dir`) is hidden from the server:
+    /// filtered out (see `filter`) or protected as unreadable.
+    pub fn is_ignored(&self, rel_path: &str, is_dir: bool) -> bool {
+        self.filter.is_ignored(rel_path, is_dir) || self.protected.is_unreadable(rel_path, is_dir)
+    }
+
+    /// Every visible file of the vault, with its vault-relative path.
+    /// Hidden folders are not descended into.
+    pub fn files(&self) -> Vec<(PathBuf, String)> {
+        self.walk(|rel, is_dir| self.is_ignored(rel, is_dir))
+    }
+
+    /// Like `files`, but keeping files protected as unreadable. Only for
+    /// checks that must see the whole vault; never hand these to a client.
+    pub fn files_with_unreadable(&self) -> Vec<(PathBuf, String)> {
+        self.walk(|rel, is_dir| self.filter.is_ignored(rel, is_dir))
+    }
+
+    fn walk(&self, skip: impl Fn(&str, bool) -> bool) -> Vec<(PathBuf, String)> {
+        let root = &self.vault_root;
+        let rel_of = |path: &Path| {
+            path.strip_prefix(root)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .replace('\\', "/")
+        };
+        WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !skip(&rel_of(e.path()), e.file_type().is_dir()))
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| {
+                let rel = rel_of(e.path());
+                (e.into_path(), rel)
+            })
+            .collect()
+    }
+
+    pub fn directory_names(&self) -> Vec<&str> {
+        self.directories.iter().map(|d| d.name.as_str()).collect()
+    }
+
+    pub fn is_directory(&self, name: &str) -> bool {
+        self.directories.iter().any(|d| d.name == name)
+    }
+
+    /// The folder (vault-relative, "" for the root) new attachments go to,
+    /// following Obsidian's attachment setting. `note` is the note the
+    /// attachment is meant for; settings relative to the note need it.
+    pub fn attachment_folder(&self, note: Option<&str>) -> Result<String, ErrorData> {
+        let note_dir = || {
+            note.map(|n| n.rsplit_once('/').map_or("", |(dir, _)| dir).to_string())
+                .ok_or_else(|| {
+                    ErrorData::invalid_params("附件设置为存放在笔记所在目录，需要提供 note 参数", None)
+                })
+        };
+        Ok(match self.obsidian.attachment_folder.as_deref() {
+            None | Some("/") => String::new(),
+            Some("./") | Some(".") => note_dir()?,
+            Some(f) if f.starts_with("./") => {
+                let sub = f.trim_start_matches("./").trim_matches('/');
+                let dir = note_dir()?;
+                if dir.is_empty() {
+                    sub.to_string()
+                } else {
+                    format!("{dir}/{sub}")
+                }
+            }
+            Some(f) => f.trim_matches('/').to_string(),
+        })
+    }
+
+    pub fn validate_directory<'a>(&self, directory: &'a str) -> Result<&'a str, ErrorData> {
+        let dir = directory.trim().trim_matches('/');
+        if !self.is_directory(dir) {
+            return Err(ErrorData::invalid_params(
+                format!(
+                    "无效的目录 '{dir}'，必须是以下之一：{}",
+                    self.directory_names().join(", ")
+                ),
+                None,
+            ));
+        }
+        Ok(dir)
+    }
+
+    pub fn validate_filename<'a>(&self, filename: &'a str) -> Result<&'a str, ErrorData> {
+        let rules = &self.filenames;
+        let filename = filename.trim().trim_end_matches(".md");
+        if filename.is_empty() {
+            return Err(ErrorData::invalid_params("文件名不能为空", None));
+        }
+        if filename.contains(['/', '\\']) || filename.contains("..") {
+            return Err(ErrorData::invalid_params("文件名不能包含路径分隔符或 ..", None));
+        }
+        if filename.chars().any(char::is_control) {
+            return Err(ErrorData::invalid_params("文件名不能包含控制字符", None));
+        }
+        if !rules.allow_spaces && filename.contains(' ') {
+            return Err(ErrorData::invalid_params("文件名不能包含空格，请用短横线连接", None));
+        }
+        if rules.ascii_only && filename.chars().any(|c| c > '\x7f') {
+            return Err(ErrorData::invalid_params("文件名必须是英文，不能包含中文等非 ASCII 字符", None));
+        }
+        if rules.lowercase && filename != filename.to_lowercase() {
+            return Err(ErrorData::invalid_params("文件名必须全部小写", None));
+        }
+        if let Some(max) = rules.max_length {
+            if filename.chars().count() > max {
+                return Err(ErrorData::invalid_params(format!("文件名不能超过 {max} 个字符"), None));
+            }
+        }
+        Ok(filename)
+    }
+
+    pub fn validate_status(&self, status: &str) -> Result<(), ErrorData> {
+        if !self.statuses.iter().any(|s| s == status) {
+            return Err(ErrorData::invalid_params(
+                format!("无效的状态 '{status}'，必须是：{}", self.statuses.join(", ")),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// The tips document, followed by the effective settings when they come
+    /// from a config file or Obsidian's own settings (the document may
+    /// describe the defaults).
+    pub fn tips_with_settings(&self) -> String {
+        if self.source.is_none() && self.obsidian.attachment_folder.is_none() && self.obsidian.link_format.is_none() {
+            return self.tips.clone();
+        }
+        let mut out = self.tips.clone();
+        out.push_str("\n\n---\n\n## 当前服务配置\n\n以下设置以服务配置为准，与上文冲突时以此为准。\n\n| 目录 | 用途 |\n|------|------|\n");
+        for dir in &self.directories {
+            out.push_str(&format!("| `{}/` | {} |\n", dir.name, dir.description));
+        }
+        out.push_str(&format!("\n- 允许的状态：{}\n", self.statuses.join(", ")));
+        let rules = &self.filenames;
+        let mut naming = Vec::new();
+        if rules.lowercase {
+            naming.push("全部小写".to_string());
+        }
+        if rules.ascii_only {
+            naming.push("仅 ASCII 字符".to_string());
+        }
+        if !rules.allow_spaces {
+            naming.push("不含空格".to_string());
+        }
+        if let Some(max) = rules.max_length {
+            naming.push(format!("最长 {max} 个字符"));
+        }
+        if !naming.is_empty() {
+            out.push_str(&format!("- 文件名：{}\n", naming.join("、")));
+        }
+        if let Some(folder) = &self.obsidian.attachment_folder {
+            let place = match folder.as_str() {
+                "/" => "知识库根目录".to_string(),
+                "./" | "." => "与笔记相同的目录".to_string(),
+                f if f.starts_with("./") => format!("笔记所在目录下的 `{}/`", f.trim_start_matches("./")),
+                f => format!("`{f}/`"),
+            };
+            out.push_str(&format!("- 附件存放位置：{place}\n"));
+        }
+        if let Some(format) = self.obsidian.link_format {
+            out.push_str(&format!("- 新建链接格式：{}\n", format.describe()));
+        }
+        let read_only = self.protected.read_only_patterns();
+        if !read_only.is_empty() {
+            let list: Vec<String> = read_only.iter().map(|p| format!("`{p}`")).collect();
+            out.push_str(&format!("- 只读路径（可读取，不可修改）：{}\n", list.join(", ")));
+        }
+        out
+    }
+}
+
+fn read_file(path: &Path) -> anyhow::Result<ConfigFile> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("cannot read config file {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("invalid config file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempVault;
+
+    /// The default config over a fresh empty vault, kept alive alongside it.
+    fn config() -> (TempVault, Config) {
+        let root = TempVault::new(&[]);
+        let config = Config::from_toml(&root, "").unwrap();
+        (root, config)
+    }
+
+    #[test]
+    fn validate_filename_accepts_conventional_names() {
+        let (_root, config) = config();
+        assert_eq!(config.validate_filename("docker-guide").unwrap(), "docker-guide");
+        assert_eq!(config.validate_filename(" docker-guide.md ").unwrap(), "docker-guide");
+        assert_eq!(config.validate_filename("v1.2-notes").unwrap(), "v1.2-notes");
+    }
+
+    #[test]
+    fn validate_filename_rejects_path_components() {
+        let (_root, config) = config();
+        assert!(config.validate_filename("../cheatsheet/api-keys").is_err());
+        assert!(config.validate_filename("sub/note").is_err());
+        assert!(config.validate_filename("sub\\note").is_err());
+        assert!(config.validate_filename("..").is_err());
+        assert!(config.validate_filename("a..b").is_err());
+    }
+
+    #[test]
+    fn validate_filename_rejects_control_characters() {
+        let (_root, config) = config();
+        assert!(config.validate_filename("note\0").is_err());
+        assert!(config.validate_filename("note\nname").is_err());
+        assert!(config.validate_filename("note\u{7f}").is_err());
+    }
+
+    #[test]
+    fn validate_filename_applies_naming_rules() {
+        let (_root, config) = config();
+        assert!(config.validate_filename("").is_err());
+        assert!(config.validate_filename("Docker-Guide").is_err());
+        assert!(config.validate_filename("docker guide").is_err());
+        assert!(config.validate_filename("docker-指南").is_err());
+    }
+
+    #[test]
+    fn enabled_tools_default_to_none() {
+        let (root, config) = config();
+        assert_eq!(config.enabled_tools, None);
+        let listed = Config::from_toml(&root, "enabled_tools = [\"read_note\", \"query_note\"]\n").unwrap();
+        assert_eq!(listed.enabled_tools.unwrap(), ["read_note", "query_note"]);
+    }
+
+    #[test]
+    fn journal_directory_must_be_a_listed_directory() {
+        let (root, config) = config();
+        assert_eq!(config.journal_directory.as_deref(), Some("journal"));
+        let custom = "journal_directory = \"log\"\n[[directories]]\nname = \"log\"\n";
+        assert_eq!(Config::from_toml(&root, custom).unwrap().journal_directory.as_deref(), Some("log"));
+        let unlisted = "journal_directory = \"log\"\n[[directories]]\nname = \"tech\"\n";
+        assert!(Config::from_toml(&root, unlisted).is_err());
+        let without = "[[directories]]\nname = \"tech\"\n";
+        assert_eq!(Config::from_toml(&root, without).unwrap().journal_directory, None);
+    }
+}
@@ -0,0 +1,214 @@
+// ---------------------------------------------------------------------------
+// Embed expansion
+// ---------------------------------------------------------------------------
+//
+// Inlines the notes a text transcludes, the way Obsidian renders them:
+//   ![[note]]              the note body (frontmatter dropped)
+//   ![[note#部署#Nginx]]    one section, subsections included
+//   ![[note#^abc123]]      one block
+// Embedded text is expanded in turn, up to a depth limit. Each inlined part
+// is wrapped in origin markers
+//   <!-- 嵌入开始：tech/note.md#部署 -->  ...  <!-- 嵌入结束：tech/note.md#部署 -->
+// and an embed that is not expanded (missing note or section, no access, a
+// cycle, too deep) stays as written, followed by a comment saying why.
+// Attachment embeds (images, PDFs, ...) are left alone.
+
+use crate::links::{self, LinkKind};
+use crate::markdown::Document;
+use crate::VaultIndex;
+use rmcp::model::ErrorData;
+
+pub const DEFAULT_DEPTH: usize = 3;
+pub const MAX_DEPTH: usize = 10;
+
+pub struct Expander<'a> {
+    pub index: &'a VaultIndex,
+    /// Reads a note, applying the caller's access checks.
+    pub load: &'a dyn Fn(&str) -> Result<String, ErrorData>,
+    pub max_depth: usize,
+    /// Number of embeds inlined so far.
+    pub expanded: usize,
+}
+
+impl Expander<'_> {
+    /// `text`, part of the note `source_rel`, with its embeds inlined.
+    pub fn expand(&mut self, source_rel: &str, text: &str) -> String {
+        self.expand_in(source_rel, text, &mut vec![(source_rel.to_string(), String::new())])
+    }
+
+    /// `stack` holds the (note, anchor) pairs being expanded, outermost first.
+    fn expand_in(&mut self, source_rel: &str, text: &str, stack: &mut Vec<(String, String)>) -> String {
+        let doc = Document::parse(text);
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for link in doc.links.iter().filter(|l| l.kind == LinkKind::Embed && !l.external) {
+            let (target, anchor) = link.dest.split_once('#').unwrap_or((&link.dest, ""));
+            if is_attachment(target) {
+                continue;
+            }
+            let raw = &text[link.span.clone()];
+            out.push_str(&text[last..link.span.start]);
+            last = link.span.end;
+
+            let Some(rel) = links::resolve_link(self.index, source_rel, LinkKind::Embed, target) else {
+                out.push_str(&format!("{raw}<!-- 无法展开：未找到笔记 -->"));
+                continue;
+            };
+            let origin = if anchor.is_empty() { rel.clone() } else { format!("{rel}#{anchor}") };
+            let cycle = stack
+                .iter()
+                .any(|(r, a)| *r == rel && (a == anchor || anchor.is_empty()));
+            if cycle {
+                out.push_str(&format!("{raw}<!-- 无法展开：循环嵌入 {origin} -->"));
+                continue;
+            }
+            if stack.len() > self.max_depth {
+                out.push_str(&format!("{raw}<!-- 无法展开：超过嵌入深度 {} -->", self.max_depth));
+                continue;
+            }
+            let part = match (self.load)(&rel).and_then(|content| select(&content, anchor)) {
+                Ok(part) => part,
+                Err(e) => {
+                    out.push_str(&format!("{raw}<!-- 无法展开：{} -->", e.message));
+                    continue;
+                }
+            };
+
+            stack.push((rel.clone(), anchor.to_string()));
+            let inner = self.expand_in(&rel, &part, stack);
+            stack.pop();
+            self.expanded += 1;
+            // the markers go on lines of their own
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str(&format!(
+                "<!-- 嵌入开始：{origin} -->\n{}\n<!-- 嵌入结束：{origin} -->",
+                inner.trim_matches('\n')
+            ));
+            if !text[last..].is_empty() && !text[last..].starts_with('\n') {
+                out.push('\n');
+            }
+        }
+        out.push_str(&text[last..]);
+        out
+    }
+}
+
+/// The part of `content` an embed anchor names: a `^block`, a heading path
+/// written with `#` separators, or the body without frontmatter.
+fn select(content: &str, anchor: &str) -> Result<String, ErrorData> {
+    let doc = Document::parse(content);
+    let range = if anchor.is_empty() {
+        doc.frontmatter.as_ref().map_or(0, |f| f.end)..doc.lines.len()
+    } else if let Some(id) = anchor.strip_prefix('^') {
+        doc.find_block(id)
+            .ok_or_else(|| ErrorData::invalid_params(format!("未找到块 ^{id}"), None))?
+    } else {
+        let i = doc
+            .find_heading(&anchor.replace('#', "/"))
+            .ok_or_else(|| ErrorData::invalid_params(format!("未找到章节 {anchor}"), None))?;
+        doc.section_lines(i)
+    };
+    Ok(doc.lines[range].join("\n"))
+}
+
+fn is_attachment(target: &str) -> bool {
+    let name = target.rsplit('/').next().unwrap_or(target);
+    name.rsplit_once('.')
+        .is_some_and(|(_, ext)| !ext.eq_ignore_ascii_case("md") && !ext.is_empty() && !ext.contains(' '))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempVault;
+
+    /// `source`'s content with its embeds expanded in a vault holding
+    /// `files`, and the number of embeds inlined.
+    fn expand(files: &[(&str, &str)], source: &str, max_depth: usize) -> (String, usize) {
+        let root = TempVault::new(files);
+        let config = crate::config::Config::from_toml(&root, "").unwrap();
+        let index = crate::build_index(&config, &crate::auth::Scope::full());
+        let load = |rel: &str| -> Result<String, ErrorData> {
+            files
+                .iter()
+                .find(|(r, _)| *r == rel)
+                .map(|(_, content)| content.to_string())
+                .ok_or_else(|| ErrorData::invalid_params(format!("文件不存在: {rel}"), None))
+        };
+        let mut expander = Expander { index: &index, load: &load, max_depth, expanded: 0 };
+        let text = load(source).unwrap();
+        let out = expander.expand(source, &text);
+        (out, expander.expanded)
+    }
+
+    #[test]
+    fn embeds_are_inlined_with_origin_markers() {
+        let files = [
+            ("a.md", "before ![[b]] after ![[img.png]]"),
+            ("b.md", "---\ntags: [x]\n---\nbody of b"),
+        ];
+        let (out, expanded) = expand(&files, "a.md", DEFAULT_DEPTH);
+        assert_eq!(
+            out,
+            "before \n<!-- 嵌入开始：b.md -->\nbody of b\n<!-- 嵌入结束：b.md -->\n after ![[img.png]]"
+        );
+        assert_eq!(expanded, 1);
+    }
+
+    #[test]
+    fn cycles_stop_at_the_repeated_note() {
+        let files = [("a.md", "A ![[b]]"), ("b.md", "B ![[a]]")];
+        let (out, expanded) = expand(&files, "a.md", MAX_DEPTH);
+        assert_eq!(expanded, 1);
+        assert!(out.contains("B ![[a]]<!-- 无法展开：循环嵌入 a.md -->"), "{out}");
+
+        let (out, expanded) = expand(&[("a.md", "![[a]]")], "a.md", MAX_DEPTH);
+        assert_eq!(expanded, 0);
+        assert_eq!(out, "![[a]]<!-- 无法展开：循环嵌入 a.md -->");
+    }
+
+    #[test]
+    fn other_sections_of_the_same_note_are_not_a_cycle() {
+        let files = [
+            ("a.md", "![[b#S]]"),
+            ("b.md", "## S\n![[b#T]]\n## T\nt text\n## U\n![[b]]"),
+        ];
+        let (out, expanded) = expand(&files, "a.md", MAX_DEPTH);
+        assert_eq!(expanded, 2);
+        assert!(out.contains("<!-- 嵌入开始：b.md#T -->\n## T\nt text\n<!-- 嵌入结束：b.md#T -->"), "{out}");
+
+        let files = [("a.md", "![[b#U]]"), ("b.md", "## U\n![[b]]")];
+        let (out, _) = expand(&files, "a.md", MAX_DEPTH);
+        assert!(out.contains("![[b]]<!-- 无法展开：循环嵌入 b.md -->"), "{out}");
+    }
+
+    #[test]
+    fn depth_limit_leaves_deeper_embeds_as_written() {
+        let files = [
+            ("c1.md", "1 ![[c2]]"),
+            ("c2.md", "2 ![[c3]]"),
+            ("c3.md", "3 ![[c4]]"),
+            ("c4.md", "4"),
+        ];
+        let (out, expanded) = expand(&files, "c1.md", 2);
+        assert_eq!(expanded, 2);
+        assert!(out.contains("3 ![[c4]]<!-- 无法展开：超过嵌入深度 2 -->"), "{out}");
+        assert!(!out.contains("嵌入开始：c4.md"));
+
+        let (out, expanded) = expand(&files, "c1.md", 3);
+        assert_eq!(expanded, 3);
+        assert!(out.contains("<!-- 嵌入开始：c4.md -->\n4\n"), "{out}");
+    }
+
+    #[test]
+    fn missing_targets_are_explained() {
+        let files = [("a.md", "![[nowhere]] ![[b#Missing]] ![[b#^gone]]"), ("b.md", "## S\ntext")];
+        let (out, expanded) = expand(&files, "a.md", DEFAULT_DEPTH);
+        assert_eq!(expanded, 0);
+        assert!(out.contains("![[nowhere]]<!-- 无法展开：未找到笔记 -->"), "{out}");
+        assert!(out.contains("![[b#Missing]]<!-- 无法展开：未找到章节 Missing -->"), "{out}");
+        assert!(out.contains("![[b#^gone]]<!-- 无法展开：未找到块 ^gone -->"), "{out}");
+    }
+}
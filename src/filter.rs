@@ -0,0 +1,99 @@
+// ---------------------------------------------------------------------------
+// Vault filter
+// ---------------------------------------------------------------------------
+//
+// Decides which files of a vault the server sees. A path is skipped when
+//   - one of its components starts with '.' (.obsidian/, .trash/, .git/, ...)
+//     or is `node_modules`,
+//   - it is inside Obsidian's template folder,
+//   - it matches Obsidian's "Excluded files" (`userIgnoreFilters`): a path
+//     prefix, or a regex written as /.../,
+//   - it matches a gitignore-style rule from the config file's `ignore` or
+//     from <vault>/.obsidianmcpignore (negation with `!` included).
+// The index, file tree, resources and search all walk the vault through
+// `Config::files`, so they agree on what exists.
+
+use crate::obsidian::AppSettings;
+use anyhow::{bail, Context};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use regex::Regex;
+use std::path::Path;
+
+/// Gitignore-style rules in the vault root.
+pub const IGNORE_FILE: &str = ".obsidianmcpignore";
+
+#[derive(Debug)]
+pub struct VaultFilter {
+    gitignore: Gitignore,
+    templates: Option<String>,
+    prefixes: Vec<String>,
+    regexes: Vec<Regex>,
+}
+
+impl VaultFilter {
+    /// `patterns` are the config file's `ignore` lines.
+    pub fn new(vault_root: &Path, patterns: &[String], settings: &AppSettings) -> anyhow::Result<Self> {
+        let mut builder = GitignoreBuilder::new(vault_root);
+        for pattern in patterns {
+            if pattern.trim().is_empty() {
+                bail!("empty pattern in `ignore`");
+            }
+            builder
+                .add_line(None, pattern)
+                .with_context(|| format!("invalid ignore pattern '{pattern}'"))?;
+        }
+        let ignore_file = vault_root.join(IGNORE_FILE);
+        if ignore_file.is_file() {
+            if let Some(e) = builder.add(&ignore_file) {
+                bail!("invalid {}: {e}", ignore_file.display());
+            }
+        }
+        let gitignore = builder.build().context("invalid ignore patterns")?;
+
+        let mut prefixes = Vec::new();
+        let mut regexes = Vec::new();
+        for filter in &settings.ignore_filters {
+            match filter.strip_prefix('/').and_then(|f| f.strip_suffix('/')) {
+                Some(pattern) if !pattern.is_empty() => match Regex::new(pattern) {
+                    Ok(re) => regexes.push(re),
+                    Err(e) => tracing::warn!("ignoring invalid excluded-files regex {filter}: {e}"),
+                },
+                _ => prefixes.push(filter.trim_start_matches('/').to_string()),
+            }
+        }
+
+        Ok(Self {
+            gitignore,
+            templates: settings.templates_folder.clone(),
+            prefixes,
+            regexes,
+        })
+    }
+
+    /// Whether the vault-relative `rel_path`, or a folder containing it, is
+    /// filtered out.
+    pub fn is_ignored(&self, rel_path: &str, is_dir: bool) -> bool {
+        let rel = rel_path.trim_matches('/');
+        if rel.is_empty() {
+            return false;
+        }
+        if rel.split('/').any(|c| c.starts_with('.') || c == "node_modules") {
+            return true;
+        }
+        if let Some(templates) = &self.templates {
+            if rel == templates || rel.strip_prefix(templates.as_str()).is_some_and(|r| r.starts_with('/')) {
+                return true;
+            }
+        }
+        // Obsidian compares the prefix with the file path as a string
+        let as_dir = format!("{rel}/");
+        let path = if is_dir { as_dir.as_str() } else { rel };
+        if self.prefixes.iter().any(|p| path.starts_with(p.as_str())) {
+            return true;
+        }
+        if self.regexes.iter().any(|re| re.is_match(rel)) {
+            return true;
+        }
+        self.gitignore.matched_path_or_any_parents(rel, is_dir).is_ignore()
+    }
+}
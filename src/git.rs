@@ -0,0 +1,319 @@
+// ---------------------------------------------------------------------------
+// Git-backed vault
+// ---------------------------------------------------------------------------
+//
+// The vault may live anywhere inside a git work tree (it does not have to be
+// the repository root). All paths taken and returned here are vault-relative;
+// they are mapped to repository paths internally.
+//
+// A `git2::Repository` is not `Sync`, so every call opens the repository
+// afresh instead of keeping a handle on the server.
+
+use git2::{BlameOptions, Repository, Signature};
+use std::path::{Path, PathBuf};
+
+pub type GitResult<T> = Result<T, git2::Error>;
+
+#[derive(Debug, Clone)]
+pub struct CommitInfo {
+    pub id: String,
+    pub time: chrono::DateTime<chrono::Local>,
+    pub author: String,
+    pub summary: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlameLine {
+    pub id: String,
+    pub time: chrono::DateTime<chrono::Local>,
+    pub author: String,
+    pub line: String,
+}
+
+/// Read `OBSIDIAN_GIT_AUTOCOMMIT` (`1` / `true` enables a commit after every
+/// mutating tool call).
+pub fn autocommit_from_env() -> bool {
+    std::env::var("OBSIDIAN_GIT_AUTOCOMMIT")
+        .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false)
+}
+
+struct Vault {
+    repo: Repository,
+    /// Vault root relative to the repository work tree.
+    prefix: PathBuf,
+}
+
+impl Vault {
+    fn open(root: &Path) -> GitResult<Self> {
+        let repo = Repository::discover(root)?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| git2::Error::from_str("bare repositories are not supported"))?
+            .canonicalize()
+            .map_err(|e| git2::Error::from_str(&e.to_string()))?;
+        let root = root
+            .canonicalize()
+            .map_err(|e| git2::Error::from_str(&e.to_string()))?;
+        let prefix = root.strip_prefix(&workdir).unwrap_or(Path::new("")).to_path_buf();
+        Ok(Self { repo, prefix })
+    }
+
+    fn repo_path(&self, rel_path: &str) -> PathBuf {
+        self.prefix.join(rel_path)
+    }
+}
+
+fn to_local(time: git2::Time) -> chrono::DateTime<chrono::Local> {
+    chrono::DateTime::from_timestamp(time.seconds(), 0)
+        .unwrap_or_default()
+        .with_timezone(&chrono::Local)
+}
+
+fn short_id(oid: git2::Oid) -> String {
+    oid.to_string()[..7].to_string()
+}
+
+/// Commit `paths` (additions, modifications and deletions) on top of HEAD.
+/// Anything else the user has staged stays staged and out of the commit;
+/// the on-disk index is only updated for `paths`. Returns `None` when
+/// nothing changed.
+pub fn commit_paths(root: &Path, paths: &[&str], message: &str) -> GitResult<Option<String>> {
+    let vault = Vault::open(root)?;
+    let repo = &vault.repo;
+    let workdir = repo.workdir().unwrap_or(Path::new("")).to_path_buf();
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+
+    // the commit's tree: HEAD's tree with just `paths` replaced
+    let mut staged = git2::Index::new()?;
+    if let Some(parent) = &parent {
+        staged.read_tree(&parent.tree()?)?;
+    }
+    let mut index = repo.index()?;
+    for rel in paths {
+        let path = vault.repo_path(rel);
+        let full = workdir.join(&path);
+        if full.is_file() {
+            let mode = staged.get_path(&path, 0).map_or(0o100644, |e| e.mode);
+            let content = std::fs::read(&full).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+            staged.add(&index_entry(&path, mode, repo.blob(&content)?, content.len()))?;
+            index.add_path(&path)?;
+        } else {
+            if staged.get_path(&path, 0).is_some() {
+                staged.remove_path(&path)?;
+            }
+            if index.get_path(&path, 0).is_some() {
+                index.remove_path(&path)?;
+            }
+        }
+    }
+
+    let tree = repo.find_tree(staged.write_tree_to(repo)?)?;
+    if parent.as_ref().is_some_and(|p| p.tree_id() == tree.id()) {
+        index.write()?;
+        return Ok(None);
+    }
+
+    let sig = repo
+        .signature()
+        .or_else(|_| Signature::now("obsidian-mcp", "obsidian-mcp@localhost"))?;
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    let oid = repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)?;
+    index.write()?;
+    Ok(Some(short_id(oid)))
+}
+
+/// An index entry for `path` holding the blob `id` of `size` bytes.
+fn index_entry(path: &Path, mode: u32, id: git2::Oid, size: usize) -> git2::IndexEntry {
+    let path = path.to_string_lossy().replace('\\', "/").into_bytes();
+    git2::IndexEntry {
+        ctime: git2::IndexTime::new(0, 0),
+        mtime: git2::IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode,
+        uid: 0,
+        gid: 0,
+        file_size: size as u32,
+        id,
+        flags: path.len().min(0xfff) as u16,
+        flags_extended: 0,
+        path,
+    }
+}
+
+/// Commits reachable from HEAD, newest first, that changed `rel_path` or,
+/// without one, anything in the vault. When the vault is a subfolder of the
+/// repository, commits that only touch files outside it are left out.
+pub fn log(root: &Path, rel_path: Option<&str>, limit: usize) -> GitResult<Vec<CommitInfo>> {
+    let vault = Vault::open(root)?;
+    let repo = &vault.repo;
+    // a tree entry's id changes exactly when something below it does
+    let path = match rel_path {
+        Some(p) => Some(vault.repo_path(p)),
+        None => Some(vault.prefix.clone()).filter(|p| !p.as_os_str().is_empty()),
+    };
+
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+
+    let blob_at = |commit: &git2::Commit, path: &Path| {
+        commit.tree().ok()?.get_path(path).ok().map(|e| e.id())
+    };
+
+    let mut out = Vec::new();
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        if let Some(path) = &path {
+            let here = blob_at(&commit, path);
+            let before = commit.parent(0).ok().and_then(|p| blob_at(&p, path));
+            if here == before {
+                continue;
+            }
+        }
+        out.push(CommitInfo {
+            id: short_id(commit.id()),
+            time: to_local(commit.time()),
+            author: commit.author().name().unwrap_or("").to_string(),
+            summary: commit.summary().unwrap_or("").to_string(),
+        });
+        if out.len() >= limit {
+            break;
+        }
+    }
+    Ok(out)
+}
+
+/// Content of `rel_path` at revision `rev`, or `None` if it did not exist there.
+pub fn read_at(root: &Path, rel_path: &str, rev: &str) -> GitResult<Option<String>> {
+    let vault = Vault::open(root)?;
+    let commit = vault.repo.revparse_single(rev)?.peel_to_commit()?;
+    let entry = match commit.tree()?.get_path(&vault.repo_path(rel_path)) {
+        Ok(entry) => entry,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let blob = entry.to_object(&vault.repo)?.peel_to_blob()?;
+    Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+}
+
+/// Last commit touching each line of `rel_path` as of revision `rev`.
+pub fn blame(root: &Path, rel_path: &str, rev: &str) -> GitResult<Vec<BlameLine>> {
+    let vault = Vault::open(root)?;
+    let repo = &vault.repo;
+    let commit = repo.revparse_single(rev)?.peel_to_commit()?;
+    let content = read_at(root, rel_path, rev)?
+        .ok_or_else(|| git2::Error::from_str(&format!("{rel_path} does not exist at {rev}")))?;
+
+    let mut opts = BlameOptions::new();
+    opts.newest_commit(commit.id());
+    let blame = repo.blame_file(&vault.repo_path(rel_path), Some(&mut opts))?;
+
+    Ok(content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let (id, time, author) = match blame.get_line(i + 1) {
+                Some(hunk) => {
+                    let sig = hunk.final_signature();
+                    (
+                        short_id(hunk.final_commit_id()),
+                        to_local(sig.when()),
+                        sig.name().unwrap_or("").to_string(),
+                    )
+                }
+                None => (String::from("-------"), to_local(git2::Time::new(0, 0)), String::new()),
+            };
+            BlameLine { id, time, author, line: line.to_string() }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempVault;
+
+    /// A repository holding `vault/a.md` and `vault/b.md` in one commit.
+    fn fixture() -> (TempVault, Repository, PathBuf) {
+        let base = TempVault::new(&[("vault/a.md", "a1"), ("vault/b.md", "b1")]);
+        let vault = base.join("vault");
+        let repo = Repository::init(&*base).unwrap();
+        commit_paths(&vault, &["a.md", "b.md"], "init").unwrap().unwrap();
+        (base, repo, vault)
+    }
+
+    fn head_files(repo: &Repository) -> Vec<String> {
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        let mut files = Vec::new();
+        tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                files.push(format!("{dir}{}", entry.name().unwrap()));
+            }
+            git2::TreeWalkResult::Ok
+        })
+        .unwrap();
+        files
+    }
+
+    #[test]
+    fn commit_paths_leaves_other_staged_changes_alone() {
+        let (_base, repo, vault) = fixture();
+        std::fs::write(vault.join("staged.md"), "user work").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("vault/staged.md")).unwrap();
+        index.write().unwrap();
+
+        std::fs::write(vault.join("a.md"), "a2").unwrap();
+        commit_paths(&vault, &["a.md"], "write_note: a.md").unwrap().unwrap();
+
+        assert_eq!(head_files(&repo), ["vault/a.md", "vault/b.md"]);
+        assert_eq!(read_at(&vault, "a.md", "HEAD").unwrap().as_deref(), Some("a2"));
+        let index = repo.index().unwrap();
+        assert!(index.get_path(Path::new("vault/staged.md"), 0).is_some());
+    }
+
+    #[test]
+    fn commit_paths_records_deletions_and_moves() {
+        let (_base, repo, vault) = fixture();
+        std::fs::rename(vault.join("b.md"), vault.join("c.md")).unwrap();
+        commit_paths(&vault, &["b.md", "c.md"], "move_note: b.md -> c.md").unwrap().unwrap();
+        assert_eq!(head_files(&repo), ["vault/a.md", "vault/c.md"]);
+        let index = repo.index().unwrap();
+        assert!(index.get_path(Path::new("vault/b.md"), 0).is_none());
+        assert!(index.get_path(Path::new("vault/c.md"), 0).is_some());
+
+        std::fs::remove_file(vault.join("c.md")).unwrap();
+        commit_paths(&vault, &["c.md"], "delete_note: c.md").unwrap().unwrap();
+        assert_eq!(head_files(&repo), ["vault/a.md"]);
+    }
+
+    #[test]
+    fn log_covers_only_the_vault_folder() {
+        let (base, repo, vault) = fixture();
+        std::fs::write(base.join("outside.md"), "not in the vault").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("outside.md")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let sig = git2::Signature::now("user", "user@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "outside", &tree, &[&head]).unwrap();
+        std::fs::write(vault.join("b.md"), "b2").unwrap();
+        commit_paths(&vault, &["b.md"], "write_note: b.md").unwrap().unwrap();
+
+        let summaries = |rel_path| -> Vec<String> {
+            log(&vault, rel_path, 10).unwrap().into_iter().map(|c| c.summary).collect()
+        };
+        assert_eq!(summaries(None), ["write_note: b.md", "init"]);
+        assert_eq!(summaries(Some("a.md")), ["init"]);
+    }
+
+    #[test]
+    fn commit_paths_skips_unchanged_files() {
+        let (_base, repo, vault) = fixture();
+        let head = repo.head().unwrap().target();
+        assert!(commit_paths(&vault, &["a.md", "missing.md"], "noop").unwrap().is_none());
+        assert_eq!(repo.head().unwrap().target(), head);
+    }
+}
@@ -0,0 +1,195 @@
+// ---------------------------------------------------------------------------
+// Per-note version history
+// ---------------------------------------------------------------------------
+//
+// Before the server modifies or removes a note, the previous content is
+// copied to `.obsidian-mcp/history/<rel_path>/<YYYYMMDD-HHMMSS-mmm>.md`.
+// Old snapshots are pruned according to a `RetentionPolicy`.
+
+use crate::STATE_DIR;
+use std::path::{Path, PathBuf};
+
+const STAMP_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    /// Keep at most this many snapshots per note.
+    pub max_versions: usize,
+    /// Drop snapshots older than this many days (`None` keeps them forever).
+    pub max_age_days: Option<i64>,
+}
+
+impl RetentionPolicy {
+    /// Read `OBSIDIAN_HISTORY_KEEP` (default 50) and `OBSIDIAN_HISTORY_DAYS`
+    /// (default 90, `0` = never expire).
+    pub fn from_env() -> Self {
+        let max_versions = std::env::var("OBSIDIAN_HISTORY_KEEP")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(50);
+        let days: i64 = std::env::var("OBSIDIAN_HISTORY_DAYS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(90);
+        Self {
+            max_versions: max_versions.max(1),
+            max_age_days: (days > 0).then_some(days),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub id: String,
+    pub time: chrono::NaiveDateTime,
+    pub size: u64,
+}
+
+fn note_dir(root: &Path, rel_path: &str) -> PathBuf {
+    root.join(STATE_DIR).join("history").join(rel_path)
+}
+
+/// Store `content` as the newest snapshot of `rel_path`, then prune.
+pub fn snapshot(root: &Path, rel_path: &str, content: &str, policy: &RetentionPolicy) -> std::io::Result<String> {
+    let dir = note_dir(root, rel_path);
+    std::fs::create_dir_all(&dir)?;
+
+    let stamp = chrono::Local::now().format(STAMP_FORMAT).to_string();
+    let mut id = stamp.clone();
+    let mut n = 1;
+    while dir.join(format!("{id}.md")).exists() {
+        id = format!("{stamp}-{n}");
+        n += 1;
+    }
+    std::fs::write(dir.join(format!("{id}.md")), content)?;
+
+    prune(root, rel_path, policy)?;
+    Ok(id)
+}
+
+/// Snapshots of `rel_path`, newest first.
+pub fn list_versions(root: &Path, rel_path: &str) -> Vec<Version> {
+    let mut versions: Vec<Version> = std::fs::read_dir(note_dir(root, rel_path))
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| {
+            let id = e.path().file_stem()?.to_string_lossy().to_string();
+            let time = chrono::NaiveDateTime::parse_from_str(id.get(..19)?, STAMP_FORMAT).ok()?;
+            let size = e.metadata().map(|m| m.len()).unwrap_or(0);
+            Some(Version { id, time, size })
+        })
+        .collect();
+    versions.sort_by_key(|v| std::cmp::Reverse((v.time, collision_index(&v.id))));
+    versions
+}
+
+/// `n` for the `n`-th extra snapshot taken within one millisecond
+/// (`<stamp>-<n>`), 0 for the first.
+fn collision_index(id: &str) -> usize {
+    id.get(19..)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn read_version(root: &Path, rel_path: &str, id: &str) -> std::io::Result<String> {
+    if id.contains(['/', '\\']) || id.contains("..") {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid version id"));
+    }
+    std::fs::read_to_string(note_dir(root, rel_path).join(format!("{id}.md")))
+}
+
+fn prune(root: &Path, rel_path: &str, policy: &RetentionPolicy) -> std::io::Result<()> {
+    let dir = note_dir(root, rel_path);
+    let cutoff = policy
+        .max_age_days
+        .map(|days| chrono::Local::now().naive_local() - chrono::Duration::days(days));
+
+    // the newest snapshot always survives, whatever its age
+    for (i, v) in list_versions(root, rel_path).iter().enumerate() {
+        let expired = i > 0 && cutoff.is_some_and(|c| v.time < c);
+        if i >= policy.max_versions || expired {
+            std::fs::remove_file(dir.join(format!("{}.md", v.id)))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempVault;
+
+    fn keep(max_versions: usize) -> RetentionPolicy {
+        RetentionPolicy { max_versions, max_age_days: None }
+    }
+
+    fn ids(root: &Path, rel_path: &str) -> Vec<String> {
+        list_versions(root, rel_path).into_iter().map(|v| v.id).collect()
+    }
+
+    #[test]
+    fn snapshots_read_back_newest_first() {
+        let root = TempVault::new(&[]);
+        let first = snapshot(&root, "tech/a.md", "one", &keep(10)).unwrap();
+        let second = snapshot(&root, "tech/a.md", "two", &keep(10)).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(ids(&root, "tech/a.md"), [second.clone(), first.clone()]);
+        assert_eq!(read_version(&root, "tech/a.md", &first).unwrap(), "one");
+        assert_eq!(read_version(&root, "tech/a.md", &second).unwrap(), "two");
+        assert!(list_versions(&root, "tech/b.md").is_empty());
+    }
+
+    #[test]
+    fn collisions_within_a_millisecond_sort_numerically() {
+        let root = TempVault::new(&[]);
+        let dir = note_dir(&root, "a.md");
+        std::fs::create_dir_all(&dir).unwrap();
+        let stamp = "20240501-120000-000";
+        let mut expected = vec![stamp.to_string()];
+        for n in 1..=11 {
+            expected.push(format!("{stamp}-{n}"));
+        }
+        for id in &expected {
+            std::fs::write(dir.join(format!("{id}.md")), id).unwrap();
+        }
+        expected.reverse();
+        assert_eq!(ids(&root, "a.md"), expected);
+
+        prune(&root, "a.md", &keep(3)).unwrap();
+        assert_eq!(ids(&root, "a.md"), [format!("{stamp}-11"), format!("{stamp}-10"), format!("{stamp}-9")]);
+    }
+
+    #[test]
+    fn prune_expires_old_snapshots_but_keeps_the_newest() {
+        let root = TempVault::new(&[]);
+        let dir = note_dir(&root, "a.md");
+        std::fs::create_dir_all(&dir).unwrap();
+        for id in ["20000101-000000-000", "20000102-000000-000"] {
+            std::fs::write(dir.join(format!("{id}.md")), id).unwrap();
+        }
+        let policy = RetentionPolicy { max_versions: 10, max_age_days: Some(30) };
+        prune(&root, "a.md", &policy).unwrap();
+        assert_eq!(ids(&root, "a.md"), ["20000102-000000-000"]);
+
+        let id = snapshot(&root, "a.md", "now", &policy).unwrap();
+        assert_eq!(ids(&root, "a.md"), [id]);
+    }
+
+    #[test]
+    fn read_version_rejects_ids_leaving_the_note_history() {
+        let root = TempVault::new(&[]);
+        snapshot(&root, "a.md", "one", &keep(10)).unwrap();
+        std::fs::write(root.join("secret.md"), "secret").unwrap();
+        for id in ["../../../secret", "x/../y", "a\\b", ".."] {
+            assert_eq!(
+                read_version(&root, "a.md", id).unwrap_err().kind(),
+                std::io::ErrorKind::InvalidInput,
+                "{id}"
+            );
+        }
+        assert!(read_version(&root, "a.md", "20000101-000000-000").is_err());
+    }
+}
@@ -0,0 +1,487 @@
+// ---------------------------------------------------------------------------
+// HTTP transport
+// ---------------------------------------------------------------------------
+//
+// One long-lived server, one shared `VaultIndex`, any number of clients:
+//
+//   POST   /mcp                  streamable HTTP: a JSON-RPC message in, the
+//                                JSON response out (202 for notifications).
+//                                `initialize` opens a session and returns its
+//                                id in the `Mcp-Session-Id` header.
+//   GET    /mcp                  SSE stream of server-initiated messages
+//                                (resource updates, ...) for a session
+//   DELETE /mcp                  end a session
+//   GET    /sse, POST /message   the older HTTP+SSE transport
+//
+// When a tokens file is configured every request needs a bearer token, and a
+// session only accepts requests carrying the token that opened it.
+//
+// Every session runs its own rmcp service over a pair of channels; a
+// dispatcher task routes the service's outgoing messages either to the POST
+// waiting for that response or to the session's SSE stream.
+
+use crate::auth::{Scope, TokenId, TokenStore};
+use crate::progress;
+use crate::ObsidianMcp;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::{SinkExt, Stream, StreamExt};
+use rmcp::model::{ClientJsonRpcMessage, ClientRequest, JsonRpcMessage, RequestId, ServerJsonRpcMessage};
+use rmcp::ServiceExt;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::{CancellationToken, PollSender};
+
+const SESSION_HEADER: &str = "mcp-session-id";
+/// Sessions without an open stream are dropped after this long without a request.
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// How long a POST waits for the handler's response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Read `OBSIDIAN_HTTP_BIND` (default `127.0.0.1:3000`).
+pub fn bind_from_env() -> String {
+    std::env::var("OBSIDIAN_HTTP_BIND").unwrap_or_else(|_| "127.0.0.1:3000".to_string())
+}
+
+struct Session {
+    to_server: mpsc::Sender<ClientJsonRpcMessage>,
+    /// POST requests waiting for their response, by JSON-RPC id.
+    pending: Mutex<HashMap<RequestId, oneshot::Sender<ServerJsonRpcMessage>>>,
+    /// The open SSE stream, if any.
+    stream: Mutex<Option<mpsc::Sender<ServerJsonRpcMessage>>>,
+    last_seen: Mutex<Instant>,
+    ct: CancellationToken,
+    /// Token that opened the session; `None` without authentication.
+    token: Option<TokenId>,
+    /// Progress tokens of the session's requests in flight.
+    progress: progress::Tokens,
+}
+
+impl Session {
+    /// Decode a client message, remembering its progress token.
+    fn decode(&self, value: Value) -> Option<ClientJsonRpcMessage> {
+        self.progress
+            .decode(value)
+            .inspect_err(|e| tracing::debug!("invalid message: {e}"))
+            .ok()
+    }
+
+    fn touch(&self) {
+        if let Ok(mut t) = self.last_seen.lock() {
+            *t = Instant::now();
+        }
+    }
+
+    fn open_stream(&self) -> mpsc::Receiver<ServerJsonRpcMessage> {
+        let (tx, rx) = mpsc::channel(64);
+        if let Ok(mut stream) = self.stream.lock() {
+            *stream = Some(tx);
+        }
+        rx
+    }
+
+    fn has_stream(&self) -> bool {
+        self.stream
+            .lock()
+            .map(|s| s.as_ref().is_some_and(|tx| !tx.is_closed()))
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    server: ObsidianMcp,
+    sessions: Arc<Mutex<HashMap<String, Arc<Session>>>>,
+    /// `None` = no authentication.
+    tokens: Option<Arc<TokenStore>>,
+    ct: CancellationToken,
+}
+
+impl AppState {
+    /// The request's bearer token and the scope it grants.
+    fn authorize(&self, headers: &HeaderMap) -> Result<(Option<TokenId>, Scope), Denied> {
+        let Some(tokens) = &self.tokens else {
+            return Ok((None, Scope::full()));
+        };
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| tokens.authenticate(v))
+            .map(|(token, scope)| (Some(token), scope.clone()))
+            .ok_or(Denied::Unauthorized)
+    }
+
+    /// The session `id`, if it exists and belongs to the request's token.
+    fn session(&self, id: &str, headers: &HeaderMap) -> Result<Arc<Session>, Denied> {
+        let (token, _) = self.authorize(headers)?;
+        let session = self
+            .sessions
+            .lock()
+            .ok()
+            .and_then(|s| s.get(id).cloned())
+            .ok_or(Denied::UnknownSession)?;
+        if session.token != token {
+            return Err(Denied::OtherToken);
+        }
+        Ok(session)
+    }
+
+    /// Start an rmcp service for a new session.
+    fn create_session(&self, token: Option<TokenId>, scope: Scope) -> (String, Arc<Session>) {
+        let id = format!("{:032x}", rand::random::<u128>());
+        let ct = self.ct.child_token();
+        let (to_server, from_client) = mpsc::channel::<ClientJsonRpcMessage>(64);
+        let (to_client, mut from_server) = mpsc::channel::<ServerJsonRpcMessage>(64);
+        let service = self.server.for_session(ct.clone(), scope.clone());
+
+        let session = Arc::new(Session {
+            to_server,
+            pending: Mutex::new(HashMap::new()),
+            stream: Mutex::new(None),
+            last_seen: Mutex::new(Instant::now()),
+            ct: ct.clone(),
+            token,
+            progress: service.progress_tokens(),
+        });
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.insert(id.clone(), session.clone());
+        }
+
+        // route outgoing messages
+        let dispatch = session.clone();
+        tokio::spawn(async move {
+            while let Some(message) = from_server.recv().await {
+                dispatch.progress.finish(&message);
+                let id = match &message {
+                    JsonRpcMessage::Response(r) => Some(r.id.clone()),
+                    JsonRpcMessage::Error(e) => Some(e.id.clone()),
+                    _ => None,
+                };
+                let waiter = id.and_then(|id| dispatch.pending.lock().ok()?.remove(&id));
+                let message = match waiter {
+                    Some(waiter) => match waiter.send(message) {
+                        Ok(()) => continue,
+                        Err(message) => message,
+                    },
+                    None => message,
+                };
+                let stream = dispatch.stream.lock().ok().and_then(|s| s.clone());
+                match stream {
+                    Some(tx) => {
+                        let _ = tx.send(message).await;
+                    }
+                    None => tracing::debug!("no open stream, dropping server message"),
+                }
+            }
+        });
+
+        let sessions = self.sessions.clone();
+        let session_id = id.clone();
+        tokio::spawn(async move {
+            let sink = PollSender::new(to_client).sink_map_err(std::io::Error::other);
+            let transport = (sink, ReceiverStream::new(from_client));
+            match service.serve_with_ct(transport, ct.clone()).await {
+                Ok(running) => {
+                    let _ = running.waiting().await;
+                }
+                Err(e) => tracing::warn!(session = %session_id, "session failed to start: {e}"),
+            }
+            ct.cancel();
+            if let Ok(mut sessions) = sessions.lock() {
+                sessions.remove(&session_id);
+            }
+            tracing::info!(session = %session_id, "session closed");
+        });
+
+        tracing::info!(session = %id, token = %scope.name, "session opened");
+        (id, session)
+    }
+
+    fn remove_session(&self, id: &str) -> bool {
+        let removed = self.sessions.lock().ok().and_then(|mut s| s.remove(id));
+        if let Some(session) = &removed {
+            session.ct.cancel();
+        }
+        removed.is_some()
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, message.to_string()).into_response()
+}
+
+enum Denied {
+    Unauthorized,
+    UnknownSession,
+    OtherToken,
+}
+
+impl IntoResponse for Denied {
+    fn into_response(self) -> Response {
+        match self {
+            Denied::Unauthorized => {
+                let mut response = error(StatusCode::UNAUTHORIZED, "missing or invalid bearer token");
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                response
+            }
+            Denied::UnknownSession => error(StatusCode::NOT_FOUND, "unknown session"),
+            Denied::OtherToken => error(StatusCode::FORBIDDEN, "session belongs to another token"),
+        }
+    }
+}
+
+/// Forward `message` to the session; for requests, wait for the response.
+async fn forward(session: &Session, message: ClientJsonRpcMessage) -> Response {
+    session.touch();
+    let waiter = match &message {
+        JsonRpcMessage::Request(r) => {
+            let (tx, rx) = oneshot::channel();
+            if let Ok(mut pending) = session.pending.lock() {
+                pending.insert(r.id.clone(), tx);
+            }
+            Some(rx)
+        }
+        _ => None,
+    };
+    if session.to_server.send(message).await.is_err() {
+        return error(StatusCode::NOT_FOUND, "session closed");
+    }
+    match waiter {
+        Some(rx) => match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(Ok(response)) => Json(response).into_response(),
+            Ok(Err(_)) => error(StatusCode::NOT_FOUND, "session closed"),
+            Err(_) => error(StatusCode::GATEWAY_TIMEOUT, "request timed out"),
+        },
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+fn sse_stream(
+    first: Option<Event>,
+    rx: mpsc::Receiver<ServerJsonRpcMessage>,
+    ct: CancellationToken,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let messages = ReceiverStream::new(rx).map(|message| {
+        let data = serde_json::to_string(&message).unwrap_or_default();
+        Ok(Event::default().event("message").data(data))
+    });
+    let stream = futures::stream::iter(first.map(Ok))
+        .chain(messages)
+        .take_until(ct.cancelled_owned());
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+fn session_id(headers: &HeaderMap) -> Option<&str> {
+    headers.get(SESSION_HEADER).and_then(|v| v.to_str().ok())
+}
+
+// ---------------------------------------------------------------------------
+// Streamable HTTP
+// ---------------------------------------------------------------------------
+
+async fn mcp_post(
+    State(app): State<AppState>,
+    headers: HeaderMap,
+    Json(message): Json<Value>,
+) -> Response {
+    if let Some(id) = session_id(&headers) {
+        let session = match app.session(id, &headers) {
+            Ok(session) => session,
+            Err(denied) => return denied.into_response(),
+        };
+        return match session.decode(message) {
+            Some(message) => forward(&session, message).await,
+            None => error(StatusCode::BAD_REQUEST, "invalid JSON-RPC message"),
+        };
+    }
+    let Ok(message) = serde_json::from_value::<ClientJsonRpcMessage>(message) else {
+        return error(StatusCode::BAD_REQUEST, "invalid JSON-RPC message");
+    };
+    let (token, scope) = match app.authorize(&headers) {
+        Ok(grant) => grant,
+        Err(denied) => return denied.into_response(),
+    };
+
+    let is_initialize = matches!(
+        &message,
+        JsonRpcMessage::Request(r) if matches!(r.request, ClientRequest::InitializeRequest(_))
+    );
+    if !is_initialize {
+        return error(StatusCode::BAD_REQUEST, "missing Mcp-Session-Id header");
+    }
+    let (id, session) = app.create_session(token, scope);
+    let mut response = forward(&session, message).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(SESSION_HEADER, value);
+    }
+    response
+}
+
+async fn mcp_get(State(app): State<AppState>, headers: HeaderMap) -> Response {
+    let session = match app.session(session_id(&headers).unwrap_or_default(), &headers) {
+        Ok(session) => session,
+        Err(denied) => return denied.into_response(),
+    };
+    session.touch();
+    sse_stream(None, session.open_stream(), session.ct.clone()).into_response()
+}
+
+async fn mcp_delete(State(app): State<AppState>, headers: HeaderMap) -> Response {
+    let id = session_id(&headers).unwrap_or_default();
+    if let Err(denied) = app.session(id, &headers) {
+        return denied.into_response();
+    }
+    app.remove_session(id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+// ---------------------------------------------------------------------------
+// HTTP+SSE (older clients)
+// ---------------------------------------------------------------------------
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageQuery {
+    session_id: String,
+}
+
+async fn sse_get(State(app): State<AppState>, headers: HeaderMap) -> Response {
+    let (token, scope) = match app.authorize(&headers) {
+        Ok(grant) => grant,
+        Err(denied) => return denied.into_response(),
+    };
+    let (id, session) = app.create_session(token, scope);
+    let endpoint = Event::default().event("endpoint").data(format!("/message?sessionId={id}"));
+    let rx = session.open_stream();
+    let stream_ct = session.ct.clone();
+
+    // the stream is the session: end it when the client goes away
+    let ct = session.ct.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = ct.cancelled() => break,
+                _ = tokio::time::sleep(Duration::from_secs(5)) => {
+                    if !session.has_stream() {
+                        app.remove_session(&id);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    sse_stream(Some(endpoint), rx, stream_ct).into_response()
+}
+
+async fn sse_post(
+    State(app): State<AppState>,
+    Query(MessageQuery { session_id }): Query<MessageQuery>,
+    headers: HeaderMap,
+    Json(message): Json<Value>,
+) -> Response {
+    let session = match app.session(&session_id, &headers) {
+        Ok(session) => session,
+        Err(denied) => return denied.into_response(),
+    };
+    session.touch();
+    let Some(message) = session.decode(message) else {
+        return error(StatusCode::BAD_REQUEST, "invalid JSON-RPC message");
+    };
+    // responses travel over the SSE stream
+    if session.to_server.send(message).await.is_err() {
+        return error(StatusCode::GONE, "session closed");
+    }
+    StatusCode::ACCEPTED.into_response()
+}
+
+// ---------------------------------------------------------------------------
+
+async fn shutdown_signal() {
+    let ctrl_c = tokio::signal::ctrl_c();
+    #[cfg(unix)]
+    {
+        let mut term = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(term) => term,
+            Err(_) => {
+                let _ = ctrl_c.await;
+                return;
+            }
+        };
+        tokio::select! {
+            _ = ctrl_c => {}
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = ctrl_c.await;
+}
+
+/// Serve MCP over HTTP on `bind` until Ctrl-C / SIGTERM.
+pub async fn serve(server: ObsidianMcp, bind: SocketAddr, tokens: Option<TokenStore>) -> anyhow::Result<()> {
+    let tokens = tokens.map(Arc::new);
+    if tokens.is_none() && !bind.ip().is_loopback() {
+        tracing::warn!("serving on {bind} without authentication; anyone who can reach it can read and write the vault");
+    }
+    let ct = CancellationToken::new();
+    let app = AppState {
+        server,
+        sessions: Arc::new(Mutex::new(HashMap::new())),
+        tokens,
+        ct: ct.clone(),
+    };
+
+    // drop sessions whose client vanished without DELETE
+    let reaper = app.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            tokio::select! {
+                _ = reaper.ct.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+            let idle: Vec<String> = match reaper.sessions.lock() {
+                Ok(sessions) => sessions
+                    .iter()
+                    .filter(|(_, s)| {
+                        !s.has_stream()
+                            && s.last_seen.lock().is_ok_and(|t| t.elapsed() > SESSION_IDLE_TIMEOUT)
+                    })
+                    .map(|(id, _)| id.clone())
+                    .collect(),
+                Err(_) => continue,
+            };
+            for id in idle {
+                reaper.remove_session(&id);
+            }
+        }
+    });
+
+    let router = Router::new()
+        .route("/mcp", post(mcp_post).get(mcp_get).delete(mcp_delete))
+        .route("/sse", get(sse_get))
+        .route("/message", post(sse_post))
+        .with_state(app);
+
+    let listener = tokio::net::TcpListener::bind(bind).await?;
+    tracing::info!("Obsidian MCP Server listening on http://{bind}/mcp");
+    axum::serve(listener, router)
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            tracing::info!("shutting down");
+            ct.cancel();
+        })
+        .await?;
+    Ok(())
+}
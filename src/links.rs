@@ -0,0 +1,551 @@
+// ---------------------------------------------------------------------------
+// Link scanning & rewriting
+// ---------------------------------------------------------------------------
+//
+// Obsidian notes reference each other in three ways:
+//   [[target#heading|alias]]   wikilink
+//   ![[target#heading|alias]]  embed (transclusion)
+//   [text](path/to/note.md#h)  markdown link (relative to the linking file)
+//
+// Only the *target* part of a link is ever rewritten, so headings, block ids
+// and display aliases survive a rename untouched.
+//
+// Links are found by the markdown parser (`markdown::Document`), so every
+// tool agrees on what counts as a link: nothing inside code, escaped
+// brackets or external URLs.
+
+use crate::config::Config;
+use crate::markdown::Document;
+use crate::obsidian::LinkFormat;
+use crate::{progress, VaultIndex};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Wiki,
+    Embed,
+    Markdown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub kind: LinkKind,
+    /// Byte range of the target (path only, no anchor / alias) inside the content.
+    pub target: Range<usize>,
+}
+
+/// All links in `content` that name a vault path, skipping code blocks, code
+/// spans and external URLs. This is the link grammar of `markdown::Document`;
+/// markdown images count as markdown links, only `![[...]]` as embeds.
+pub fn scan_links(content: &str) -> Vec<Link> {
+    Document::parse(content)
+        .links
+        .into_iter()
+        .filter(|l| !l.external)
+        .filter_map(|l| {
+            let target = l.target?;
+            let kind = if l.wiki { l.kind } else { LinkKind::Markdown };
+            // `[text](#heading)` stays within the note
+            (kind != LinkKind::Markdown || !target.is_empty()).then_some(Link { kind, target })
+        })
+        .collect()
+}
+
+fn parent_dir(rel: &str) -> &str {
+    rel.rfind('/').map(|p| &rel[..p]).unwrap_or("")
+}
+
+/// Collapse `.` / `..` segments; `None` if the path climbs above the vault root.
+pub fn normalize_rel(path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for seg in path.split('/') {
+        match seg {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            s => parts.push(s),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Path of `to` relative to the directory containing `from` (both vault-relative).
+pub fn relative_path(from: &str, to: &str) -> String {
+    let from_dir: Vec<&str> = parent_dir(from).split('/').filter(|s| !s.is_empty()).collect();
+    let to_parts: Vec<&str> = to.split('/').collect();
+
+    let common = from_dir
+        .iter()
+        .zip(&to_parts)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut out: Vec<&str> = vec![".."; from_dir.len() - common];
+    out.extend(&to_parts[common..]);
+    out.join("/")
+}
+
+/// Resolve a link target written in `source_rel` to a vault-relative path.
+///
+/// Wikilinks resolve against the index (Obsidian's shortest-path rule: a bare
+/// name matches by filename, preferring the note closest to the source).
+/// A path-qualified wikilink prefers the note relative to the source's
+/// folder, then the path from the vault root, then the shortest path ending
+/// in it. Markdown links resolve as percent-decoded paths relative to the
+/// source file; a target without extension names the note with `.md` added
+/// if there is one. They are not otherwise checked for existence.
+pub fn resolve_link(index: &VaultIndex, source_rel: &str, kind: LinkKind, target: &str) -> Option<String> {
+    match kind {
+        LinkKind::Markdown => {
+            let decoded = percent_decode(target);
+            let joined = match decoded.strip_prefix('/') {
+                Some(abs) => abs.to_string(),
+                None => format!("{}/{decoded}", parent_dir(source_rel)),
+            };
+            let rel = normalize_rel(&joined)?;
+            let note = format!("{rel}.md");
+            if !rel.ends_with(".md") && index.entries.iter().any(|e| e.rel_path == note) {
+                return Some(note);
+            }
+            Some(rel)
+        }
+        LinkKind::Wiki | LinkKind::Embed => {
+            let t = target.trim().trim_start_matches('/');
+            if t.is_empty() {
+                // `[[#heading]]` points at the source note itself
+                return Some(source_rel.to_string());
+            }
+            let t = t.strip_suffix(".md").unwrap_or(t).to_lowercase();
+
+            // written with Obsidian's "relative path" link format
+            if t.starts_with("./") || t.starts_with("../") {
+                let joined = normalize_rel(&format!("{}/{t}.md", parent_dir(source_rel)))?;
+                return index
+                    .entries
+                    .iter()
+                    .find(|e| e.rel_path.to_lowercase() == joined)
+                    .map(|e| e.rel_path.clone());
+            }
+
+            if t.contains('/') {
+                let stem_of = |p: &str| p.strip_suffix(".md").unwrap_or(p).to_lowercase();
+                let source_dir = parent_dir(source_rel).to_lowercase();
+                let near = if source_dir.is_empty() { t.clone() } else { format!("{source_dir}/{t}") };
+                let suffix = format!("/{t}");
+                let paths = || index.entries.iter().map(|e| &e.rel_path);
+                return paths()
+                    .find(|p| stem_of(p) == near)
+                    .or_else(|| paths().find(|p| stem_of(p) == t))
+                    .or_else(|| paths().filter(|p| stem_of(p).ends_with(&suffix)).min_by_key(|p| p.len()))
+                    .cloned();
+            }
+
+            let source_dir = parent_dir(source_rel);
+            index
+                .entries
+                .iter()
+                .filter(|e| e.title.to_lowercase() == t)
+                .min_by_key(|e| (parent_dir(&e.rel_path) != source_dir, e.rel_path.len()))
+                .map(|e| e.rel_path.clone())
+        }
+    }
+}
+
+/// Resolve a link target written in `source_rel` to an attachment, as an
+/// index into `index.attachments`. Wikilinks match a bare filename anywhere
+/// (preferring the source's folder, then the shortest path) or a path
+/// suffix; markdown links are paths relative to the source file.
+pub fn resolve_attachment(index: &VaultIndex, source_rel: &str, kind: LinkKind, target: &str) -> Option<usize> {
+    let attachments = &index.attachments;
+    match kind {
+        LinkKind::Markdown => {
+            let rel = resolve_link(index, source_rel, kind, target)?;
+            attachments.iter().position(|a| a.rel_path == rel)
+        }
+        LinkKind::Wiki | LinkKind::Embed => {
+            let t = target.trim().trim_start_matches('/');
+            if t.is_empty() {
+                return None;
+            }
+            if t.starts_with("./") || t.starts_with("../") {
+                let joined = normalize_rel(&format!("{}/{t}", parent_dir(source_rel)))?;
+                return attachments.iter().position(|a| a.rel_path.eq_ignore_ascii_case(&joined));
+            }
+            if t.contains('/') {
+                let suffix = format!("/{}", t.to_lowercase());
+                return attachments.iter().position(|a| {
+                    let p = a.rel_path.to_lowercase();
+                    p == t.to_lowercase() || p.ends_with(&suffix)
+                });
+            }
+            let source_dir = parent_dir(source_rel);
+            attachments
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| a.rel_path.rsplit('/').next().is_some_and(|n| n.eq_ignore_ascii_case(t)))
+                .min_by_key(|(_, a)| (parent_dir(&a.rel_path) != source_dir, a.rel_path.len()))
+                .map(|(i, _)| i)
+        }
+    }
+}
+
+/// Notes (vault-relative paths) whose links resolve to `target_rel`, with the
+/// number of such links in each.
+pub fn incoming_links(index: &VaultIndex, root: &Path, target_rel: &str) -> Vec<(String, usize)> {
+    let mut out = Vec::new();
+    for (i, entry) in index.entries.iter().enumerate() {
+        progress::report(i, index.entries.len());
+        if progress::is_cancelled() {
+            break;
+        }
+        if entry.rel_path == target_rel {
+            continue;
+        }
+        let Ok(content) = std::fs::read_to_string(root.join(&entry.rel_path)) else {
+            continue;
+        };
+        let count = scan_links(&content)
+            .iter()
+            .filter(|l| {
+                resolve_link(index, &entry.rel_path, l.kind, &content[l.target.clone()]).as_deref()
+                    == Some(target_rel)
+            })
+            .count();
+        if count > 0 {
+            out.push((entry.rel_path.clone(), count));
+        }
+    }
+    progress::report(index.entries.len(), index.entries.len());
+    out
+}
+
+/// Number of notes missing from `index` (protected as unreadable, or outside
+/// the session's directories) whose links resolve to `target_rel`. Links are
+/// resolved against `index`, so this is what the session would break without
+/// being able to see or fix it; the notes themselves are not named.
+pub fn hidden_incoming(index: &VaultIndex, config: &Config, target_rel: &str) -> usize {
+    let visible: HashSet<&str> = index.entries.iter().map(|e| e.rel_path.as_str()).collect();
+    let mut count = 0;
+    for (path, rel) in config.files_with_unreadable() {
+        if progress::is_cancelled() {
+            break;
+        }
+        if !rel.ends_with(".md") || visible.contains(rel.as_str()) {
+            continue;
+        }
+        let Ok(content) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        if scan_links(&content)
+            .iter()
+            .any(|l| resolve_link(index, &rel, l.kind, &content[l.target.clone()]).as_deref() == Some(target_rel))
+        {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Rewrite the links in `content` (a file that lives at `source_old`, and will
+/// live at `source_new`) after a note moves from `old_rel` to `new_rel`.
+///
+/// Links that pointed at the moved note are retargeted in the same shape they
+/// were written (bare name, path, `.md` suffix or not), or in the format
+/// set as Obsidian's "New link format" in the vault. When the source file
+/// itself moves, its own links are fixed up so they keep pointing at the same
+/// files: relative markdown links are re-relativized, and wikilinks that
+/// would resolve differently from the new folder (relative, path-qualified
+/// or bare names) are rewritten to a path that still reaches the old target.
+/// Returns the new content and the number of rewritten links.
+pub fn rewrite_links(
+    index: &VaultIndex,
+    config: &Config,
+    content: &str,
+    source_old: &str,
+    source_new: &str,
+    old_rel: &str,
+    new_rel: &str,
+) -> (String, usize) {
+    let new_stem = stem(new_rel);
+    // A bare name is only safe if no other note already uses the new filename.
+    let bare_ok = !index
+        .entries
+        .iter()
+        .any(|e| e.rel_path != old_rel && e.title.eq_ignore_ascii_case(new_stem));
+
+    let mut out = String::with_capacity(content.len());
+    let mut last = 0;
+    let mut count = 0;
+
+    for link in scan_links(content) {
+        let raw = &content[link.target.clone()];
+        let Some(resolved) = resolve_link(index, source_old, link.kind, raw) else {
+            continue;
+        };
+        let points_at_moved = resolved == old_rel;
+        let source_moves = source_old != source_new;
+
+        let replacement = match link.kind {
+            LinkKind::Wiki | LinkKind::Embed => {
+                if raw.trim().is_empty() {
+                    continue;
+                }
+                let keep_ext = raw.ends_with(".md");
+                let mut t = if points_at_moved {
+                    let full = new_rel.strip_suffix(".md").unwrap_or(new_rel);
+                    match config.obsidian.link_format {
+                        Some(LinkFormat::Absolute) => full.to_string(),
+                        Some(LinkFormat::Relative) => relative_wikilink(source_new, new_rel),
+                        Some(LinkFormat::Shortest) if bare_ok => new_stem.to_string(),
+                        None if is_relative(raw) => relative_wikilink(source_new, new_rel),
+                        None if bare_ok && !raw.contains('/') => new_stem.to_string(),
+                        _ => full.to_string(),
+                    }
+                } else if source_moves
+                    && resolve_link(index, source_new, link.kind, raw).as_deref() != Some(resolved.as_str())
+                {
+                    // the moving note's own link would reach another note
+                    // from its new folder: pin it to the old target
+                    if is_relative(raw) {
+                        relative_wikilink(source_new, &resolved)
+                    } else {
+                        resolved.strip_suffix(".md").unwrap_or(&resolved).to_string()
+                    }
+                } else {
+                    continue;
+                };
+                if keep_ext && !t.ends_with(".md") {
+                    t.push_str(".md");
+                }
+                t
+            }
+            LinkKind::Markdown => {
+                let absolute = raw.starts_with('/');
+                let target = if points_at_moved {
+                    new_rel
+                } else if source_moves && !absolute && config.vault_root.join(&resolved).exists() {
+                    resolved.as_str()
+                } else {
+                    continue;
+                };
+                let path = if absolute {
+                    format!("/{target}")
+                } else {
+                    relative_path(source_new, target)
+                };
+                // written without the extension: keep it that way
+                let path = match path.strip_suffix(".md") {
+                    Some(bare) if !percent_decode(raw).ends_with(".md") => bare.to_string(),
+                    _ => path,
+                };
+                percent_encode(&path, raw)
+            }
+        };
+
+        if replacement != raw {
+            out.push_str(&content[last..link.target.start]);
+            out.push_str(&replacement);
+            last = link.target.end;
+            count += 1;
+        }
+    }
+
+    out.push_str(&content[last..]);
+    (out, count)
+}
+
+/// `to` as a relative wikilink target from `from`, without `.md`.
+fn relative_wikilink(from: &str, to: &str) -> String {
+    let rel = relative_path(from, to);
+    let rel = rel.strip_suffix(".md").unwrap_or(&rel);
+    if rel.contains('/') {
+        rel.to_string()
+    } else {
+        format!("./{rel}")
+    }
+}
+
+fn is_relative(target: &str) -> bool {
+    target.starts_with("./") || target.starts_with("../")
+}
+
+/// Decode `%XX` escapes; malformed ones are kept as written.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match hex.filter(|_| bytes[i] == b'%').and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Encode `path` for a markdown link, matching how `raw` was written: as is
+/// inside `<...>`, fully percent-encoded if `raw` used escapes beyond `%20`,
+/// otherwise with just the spaces encoded.
+fn percent_encode(path: &str, raw: &str) -> String {
+    if raw.contains(' ') {
+        return path.to_string();
+    }
+    let full = raw.match_indices('%').any(|(i, _)| {
+        raw.get(i + 1..i + 3)
+            .is_some_and(|h| h != "20" && h.chars().all(|c| c.is_ascii_hexdigit()))
+    });
+    if !full {
+        return path.replace(' ', "%20");
+    }
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/!$&'*+,;=:@".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+fn stem(rel: &str) -> &str {
+    let name = rel.rsplit('/').next().unwrap_or(rel);
+    name.strip_suffix(".md").unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempVault;
+
+    /// A vault holding `files`, its config and its index.
+    fn fixture(files: &[(&str, &str)]) -> (TempVault, Config, VaultIndex) {
+        let root = TempVault::new(files);
+        let config = Config::from_toml(&root, "").unwrap();
+        let index = crate::build_index(&config, &crate::auth::Scope::full());
+        (root, config, index)
+    }
+
+    fn targets(content: &str) -> Vec<(LinkKind, &str)> {
+        scan_links(content).into_iter().map(|l| (l.kind, &content[l.target])).collect()
+    }
+
+    #[test]
+    fn scan_links_finds_targets_without_anchor_or_alias() {
+        let content = "[[a#h|A]] ![[img.png]] [t](dir/b.md#x) [s](<c d.md>)";
+        assert_eq!(
+            targets(content),
+            [
+                (LinkKind::Wiki, "a"),
+                (LinkKind::Embed, "img.png"),
+                (LinkKind::Markdown, "dir/b.md"),
+                (LinkKind::Markdown, "c d.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_links_skips_code_and_external_links() {
+        let content = "`[[a]]` [[b]]\n```\n[[c]]\n```\n[w](https://example.com) [m](mailto:x@y)";
+        assert_eq!(targets(content), [(LinkKind::Wiki, "b")]);
+    }
+
+    #[test]
+    fn scan_links_uses_the_markdown_grammar() {
+        let content = "``[[a]]`` \\[[b]] [u](c(d).md) ![i](img/p.png) [r][ref] [h](#top) | [[e\\|E]] |\n\n[ref]: f.md\n";
+        assert_eq!(
+            targets(content),
+            [(LinkKind::Markdown, "c(d).md"), (LinkKind::Markdown, "img/p.png"), (LinkKind::Wiki, "e")]
+        );
+    }
+
+    #[test]
+    fn scan_links_reads_wikilinks_in_frontmatter() {
+        let content = "---\nrelated: \"[[a]]\"\ntags:\n  - x\n---\n# T\n[[b#h]]\n";
+        assert_eq!(targets(content), [(LinkKind::Wiki, "a"), (LinkKind::Wiki, "b")]);
+    }
+
+    #[test]
+    fn resolve_link_follows_obsidian_rules() {
+        let (_root, _, index) = fixture(&[
+            ("tech/a.md", ""),
+            ("tech/z.md", ""),
+            ("ideas/z.md", ""),
+            ("tech/sub/y.md", ""),
+            ("ideas/sub/y.md", ""),
+            ("tech/中文.md", ""),
+        ]);
+        let wiki = |source: &str, target: &str| resolve_link(&index, source, LinkKind::Wiki, target);
+        let md = |source: &str, target: &str| resolve_link(&index, source, LinkKind::Markdown, target);
+
+        // bare names prefer the source's folder
+        assert_eq!(wiki("tech/a.md", "z").as_deref(), Some("tech/z.md"));
+        assert_eq!(wiki("ideas/q.md", "Z.md").as_deref(), Some("ideas/z.md"));
+        // path-qualified names prefer the source's folder, then the root
+        assert_eq!(wiki("tech/a.md", "sub/y").as_deref(), Some("tech/sub/y.md"));
+        assert_eq!(wiki("ideas/q.md", "sub/y").as_deref(), Some("ideas/sub/y.md"));
+        assert_eq!(wiki("methods/q.md", "ideas/sub/y").as_deref(), Some("ideas/sub/y.md"));
+        assert_eq!(wiki("ideas/q.md", "../tech/z").as_deref(), Some("tech/z.md"));
+        assert_eq!(wiki("tech/a.md", "").as_deref(), Some("tech/a.md"));
+        assert_eq!(wiki("tech/a.md", "missing"), None);
+
+        assert_eq!(md("tech/a.md", "z.md").as_deref(), Some("tech/z.md"));
+        assert_eq!(md("tech/a.md", "z").as_deref(), Some("tech/z.md"));
+        assert_eq!(md("ideas/q.md", "/tech/z.md").as_deref(), Some("tech/z.md"));
+        assert_eq!(md("tech/a.md", "%E4%B8%AD%E6%96%87.md").as_deref(), Some("tech/中文.md"));
+        assert_eq!(md("tech/a.md", "../../x.md"), None);
+    }
+
+    #[test]
+    fn rewrite_links_retargets_incoming_links() {
+        let (_root, config, index) = fixture(&[("tech/a.md", ""), ("ideas/ref.md", "")]);
+        let content = "[[a|A]] ![[a#h]] [[tech/a.md]] [A](../tech/a.md#h) [A](../tech/a) [A](/tech/a.md)";
+        let (out, count) = rewrite_links(&index, &config, content, "ideas/ref.md", "ideas/ref.md", "tech/a.md", "methods/b.md");
+        assert_eq!(
+            out,
+            "[[b|A]] ![[b#h]] [[methods/b.md]] [A](../methods/b.md#h) [A](../methods/b) [A](/methods/b.md)"
+        );
+        assert_eq!(count, 6);
+    }
+
+    #[test]
+    fn rewrite_links_keeps_a_moving_notes_own_links_on_target() {
+        let (_root, config, index) = fixture(&[
+            ("tech/a.md", ""),
+            ("tech/w.md", ""),
+            ("tech/z.md", ""),
+            ("ideas/z.md", ""),
+            ("tech/sub/y.md", ""),
+            ("ideas/sub/y.md", ""),
+            ("tech/中文.md", ""),
+            ("tech/img/p.png", ""),
+        ]);
+        let content = "[[./w]] [[sub/y|Y]] [[z]] [[w]] [W](w) [W](w.md#h) [中](%E4%B8%AD%E6%96%87.md) ![p](img/p.png) [[a#self]]";
+        let (out, count) = rewrite_links(&index, &config, content, "tech/a.md", "ideas/a.md", "tech/a.md", "ideas/a.md");
+        assert_eq!(
+            out,
+            "[[../tech/w]] [[tech/sub/y|Y]] [[tech/z]] [[w]] [W](../tech/w) [W](../tech/w.md#h) \
+             [中](../tech/%E4%B8%AD%E6%96%87.md) ![p](../tech/img/p.png) [[a#self]]"
+        );
+        assert_eq!(count, 7);
+    }
+
+    #[test]
+    fn rewrite_links_leaves_unrelated_links_alone() {
+        let (_root, config, index) = fixture(&[("tech/a.md", ""), ("tech/w.md", "")]);
+        let content = "[[w]] [W](w.md) [x](https://example.com/a.md)";
+        let (out, count) = rewrite_links(&index, &config, content, "tech/w.md", "tech/w.md", "tech/a.md", "tech/b.md");
+        assert_eq!(out, content);
+        assert_eq!(count, 0);
+    }
+}
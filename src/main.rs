@@ -12,32 +12,79 @@
 // ════════════════════════════════════════════════════════════════════════════════════
 //
 // 1️⃣  INITIALIZATION
-//    ├─ Read OBSIDIAN_VAULT_ROOT env var or fallback to VAULT_ROOT const
-//    ├─ Build VaultIndex by scanning all .md files
+//    ├─ Load config (--config / OBSIDIAN_CONFIG / <vault>/.obsidian-mcp.toml):
+//    │  vault path, directories, statuses, filename rules, ignore globs, tips
+//    ├─ OBSIDIAN_VAULT_ROOT env var overrides the configured vault path
+//    ├─ [[vaults]] in the config file → several named vaults, each with its
+//    │  own index and conventions; tools pick one with `vault` (default: first)
+//    ├─ Build VaultIndex by scanning all .md files, skipping hidden folders,
+//    │  node_modules, Obsidian's excluded files / template folder
+//    │  (.obsidian/app.json, templates.json) and .obsidianmcpignore rules
 //    ├─ Parse frontmatter (tags, aliases, status) from each note
-//    ├─ Create mappings: tag→notes, name→note
-//    └─ Start MCP server with stdio transport
+//    ├─ Create mappings: tag→notes, name→note, attachment→referencing notes,
+//    │  note→canvases that show or link to it
+//    ├─ OBSIDIAN_GIT_AUTOCOMMIT=1 → commit after every mutating tool
+//    ├─ completion/complete → note paths, tags, directories for prompt / template args
+//    ├─ Poll the vault every OBSIDIAN_WATCH_INTERVAL seconds while resources are subscribed
+//    ├─ Log every tool call (tracing + logging/setLevel notifications),
+//    │  optionally as JSON lines to OBSIDIAN_AUDIT_LOG
+//    ├─ notifications/progress for long scans; notifications/cancelled stops them
+//    └─ Start MCP server over stdio, or HTTP with --transport http
+//       (tools + resources + prompts + logging capabilities)
 //
-// 2️⃣  AVAILABLE TOOLS (5 tools total)
+// 2️⃣  AVAILABLE TOOLS (21 tools total)
 //    ├─ 📂 note_index_tree() → Complete file tree + tag statistics
 //    ├─ 📋 write_note_tips() → Writing guidelines and conventions
-//    ├─ 🔍 query_note(params) → Multi-mode search (tags/exact/keyword)
-//    ├─ 📖 read_note(path) → Read full note content by path
-//    └─ ✏️  write_note(params) → Create/append notes with auto-frontmatter
+//    ├─ 🗂️  list_vaults() → Configured vaults with note counts
+//    ├─ 🔍 query_note(params) → Ranked search (tags/exact/keyword), across vaults
+//    ├─ 📖 read_note(params) → Read a note, or one section / block / line range,
+//    │  frontmatter only, optionally capped at max_chars; expand_embeds
+//    │  inlines ![[note#section]] embeds recursively (depth limit, cycles)
+//    ├─ 📚 read_notes(params) → Several notes (paths or query) in one call,
+//    │  sharing a character budget fairly
+//    ├─ 📑 note_outline(path) → Heading tree with line numbers and sizes,
+//    │  callouts, code-block languages, block ids, link counts
+//    ├─ ✏️  write_note(params) → Create/append notes with auto-frontmatter
+//    ├─ 🚚 move_note(params) → Move/rename note + rewrite incoming links
+//    ├─ 🗑️  delete_note(path) → Move note into .trash/ (warns on incoming links)
+//    ├─ ♻️  restore_note(trash_path) → Restore a trashed note to its original path
+//    ├─ 🧺 list_trash() → List trashed files with original path + deletion time
+//    ├─ 🖼️  list_attachments(params) → Attachments with type, size, referencing
+//    │  notes; unused=true for the ones no note references
+//    ├─ 📎 add_attachment(params) → Save base64 content into Obsidian's
+//    │  attachment folder and return the embed syntax
+//    ├─ 🗺️  read_canvas(path) → A .canvas as an outline: grouped nodes, edges
+//    ├─ 🎨 write_canvas(params) → Create / merge / prune a canvas by node id,
+//    │  validated against the JSON Canvas spec
+//    ├─ 🕘 note_history(params) → List / show / diff saved versions of a note
+//    ├─ ⏪ restore_note_version(params) → Roll a note back to a saved version
+//    ├─ 🌿 vault_log(params) → Git commit history (optionally for one note)
+//    ├─ 🔀 note_diff(params) → Diff a note between git revisions / working tree
+//    └─ 👤 note_blame(params) → Per-line last commit of a note
 //
 // 3️⃣  TYPICAL USAGE PATTERNS
 //    ├─ Discovery: note_index_tree() → understand vault structure
 //    ├─ Guidelines: write_note_tips() → learn writing conventions
 //    ├─ Search: query_note() → find relevant notes
-//    ├─ Read: read_note() → get full content
+//    ├─ Outline: note_outline() → see the shape of a long note first
+//    ├─ Read: read_note() → get full content, or just the part that matters
+//    ├─ Batch: read_notes() → read the search hits in one round trip
 //    └─ Write: write_note() → create new notes
 //
 // 4️⃣  VALIDATION & SECURITY
-//    ├─ Path validation (no ".." traversal)
-//    ├─ Directory whitelist (8 valid dirs only)
-//    ├─ Filename validation (lowercase+hyphens only)
-//    ├─ Status validation (active/archived/draft)
-//    └─ Auto frontmatter generation with timestamps
+//    ├─ Path sandboxing: lexical cleanup + canonical containment check,
+//    │  symlinks followed only while they stay inside the vault
+//    ├─ [secrets]: API keys, tokens, private keys (+ custom regexes, entropy
+//    │  heuristic) redacted or refused in tool results, resources and prompts
+//    ├─ [protected_paths]: unreadable paths hidden and refused, read_only
+//    │  paths refused by every modifying tool
+//    ├─ HTTP bearer tokens scoped to read/write + allowed directories
+//    ├─ --read-only / OBSIDIAN_ENABLED_TOOLS / enabled_tools decide the registered tools
+//    ├─ Directory whitelist (8 default dirs, configurable)
+//    ├─ Filename validation (lowercase+hyphens by default)
+//    ├─ Status validation (active/archived/draft by default)
+//    ├─ Auto frontmatter generation with timestamps
+//    └─ Version snapshot in .obsidian-mcp/history/ before every modification
 //
 // 5️⃣  NOTE STRUCTURE (Obsidian format)
 //    ├─ Frontmatter: tags, aliases, created, updated, status
@@ -52,27 +99,57 @@ use rmcp::{
     model::*,
     tool, tool_box,
     schemars, ServerHandler, ServiceExt,
-    transport::io::stdio,
 };
 use rmcp::handler::server::tool::Parameters;
 use rmcp::model::ErrorData as McpError;
+use rmcp::service::{Peer, RequestContext, RoleServer};
 use serde::Deserialize;
 use serde::de::Deserializer;
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use base64::Engine;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio_util::sync::CancellationToken;
 use walkdir::WalkDir;
 
-
-// default vault root
-const VAULT_ROOT: &str = r"D:\notes\Fromsko";
-
-const WRITE_NOTE_TIPS: &str = include_str!("../write-note-tips.md");
-
-fn get_vault_root() -> String {
-    std::env::var("OBSIDIAN_VAULT_ROOT")
-        .unwrap_or_else(|_| VAULT_ROOT.to_string())
-}
+mod audit;
+mod auth;
+mod canvas;
+mod completion;
+mod config;
+mod embeds;
+mod filter;
+mod git;
+mod guard;
+mod history;
+mod http;
+mod links;
+mod markdown;
+mod obsidian;
+mod progress;
+mod prompts;
+mod resources;
+mod sandbox;
+#[cfg(test)]
+mod test_util;
+mod toolset;
+mod watch;
+
+// Obsidian's own trash folder (Settings → Files & Links → Deleted files)
+const TRASH_DIR: &str = ".trash";
+
+// server-private state inside the vault (version history, ...)
+const STATE_DIR: &str = ".obsidian-mcp";
+
+// read_notes: default total character budget, most notes per call, and the
+// smallest share worth returning per note
+const READ_NOTES_BUDGET: usize = 20_000;
+const READ_NOTES_MAX: usize = 50;
+const READ_NOTES_MIN_SHARE: usize = 500;
+
+// largest file add_attachment accepts
+const MAX_ATTACHMENT_BYTES: usize = 20 * 1024 * 1024;
 
 fn flexible_string_vec<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
 where
@@ -145,11 +222,28 @@ struct NoteEntry {
     title: String,
 }
 
+/// A non-markdown file in the vault (image, PDF, canvas, ...).
+#[derive(Debug, Clone)]
+struct Attachment {
+    rel_path: String,
+    size: u64,
+    mime: String,
+    /// Notes linking to or embedding the file.
+    referenced_by: Vec<String>,
+}
+
 #[derive(Debug, Default)]
 struct VaultIndex {
     entries: Vec<NoteEntry>,
     tag_map: HashMap<String, Vec<usize>>,
     name_map: HashMap<String, usize>,
+    attachments: Vec<Attachment>,
+    /// Note → canvases that show it as a file node or link to it from a text
+    /// node, with the number of such references.
+    canvas_refs: HashMap<String, Vec<(String, usize)>>,
+    /// When the walk finished; lets sessions sharing the index skip a
+    /// rebuild another one just did.
+    built: Option<std::time::Instant>,
 }
 
 fn parse_frontmatter(content: &str) -> (Vec<String>, Vec<String>, String) {
@@ -202,23 +296,31 @@ fn parse_frontmatter(content: &str) -> (Vec<String>, Vec<String>, String) {
     (tags, aliases, status)
 }
 
-fn build_index(root: &Path) -> VaultIndex {
+fn build_index(config: &config::Config, scope: &auth::Scope) -> VaultIndex {
     let mut index = VaultIndex::default();
 
-    for entry in WalkDir::new(root)
+    let (files, others): (Vec<_>, Vec<_>) = config
+        .files()
         .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| {
-            e.file_type().is_file()
-                && e.path().extension().map(|ext| ext == "md").unwrap_or(false)
+        .filter(|(_, rel)| scope.allows(rel))
+        .partition(|(_, rel)| rel.ends_with(".md"));
+    index.attachments = others
+        .into_iter()
+        .map(|(path, rel)| Attachment {
+            size: std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+            mime: resources::mime_for(&path),
+            rel_path: rel,
+            referenced_by: Vec::new(),
         })
-    {
-        let path = entry.path();
-        let rel = path
-            .strip_prefix(root)
-            .unwrap_or(path)
-            .to_string_lossy()
-            .replace('\\', "/");
+        .collect();
+    // (note, link kind, target) of every link, resolved once all notes are known
+    let mut note_links: Vec<(usize, links::LinkKind, String)> = Vec::new();
+
+    let total = files.len();
+    for (i, (path, rel)) in files.into_iter().enumerate() {
+        if progress::is_cancelled() {
+            break;
+        }
 
         let title = path
             .file_stem()
@@ -230,6 +332,9 @@ fn build_index(root: &Path) -> VaultIndex {
         let (tags, aliases, status) = parse_frontmatter(&content);
 
         let idx = index.entries.len();
+        for link in links::scan_links(&content) {
+            note_links.push((idx, link.kind, content[link.target].to_string()));
+        }
         index.entries.push(NoteEntry {
             rel_path: rel,
             tags: tags.clone(),
@@ -246,26 +351,99 @@ fn build_index(root: &Path) -> VaultIndex {
                 .or_default()
                 .push(idx);
         }
+        progress::report(i + 1, total);
+    }
+
+    for (note, kind, target) in note_links {
+        let source = &index.entries[note].rel_path;
+        if let Some(a) = links::resolve_attachment(&index, source, kind, &target) {
+            let source = source.clone();
+            let referenced_by = &mut index.attachments[a].referenced_by;
+            if !referenced_by.contains(&source) {
+                referenced_by.push(source);
+            }
+        }
+    }
+
+    // canvases: file nodes hold vault paths, text nodes hold ordinary links
+    let canvases: Vec<String> = index
+        .attachments
+        .iter()
+        .filter(|a| a.rel_path.ends_with(".canvas"))
+        .map(|a| a.rel_path.clone())
+        .collect();
+    for rel in canvases {
+        let content = std::fs::read_to_string(config.vault_root.join(&rel)).unwrap_or_default();
+        let Ok(parsed) = canvas::Canvas::parse(&content) else {
+            continue;
+        };
+        let (files, text_links) = parsed.references();
+        let mut notes: Vec<String> = Vec::new();
+        let mut attachments = Vec::new();
+        for file in files {
+            if index.entries.iter().any(|e| e.rel_path == file) {
+                notes.push(file.to_string());
+            } else if let Some(a) = index.attachments.iter().position(|a| a.rel_path == file) {
+                attachments.push(a);
+            }
+        }
+        for (kind, target) in text_links {
+            let note = links::resolve_link(&index, &rel, kind, &target)
+                .filter(|n| index.entries.iter().any(|e| e.rel_path == *n));
+            if let Some(note) = note {
+                notes.push(note);
+            } else if let Some(a) = links::resolve_attachment(&index, &rel, kind, &target) {
+                attachments.push(a);
+            }
+        }
+        for a in attachments {
+            let referenced_by = &mut index.attachments[a].referenced_by;
+            if !referenced_by.contains(&rel) {
+                referenced_by.push(rel.clone());
+            }
+        }
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for note in notes {
+            *counts.entry(note).or_default() += 1;
+        }
+        for (note, count) in counts {
+            index.canvas_refs.entry(note).or_default().push((rel.clone(), count));
+        }
     }
 
+    index.built = Some(std::time::Instant::now());
     index
 }
 
-fn build_file_tree(root: &Path) -> String {
+fn build_file_tree(config: &config::Config, scope: &auth::Scope) -> String {
     let mut lines = Vec::new();
-    tree_recursive(root, "", &mut lines);
+    tree_recursive(config, &config.vault_root, "", "", scope, &mut lines);
     lines.join("\n")
 }
 
-fn tree_recursive(dir: &Path, prefix: &str, lines: &mut Vec<String>) {
+fn tree_recursive(
+    config: &config::Config,
+    dir: &Path,
+    rel_dir: &str,
+    prefix: &str,
+    scope: &auth::Scope,
+    lines: &mut Vec<String>,
+) {
+    let rel_of = |name: &str| {
+        if rel_dir.is_empty() {
+            name.to_string()
+        } else {
+            format!("{rel_dir}/{name}")
+        }
+    };
     let mut entries: Vec<_> = std::fs::read_dir(dir)
         .into_iter()
         .flatten()
         .filter_map(|e| e.ok())
         .filter(|e| {
-            !e.file_name()
-                .to_string_lossy()
-                .starts_with('.')
+            let rel = rel_of(&e.file_name().to_string_lossy());
+            let is_dir = e.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            scope.allows_prefix(&rel) && !config.is_ignored(&rel, is_dir)
         })
         .collect();
     entries.sort_by_key(|e| e.file_name());
@@ -284,13 +462,71 @@ fn tree_recursive(dir: &Path, prefix: &str, lines: &mut Vec<String>) {
             } else {
                 format!("{prefix}│   ")
             };
-            tree_recursive(&path, &child_prefix, lines);
+            tree_recursive(config, &path, &rel_of(&name), &child_prefix, scope, lines);
         } else {
             lines.push(format!("{prefix}{connector}{name}"));
         }
     }
 }
 
+/// Notes matching a `query_note` request, with a relevance score (higher
+/// is better) so results from several vaults can be merged.
+fn search_index(idx: &VaultIndex, params: &QueryNoteParams) -> Vec<(usize, u32)> {
+    let mut candidates: Vec<usize> = (0..idx.entries.len()).collect();
+
+    if let Some(ref tags) = params.tags {
+        for tag in tags {
+            let tag_lower = tag.to_lowercase();
+            if let Some(indices) = idx.tag_map.get(&tag_lower) {
+                let set: BTreeSet<usize> = indices.iter().copied().collect();
+                candidates.retain(|i| set.contains(i));
+            } else {
+                candidates.clear();
+                break;
+            }
+        }
+    }
+
+    if let Some(ref name) = params.exact_name {
+        let name_lower = name.to_lowercase().replace(".md", "");
+        if let Some(&i) = idx.name_map.get(&name_lower) {
+            candidates.retain(|c| *c == i);
+        } else {
+            candidates.clear();
+        }
+    }
+
+    let kw_lower = params.keyword.as_ref().map(|kw| kw.to_lowercase());
+    candidates
+        .into_iter()
+        .filter_map(|i| {
+            let e = &idx.entries[i];
+            let mut score = if params.exact_name.is_some() { 100 } else { 0 };
+            if let Some(kw) = &kw_lower {
+                let title = e.title.to_lowercase();
+                score += if title == *kw {
+                    50
+                } else if title.starts_with(kw.as_str()) {
+                    40
+                } else if title.contains(kw.as_str()) {
+                    30
+                } else if e.aliases.iter().any(|a| a.to_lowercase().contains(kw.as_str())) {
+                    20
+                } else if e.tags.iter().any(|t| t.to_lowercase() == *kw) {
+                    15
+                } else if e.tags.iter().any(|t| t.to_lowercase().contains(kw.as_str())) {
+                    10
+                } else if e.rel_path.to_lowercase().contains(kw.as_str()) {
+                    5
+                } else {
+                    return None;
+                };
+            }
+            Some((i, score))
+        })
+        .collect()
+}
+
 fn update_frontmatter_date(fm: &str, today: &str) -> String {
     fm.lines()
         .map(|line| {
@@ -304,32 +540,509 @@ fn update_frontmatter_date(fm: &str, today: &str) -> String {
         .join("\n")
 }
 
+/// Split a path inside `.trash/` into the note's original vault-relative path
+/// and its deletion time. Notes trashed by `delete_note` live under a
+/// `YYYYMMDD-HHMMSS/` folder (`YYYYMMDD-HHMMSS-N/` for later deletes within
+/// the same second); anything else (e.g. trashed by Obsidian itself) is
+/// restored to the same relative path.
+fn trash_origin(trash_rel: &str) -> (String, Option<chrono::NaiveDateTime>) {
+    if let Some((folder, rest)) = trash_rel.split_once('/') {
+        let (stamp, suffix) = folder.split_at(folder.len().min(15));
+        let suffix_ok = suffix.is_empty()
+            || suffix.strip_prefix('-').is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+        if let (true, Ok(ts)) = (suffix_ok, chrono::NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S")) {
+            return (rest.to_string(), Some(ts));
+        }
+    }
+    (trash_rel.to_string(), None)
+}
+
+/// Wrap `body` in a code fence longer than any backtick run it contains, so
+/// notes that themselves contain fences render intact.
+fn fenced(lang: &str, body: &str) -> String {
+    let longest = body
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}{lang}\n{}\n{fence}", body.trim_end_matches('\n'))
+}
+
+/// `bytes` as B / KB / MB.
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        b if b < 1024 => format!("{b} B"),
+        b if b < 1024 * 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+/// Byte length of the longest prefix of `text` with at most `max_chars`
+/// characters, ending on a whole line when there is one to end on.
+fn cut_point(text: &str, max_chars: usize) -> usize {
+    let cut = text.char_indices().nth(max_chars).map_or(text.len(), |(i, _)| i);
+    if cut == text.len() {
+        return cut;
+    }
+    text[..cut].rfind('\n').filter(|&i| i > 0).unwrap_or(cut)
+}
+
+/// Split `budget` characters over texts of the given lengths: short texts get
+/// all they need, the rest share what is left equally.
+fn fair_shares(lengths: &[usize], budget: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..lengths.len()).collect();
+    order.sort_by_key(|&i| lengths[i]);
+    let mut shares = vec![0; lengths.len()];
+    let mut left = budget;
+    for (n, &i) in order.iter().enumerate() {
+        let share = lengths[i].min(left / (order.len() - n));
+        shares[i] = share;
+        left -= share;
+    }
+    shares
+}
+
+/// Render a pending change for dry-run previews: the full resulting file, or a
+/// unified diff against the current content.
+fn render_preview(old_rel: &str, new_rel: &str, old: &str, new: &str, diff: bool) -> String {
+    if !diff {
+        return fenced("markdown", new);
+    }
+    if old == new {
+        return "（内容无变化）".to_string();
+    }
+    let old_header = if old.is_empty() { "/dev/null".to_string() } else { format!("a/{old_rel}") };
+    let text = similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&old_header, &format!("b/{new_rel}"))
+        .to_string();
+    fenced("diff", &text)
+}
+
 // ---------------------------------------------------------------------------
 // MCP Server
 // ---------------------------------------------------------------------------
 
+/// One configured vault and its index.
+#[derive(Clone)]
+struct Vault {
+    config: Arc<config::Config>,
+    index: Arc<RwLock<VaultIndex>>,
+    /// Background poll for sessions with subscriptions, shared by all of them.
+    poller: Arc<watch::Poller>,
+}
+
+/// Ids for `ObsidianMcp::session`.
+static NEXT_SESSION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+fn next_session() -> u64 {
+    NEXT_SESSION.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
 #[derive(Clone)]
 pub struct ObsidianMcp {
+    // The vault requests act on: the default one, or the one a tool call
+    // picked with `vault` (see `in_vault`).
     index: Arc<RwLock<VaultIndex>>,
-    vault_root: PathBuf,
+    /// Vault location and conventions.
+    config: Arc<config::Config>,
+    /// All configured vaults, the default one first.
+    vaults: Arc<Vec<Vault>>,
+    history: history::RetentionPolicy,
+    git_autocommit: bool,
+    // Per-connection state; see `for_session`.
+    /// Identifies the connection to the vault poller.
+    session: u64,
+    /// Resource URIs the client subscribed to.
+    subscriptions: Arc<RwLock<HashSet<String>>>,
+    /// Vault fingerprint at the last index rebuild, for change notifications.
+    vault_state: Arc<Mutex<watch::VaultState>>,
+    peer: Option<Peer<RoleServer>>,
+    /// Cancelled when the connection ends; stops polling for it.
+    shutdown: CancellationToken,
+    /// What the client may read and write.
+    scope: Arc<auth::Scope>,
+    /// Minimum level for `notifications/message`; `None` until the client
+    /// calls `logging/setLevel`.
+    log_level: Arc<RwLock<Option<LoggingLevel>>>,
+    /// JSON-lines audit file shared by all sessions (`OBSIDIAN_AUDIT_LOG`).
+    audit_log: Option<Arc<audit::AuditLog>>,
+    /// Progress tokens of in-flight requests, filled in by the transport.
+    progress_tokens: progress::Tokens,
+    /// Tools registered for clients, decided at startup.
+    tools: Arc<toolset::ToolSet>,
 }
 
 impl ObsidianMcp {
-    pub fn new() -> Self {
-        let vault_root = PathBuf::from(get_vault_root());
-        let index = build_index(&vault_root);
+    /// `configs` must not be empty; the first one is the default vault.
+    pub fn new(configs: Vec<config::Config>, tools: toolset::ToolSet) -> Self {
+        let scope = auth::Scope::full();
+        let vaults: Vec<Vault> = configs
+            .into_iter()
+            .map(|config| Vault {
+                index: Arc::new(RwLock::new(build_index(&config, &scope))),
+                config: Arc::new(config),
+                poller: Arc::new(watch::Poller::from_env()),
+            })
+            .collect();
+        let default = vaults[0].clone();
+        let vault_state = match default.index.read() {
+            Ok(idx) => watch::scan(&idx, &default.config, &scope),
+            Err(_) => watch::VaultState::default(),
+        };
+        let audit_log = match audit::AuditLog::from_env() {
+            Ok(log) => log.map(Arc::new),
+            Err(e) => {
+                tracing::error!("cannot open audit log: {e}");
+                None
+            }
+        };
         Self {
-            index: Arc::new(RwLock::new(index)),
-            vault_root,
+            index: default.index,
+            config: default.config,
+            vaults: Arc::new(vaults),
+            history: history::RetentionPolicy::from_env(),
+            git_autocommit: git::autocommit_from_env(),
+            session: next_session(),
+            subscriptions: Arc::new(RwLock::new(HashSet::new())),
+            vault_state: Arc::new(Mutex::new(vault_state)),
+            peer: None,
+            shutdown: CancellationToken::new(),
+            scope: Arc::new(scope),
+            log_level: Arc::new(RwLock::new(None)),
+            audit_log,
+            progress_tokens: progress::Tokens::default(),
+            tools: Arc::new(tools),
+        }
+    }
+
+    /// Names of all tools the server implements.
+    pub fn tool_names() -> Vec<String> {
+        let mut names: Vec<String> = Self::tool_box().list().into_iter().map(|t| t.name.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    /// A handler for one more client connection: shares the indexes and
+    /// settings, but has its own subscriptions and change tracking. A client
+    /// restricted to some directories gets its own indexes of just those.
+    pub fn for_session(&self, shutdown: CancellationToken, scope: auth::Scope) -> Self {
+        // a restricted token gets its own indexes; the default vault's is
+        // built now, the others' on first use (see `view`)
+        let vaults: Vec<Vault> = if scope.is_restricted() {
+            self.vaults
+                .iter()
+                .enumerate()
+                .map(|(i, v)| Vault {
+                    config: v.config.clone(),
+                    index: Arc::new(RwLock::new(if i == 0 {
+                        build_index(&v.config, &scope)
+                    } else {
+                        VaultIndex::default()
+                    })),
+                    poller: v.poller.clone(),
+                })
+                .collect()
+        } else {
+            self.vaults.to_vec()
+        };
+        let default = vaults[0].clone();
+        let vault_state = match default.index.read() {
+            Ok(idx) => watch::scan(&idx, &default.config, &scope),
+            Err(_) => watch::VaultState::default(),
+        };
+        Self {
+            index: default.index,
+            config: default.config,
+            vaults: Arc::new(vaults),
+            session: next_session(),
+            subscriptions: Arc::new(RwLock::new(HashSet::new())),
+            vault_state: Arc::new(Mutex::new(vault_state)),
+            peer: None,
+            shutdown,
+            scope: Arc::new(scope),
+            log_level: Arc::new(RwLock::new(None)),
+            progress_tokens: progress::Tokens::default(),
+            ..self.clone()
+        }
+    }
+
+    /// The transport must decode client messages through these for progress
+    /// notifications to work.
+    pub fn progress_tokens(&self) -> progress::Tokens {
+        self.progress_tokens.clone()
+    }
+
+    /// This handler, acting on the vault named `name` (default: the first
+    /// one). Change notifications only cover the default vault, so views of
+    /// the others have no peer to notify.
+    fn in_vault(&self, name: Option<&str>) -> Result<Self, McpError> {
+        let Some(name) = name.map(str::trim).filter(|n| !n.is_empty()) else {
+            return Ok(self.clone());
+        };
+        match self.vaults.iter().position(|v| v.config.name == name) {
+            Some(i) => Ok(self.view(i)),
+            None => Err(McpError::invalid_params(
+                format!(
+                    "未知的知识库 '{name}'，可用的知识库：{}",
+                    self.vaults.iter().map(|v| v.config.name.as_str()).collect::<Vec<_>>().join(", ")
+                ),
+                None,
+            )),
+        }
+    }
+
+    fn view(&self, i: usize) -> Self {
+        if i == 0 {
+            return self.clone();
         }
+        let vault = &self.vaults[i];
+        let view = Self {
+            index: vault.index.clone(),
+            config: vault.config.clone(),
+            subscriptions: Arc::new(RwLock::new(HashSet::new())),
+            vault_state: Arc::new(Mutex::new(watch::VaultState::default())),
+            peer: None,
+            ..self.clone()
+        };
+        if view.index.read().is_ok_and(|idx| idx.built.is_none()) {
+            view.rebuild_index();
+        }
+        view
     }
 
     fn rebuild_index(&self) {
-        let new_index = build_index(&self.vault_root);
+        let new_index = build_index(&self.config, &self.scope);
+        // keep the old index rather than a partial one
+        if progress::is_cancelled() {
+            return;
+        }
+        self.notify_changes(&new_index);
         if let Ok(mut idx) = self.index.write() {
             *idx = new_index;
         }
     }
+
+    fn poller(&self) -> &watch::Poller {
+        &self.vaults[0].poller
+    }
+
+    /// Have the vault poller check for edits made outside the server (e.g.
+    /// in Obsidian), so they reach this client's subscriptions without
+    /// waiting for the next request.
+    fn start_polling(&self) {
+        let Some(interval) = self.poller().interval() else {
+            return;
+        };
+        let server = self.clone();
+        self.poller().add(self.session, move || server.poll(interval));
+    }
+
+    fn stop_polling(&self) {
+        self.poller().remove(self.session);
+    }
+
+    /// One poller tick. Sessions sharing an index take turns within a tick,
+    /// so only the first one walks the vault.
+    fn poll(&self, interval: std::time::Duration) {
+        let fresh = self
+            .index
+            .read()
+            .is_ok_and(|idx| idx.built.is_some_and(|t| t.elapsed() < interval / 2));
+        if !fresh {
+            self.rebuild_index();
+        } else if let Ok(idx) = self.index.read() {
+            self.notify_changes(&idx);
+        }
+    }
+
+    /// Compare the vault against the previous rebuild and notify the client
+    /// about changed subscribed resources and added / removed files.
+    fn notify_changes(&self, new_index: &VaultIndex) {
+        let Some(peer) = self.peer.clone() else {
+            return;
+        };
+        let new_state = watch::scan(new_index, &self.config, &self.scope);
+        let Ok(mut state) = self.vault_state.lock() else {
+            return;
+        };
+        let changes = match self.subscriptions.read() {
+            Ok(subs) => watch::diff(&state, &new_state, &subs),
+            Err(_) => return,
+        };
+        *state = new_state;
+        drop(state);
+
+        if changes.updated.is_empty() && !changes.list_changed {
+            return;
+        }
+        tokio::spawn(async move {
+            for uri in changes.updated {
+                if let Err(e) = peer
+                    .notify_resource_updated(ResourceUpdatedNotificationParam { uri })
+                    .await
+                {
+                    tracing::warn!("failed to send resource update: {e}");
+                }
+            }
+            if changes.list_changed {
+                if let Err(e) = peer.notify_resource_list_changed().await {
+                    tracing::warn!("failed to send resource list change: {e}");
+                }
+            }
+        });
+    }
+
+    /// Report a finished tool call to tracing, the audit file and, if it
+    /// asked for this level, the client.
+    fn log_tool_call(&self, peer: &Peer<RoleServer>, event: &audit::ToolEvent) {
+        event.trace();
+        let record = event.to_json();
+        if let Some(log) = &self.audit_log {
+            log.write(&record);
+        }
+        let level = event.level();
+        let wanted = match self.log_level.read() {
+            Ok(min) => min.as_ref().is_some_and(|min| audit::rank(&level) >= audit::rank(min)),
+            Err(_) => false,
+        };
+        if !wanted {
+            return;
+        }
+        let peer = peer.clone();
+        tokio::spawn(async move {
+            let param = LoggingMessageNotificationParam {
+                level,
+                logger: Some("obsidian-mcp".to_string()),
+                data: record,
+            };
+            if let Err(e) = peer.notify_logging_message(param).await {
+                tracing::warn!("failed to send log message: {e}");
+            }
+        });
+    }
+
+    /// Fail unless the client's scope covers `rel_path` and the vault does
+    /// not hide it (protected, or left out of the index).
+    fn check_read(&self, rel_path: &str) -> Result<(), McpError> {
+        if !self.scope.allows(rel_path) {
+            return Err(McpError::invalid_request(
+                format!("权限不足：令牌 '{}' 无权访问 {rel_path}", self.scope.name),
+                None,
+            ));
+        }
+        if self.config.protected.is_unreadable(rel_path, false) {
+            return Err(McpError::invalid_request(
+                format!("受保护的路径：{rel_path} 不可通过 MCP 访问"),
+                None,
+            ));
+        }
+        if self.config.is_ignored(rel_path, false) {
+            return Err(McpError::invalid_request(
+                format!("已忽略的路径：{rel_path} 不在索引范围内，不可通过 MCP 访问"),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the client may call `write_note` at all.
+    fn can_write(&self) -> bool {
+        self.scope.write && self.tools.allows("write_note")
+    }
+
+    /// Fail unless the client may modify `rel_path`.
+    fn check_write(&self, rel_path: &str) -> Result<(), McpError> {
+        if !self.scope.write {
+            return Err(McpError::invalid_request(
+                format!("权限不足：令牌 '{}' 为只读", self.scope.name),
+                None,
+            ));
+        }
+        self.check_read(rel_path)?;
+        if self.config.protected.is_read_only(rel_path) {
+            return Err(McpError::invalid_request(
+                format!("受保护的路径：{rel_path} 为只读，不可通过 MCP 修改"),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Redact (or refuse) the secrets in a tool result, following the
+    /// settings of the vault the call addressed.
+    fn guard_secrets(&self, vault: Option<&str>, mut result: CallToolResult) -> Result<CallToolResult, McpError> {
+        let config = match self.in_vault(vault) {
+            Ok(view) => view.config,
+            Err(_) => self.config.clone(),
+        };
+        for content in &mut result.content {
+            if let RawContent::Text(raw) = &mut content.raw {
+                if let Cow::Owned(text) = config.secrets.apply(&raw.text)? {
+                    raw.text = text;
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Where the vault-relative `rel_path` (from `sandbox::clean`) lives on
+    /// disk, refusing symlinks that lead out of the vault.
+    fn vault_path(&self, rel_path: &str) -> Result<PathBuf, McpError> {
+        sandbox::resolve(&self.config.vault_root, rel_path)
+    }
+
+    /// Spec violations of a canvas, plus file nodes whose file is missing
+    /// from the vault (Obsidian shows those as broken cards).
+    fn canvas_problems(&self, canvas: &canvas::Canvas) -> Vec<String> {
+        let mut problems = canvas.validate();
+        for node in &canvas.nodes {
+            if let canvas::NodeKind::File { file, .. } = &node.kind {
+                let exists = sandbox::clean(file)
+                    .ok()
+                    .and_then(|rel| self.vault_path(&rel).ok())
+                    .is_some_and(|p| p.is_file());
+                if !exists {
+                    problems.push(format!("文件节点 `{}` 引用的文件不存在：{file}", node.id));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Save the current content of a note to its version history before it
+    /// gets modified.
+    fn snapshot(&self, rel_path: &str, content: &str) -> Result<(), McpError> {
+        history::snapshot(&self.config.vault_root, rel_path, content, &self.history)
+            .map(|_| ())
+            .map_err(|e| McpError::internal_error(format!("保存历史版本失败: {e}"), None))
+    }
+
+    /// In git autocommit mode, commit `paths` after a mutating tool call.
+    /// Returns a line to append to the tool result (empty when disabled).
+    /// The change itself is already on disk, so a failed commit is reported
+    /// rather than turned into a tool error.
+    fn git_commit(&self, message: &str, paths: &[&str]) -> String {
+        if !self.git_autocommit {
+            return String::new();
+        }
+        match git::commit_paths(&self.config.vault_root, paths, message) {
+            Ok(Some(id)) => format!("\n\n📝 git 提交 `{id}`：{}", message.lines().next().unwrap_or("")),
+            Ok(None) => String::new(),
+            Err(e) => {
+                tracing::warn!("git commit failed: {e}");
+                format!("\n\n⚠️ git 提交失败：{}", e.message())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "选择知识库的参数。")]
+pub struct VaultParams {
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
@@ -344,12 +1057,15 @@ pub struct QueryNoteParams {
 
     #[schemars(description = "模糊搜索关键词，同时匹配文件名、别名和标签")]
     keyword: Option<String>,
+
+    #[schemars(description = "可选。只搜索该知识库（见 list_vaults），不传时搜索所有知识库并合并排序结果")]
+    vault: Option<String>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 #[schemars(description = "写入笔记的参数。所有元标签字段必须显式提供。")]
 pub struct WriteNoteParams {
-    #[schemars(description = "目标分区目录，必须是以下之一：tech, ai, projects, methods, career, ideas, cheatsheet, journal")]
+    #[schemars(description = "目标分区目录，默认为以下之一：tech, ai, projects, methods, career, ideas, cheatsheet, journal（以 write_note_tips 中的服务配置为准）")]
     directory: String,
 
     #[schemars(description = "文件名（不含 .md 后缀），必须是英文小写+短横线，如 docker-guide")]
@@ -363,11 +1079,22 @@ pub struct WriteNoteParams {
     #[serde(deserialize_with = "flexible_string_vec")]
     aliases: Vec<String>,
 
-    #[schemars(description = "笔记状态：active | archived | draft")]
+    #[schemars(description = "笔记状态，默认可选 active | archived | draft（以 write_note_tips 中的服务配置为准）")]
     status: String,
 
     #[schemars(description = "Markdown 正文内容（不含 frontmatter，由服务自动生成）。内容应遵循 Obsidian 格式规范：使用 Callout、Wikilinks、末尾包含 ## 相关笔记 章节。")]
     content: String,
+
+    #[schemars(description = "可选。为 true 时只返回写入后的完整文件内容（含自动生成的 Frontmatter），不写入磁盘")]
+    #[serde(default)]
+    dry_run: bool,
+
+    #[schemars(description = "可选。配合 dry_run 使用：为 true 时返回与当前内容的 unified diff，而不是完整文件")]
+    #[serde(default)]
+    diff: bool,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
@@ -375,139 +1102,435 @@ pub struct WriteNoteParams {
 pub struct ReadNoteParams {
     #[schemars(description = "笔记的相对路径（从 query_note 返回的 path 字段获取），如 \"tech/docker-guide.md\" 或 \"ai/mcp-development.md\"")]
     path: String,
-}
 
-const VALID_DIRS: &[&str] = &[
-    "tech", "ai", "projects", "methods", "career", "ideas", "cheatsheet", "journal",
-];
+    #[schemars(description = "可选。只读取某个章节（含子章节），按标题路径指定，如 \"## 部署/### Nginx\" 或 \"部署/Nginx\"；# 可省略，中间层级可跳过")]
+    section: Option<String>,
 
-#[tool]
-impl ObsidianMcp {
-    #[tool(
-        name = "note_index_tree",
-        description = "获取 Obsidian 知识库的完整文件树索引和所有已有标签。用于了解笔记库的整体结构和内容分布。无需参数。"
-    )]
-    async fn note_index_tree(&self) -> Result<CallToolResult, McpError> {
-        self.rebuild_index();
-        let tree = build_file_tree(&self.vault_root);
+    #[schemars(description = "可选。只读取带有块 ID 的段落、列表项或块，如 \"^abc123\"")]
+    block: Option<String>,
 
-        let idx = self.index.read().map_err(|e| {
-            McpError::internal_error(format!("lock error: {e}"), None)
-        })?;
+    #[schemars(description = "可选。起始行号（从 1 开始，包含 frontmatter），与 end_line 一起按行范围读取")]
+    start_line: Option<usize>,
 
-        let mut all_tags = BTreeSet::new();
-        for entry in &idx.entries {
-            for tag in &entry.tags {
-                all_tags.insert(tag.clone());
-            }
-        }
+    #[schemars(description = "可选。结束行号（包含该行），默认到文件末尾")]
+    end_line: Option<usize>,
 
-        let mut tag_summary = BTreeMap::new();
-        for (tag, indices) in &idx.tag_map {
-            tag_summary.insert(tag.clone(), indices.len());
-        }
+    #[schemars(description = "可选。为 true 时只返回 frontmatter")]
+    #[serde(default)]
+    frontmatter_only: bool,
 
-        let mut output = String::new();
-        output.push_str("## 📂 文件树\n\n```\n");
-        output.push_str(&tree);
-        output.push_str("\n```\n\n");
+    #[schemars(description = "可选。最多返回的字符数，超出部分会被截断，并说明截掉了哪些行")]
+    max_chars: Option<usize>,
 
-        output.push_str(&format!(
-            "## 🏷️ 所有标签（共 {} 个）\n\n",
-            all_tags.len()
-        ));
-        output.push_str("| 标签 | 笔记数 |\n|------|--------|\n");
-        for (tag, count) in &tag_summary {
-            output.push_str(&format!("| `{tag}` | {count} |\n"));
-        }
+    #[schemars(description = "可选。为 true 时把 ![[笔记]]、![[笔记#章节]]、![[笔记#^块ID]] 嵌入替换为被嵌入的内容（递归展开，带来源标记）")]
+    #[serde(default)]
+    expand_embeds: bool,
 
-        output.push_str(&format!(
-            "\n## 📊 统计\n\n- 笔记总数：{}\n- 标签总数：{}\n",
-            idx.entries.len(),
-            all_tags.len()
-        ));
+    #[schemars(description = "可选。expand_embeds 的最大嵌套层数，默认 3，最大 10")]
+    embed_depth: Option<usize>,
 
-        Ok(CallToolResult::success(vec![Content::text(output)]))
-    }
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
 
-    #[tool(
-        name = "write_note_tips",
-        description = "【重要】返回 Obsidian 知识库的完整写入规范文档。在你不确定如何正确写入笔记、不清楚目录结构、文件命名、Frontmatter 格式、Callout 用法、Wikilinks 规则时，必须先调用此工具查阅规范。这是你操作此知识库的权威参考手册。无需任何参数。"
-    )]
-    async fn write_note_tips(&self) -> Result<CallToolResult, McpError> {
-        Ok(CallToolResult::success(vec![Content::text(
-            WRITE_NOTE_TIPS.to_string(),
-        )]))
-    }
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "批量读取笔记的参数。paths 与查询参数（tags / exact_name / keyword）二选一。")]
+pub struct ReadNotesParams {
+    #[schemars(description = "笔记相对路径列表，如 [\"tech/docker-guide.md\", \"ai/mcp-development.md\"]")]
+    #[serde(default, deserialize_with = "flexible_string_vec_opt")]
+    paths: Option<Vec<String>>,
 
-    #[tool(
-        name = "query_note",
-        description = "搜索 Obsidian 知识库中的笔记。支持三种模式混合使用：1) 按标签过滤（多标签取交集）2) 精确文件名匹配 3) 模糊关键词搜索（匹配文件名、别名、标签）。至少提供一个查询参数。\n\n⚠️ 如果你还没有调用过 write_note_tips 了解本知识库的操作规范，请先调用它。\n\n调用示例：{\"tags\": [\"docker\"]} | {\"exact_name\": \"docker-guide\"} | {\"keyword\": \"Docker\"} | 混合: {\"tags\": [\"rust\"], \"keyword\": \"mcp\"}"
-    )]
-    async fn query_note(
-        &self,
-        #[tool(aggr)] Parameters(params): Parameters<QueryNoteParams>,
-    ) -> Result<CallToolResult, McpError> {
-        self.rebuild_index();
+    #[schemars(description = "按标签查询，规则同 query_note")]
+    #[serde(default, deserialize_with = "flexible_string_vec_opt")]
+    tags: Option<Vec<String>>,
 
-        let idx = self.index.read().map_err(|e| {
-            McpError::internal_error(format!("lock error: {e}"), None)
-        })?;
+    #[schemars(description = "按文件名精确查询，规则同 query_note")]
+    exact_name: Option<String>,
 
-        if params.tags.is_none() && params.exact_name.is_none() && params.keyword.is_none() {
-            return Err(McpError::invalid_params(
-                "至少提供 tags、exact_name 或 keyword 中的一个参数",
-                None,
-            ));
-        }
+    #[schemars(description = "按关键词查询，规则同 query_note")]
+    keyword: Option<String>,
 
-        let mut candidates: Vec<usize> = (0..idx.entries.len()).collect();
+    #[schemars(description = "可选。按查询读取时最多读取的笔记数（按相关度排序），默认 10，最大 50")]
+    limit: Option<usize>,
 
-        if let Some(ref tags) = params.tags {
-            for tag in tags {
-                let tag_lower = tag.to_lowercase();
-                if let Some(indices) = idx.tag_map.get(&tag_lower) {
-                    let set: BTreeSet<usize> = indices.iter().copied().collect();
-                    candidates.retain(|i| set.contains(i));
-                } else {
-                    candidates.clear();
-                    break;
-                }
-            }
-        }
+    #[schemars(description = "可选。所有笔记正文合计的字符预算，默认 20000；超出时公平截断，短笔记完整返回，长笔记平分剩余额度")]
+    max_chars: Option<usize>,
 
-        if let Some(ref name) = params.exact_name {
-            let name_lower = name.to_lowercase().replace(".md", "");
-            if let Some(&i) = idx.name_map.get(&name_lower) {
-                candidates.retain(|c| *c == i);
-            } else {
-                candidates.clear();
-            }
-        }
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
 
-        if let Some(ref kw) = params.keyword {
-            let kw_lower = kw.to_lowercase();
-            candidates.retain(|&i| {
-                let e = &idx.entries[i];
-                e.title.to_lowercase().contains(&kw_lower)
-                    || e.aliases.iter().any(|a| a.to_lowercase().contains(&kw_lower))
-                    || e.tags.iter().any(|t| t.to_lowercase().contains(&kw_lower))
-                    || e.rel_path.to_lowercase().contains(&kw_lower)
-            });
-        }
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "列出附件的参数")]
+pub struct ListAttachmentsParams {
+    #[schemars(description = "可选。只列出该目录（含子目录）下的附件，如 \"assets\"")]
+    directory: Option<String>,
 
-        if candidates.is_empty() {
+    #[schemars(description = "可选。只列出这篇笔记引用的附件，如 \"tech/docker-guide.md\"")]
+    note: Option<String>,
+
+    #[schemars(description = "可选。为 true 时只列出没有任何笔记引用的附件")]
+    #[serde(default)]
+    unused: bool,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "添加附件的参数")]
+pub struct AddAttachmentParams {
+    #[schemars(description = "附件文件名（含扩展名，不含目录），如 \"nginx-architecture.png\"")]
+    filename: String,
+
+    #[schemars(description = "文件内容的 Base64 编码（也接受 data: URL）")]
+    content_base64: String,
+
+    #[schemars(description = "可选。要嵌入该附件的笔记路径，如 \"tech/nginx-guide.md\"；Obsidian 附件设置为“笔记所在目录”时必填，也用于生成相对链接")]
+    note: Option<String>,
+
+    #[schemars(description = "为 true 时只预览保存位置和嵌入语法，不写入磁盘")]
+    #[serde(default)]
+    dry_run: bool,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "读取画布的参数")]
+pub struct ReadCanvasParams {
+    #[schemars(description = "画布的相对路径，如 \"projects/roadmap.canvas\"")]
+    path: String,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "创建或修改画布的参数。nodes / edges 中与现有 id 相同的项整体替换，其余追加。")]
+pub struct WriteCanvasParams {
+    #[schemars(description = "画布的相对路径，如 \"projects/roadmap.canvas\"（.canvas 后缀可省略）；不存在时创建")]
+    path: String,
+
+    #[schemars(description = "要新增或替换的节点。type 为 text（text）、file（file，可选 subpath 如 \"#章节\"）、link（url）或 group（可选 label）")]
+    #[serde(default)]
+    nodes: Vec<canvas::Node>,
+
+    #[schemars(description = "要新增或替换的连线，fromNode / toNode 必须是画布中的节点 id")]
+    #[serde(default)]
+    edges: Vec<canvas::Edge>,
+
+    #[schemars(description = "要删除的节点或连线 id；删除节点时连到它的连线一并删除")]
+    #[serde(default)]
+    remove: Vec<String>,
+
+    #[schemars(description = "为 true 时丢弃画布现有内容，只保留本次给出的 nodes / edges")]
+    #[serde(default)]
+    replace: bool,
+
+    #[schemars(description = "为 true 时只预览修改（unified diff），不写入磁盘")]
+    #[serde(default)]
+    dry_run: bool,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "查看笔记结构的参数")]
+pub struct NoteOutlineParams {
+    #[schemars(description = "笔记的相对路径，如 \"tech/docker-guide.md\"")]
+    path: String,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "移动/重命名笔记的参数。会自动改写所有指向该笔记的 Wikilinks 和 Markdown 链接，保留别名和章节锚点。")]
+pub struct MoveNoteParams {
+    #[schemars(description = "源笔记的相对路径，如 \"tech/docker-guide.md\"")]
+    path: String,
+
+    #[schemars(description = "目标分区目录，默认为以下之一：tech, ai, projects, methods, career, ideas, cheatsheet, journal（以 write_note_tips 中的服务配置为准）")]
+    directory: String,
+
+    #[schemars(description = "新文件名（不含 .md 后缀），必须是英文小写+短横线，如 docker-handbook")]
+    filename: String,
+
+    #[schemars(description = "为 true 时只预览将要修改的文件，不写入磁盘")]
+    #[serde(default)]
+    dry_run: bool,
+
+    #[schemars(description = "配合 dry_run 使用：为 true 时附带每个被修改文件的 unified diff")]
+    #[serde(default)]
+    diff: bool,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "删除笔记的参数。笔记会被移入知识库的 .trash/ 回收站，可用 restore_note 恢复。")]
+pub struct DeleteNoteParams {
+    #[schemars(description = "要删除的笔记相对路径，如 \"tech/docker-guide.md\"")]
+    path: String,
+
+    #[schemars(description = "为 true 时只预览将被移入回收站的文件和失效链接，不修改磁盘")]
+    #[serde(default)]
+    dry_run: bool,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "从回收站恢复笔记的参数。")]
+pub struct RestoreNoteParams {
+    #[schemars(description = "回收站内的路径（从 list_trash 返回的结果获取），如 \"20250101-120000/tech/docker-guide.md\"")]
+    trash_path: String,
+
+    #[schemars(description = "为 true 时只预览恢复目标路径，不修改磁盘")]
+    #[serde(default)]
+    dry_run: bool,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "查看笔记历史版本的参数。只传 path 时列出所有版本；同时传 version 时返回该版本内容，加 diff 则返回该版本与当前内容的差异。")]
+pub struct NoteHistoryParams {
+    #[schemars(description = "笔记的相对路径，如 \"tech/docker-guide.md\"（已删除的笔记同样可查）")]
+    path: String,
+
+    #[schemars(description = "可选。版本号（从版本列表获取），如 \"20250101-120000-000\"")]
+    version: Option<String>,
+
+    #[schemars(description = "可选。为 true 时返回该版本与当前内容的 unified diff")]
+    #[serde(default)]
+    diff: bool,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "将笔记回滚到某个历史版本的参数。回滚前会先保存当前内容为新的历史版本。")]
+pub struct RestoreNoteVersionParams {
+    #[schemars(description = "笔记的相对路径，如 \"tech/docker-guide.md\"")]
+    path: String,
+
+    #[schemars(description = "要回滚到的版本号（从 note_history 获取）")]
+    version: String,
+
+    #[schemars(description = "为 true 时只返回当前内容到该版本的 diff，不写入磁盘")]
+    #[serde(default)]
+    dry_run: bool,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "查看知识库 git 提交历史的参数。")]
+pub struct VaultLogParams {
+    #[schemars(description = "可选。只列出修改过该笔记的提交，如 \"tech/docker-guide.md\"")]
+    path: Option<String>,
+
+    #[schemars(description = "可选。最多返回的提交数，默认 20")]
+    limit: Option<usize>,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "比较笔记在两个 git 版本之间差异的参数。")]
+pub struct NoteDiffParams {
+    #[schemars(description = "笔记的相对路径，如 \"tech/docker-guide.md\"")]
+    path: String,
+
+    #[schemars(description = "可选。起始版本（提交号、分支、HEAD~3 等），默认 HEAD")]
+    from: Option<String>,
+
+    #[schemars(description = "可选。目标版本，不传则与当前磁盘上的内容比较")]
+    to: Option<String>,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[schemars(description = "查看笔记逐行最后修改提交的参数。")]
+pub struct NoteBlameParams {
+    #[schemars(description = "笔记的相对路径，如 \"tech/docker-guide.md\"")]
+    path: String,
+
+    #[schemars(description = "可选。版本（提交号、分支等），默认 HEAD")]
+    rev: Option<String>,
+
+    #[schemars(description = "可选。知识库名称（见 list_vaults），默认为第一个知识库")]
+    vault: Option<String>,
+}
+
+#[tool]
+impl ObsidianMcp {
+    #[tool(
+        name = "note_index_tree",
+        description = "获取 Obsidian 知识库的完整文件树索引和所有已有标签。用于了解笔记库的整体结构和内容分布。无需参数。"
+    )]
+    async fn note_index_tree(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<VaultParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        this.rebuild_index();
+        let tree = build_file_tree(&this.config, &this.scope);
+
+        let idx = this.index.read().map_err(|e| {
+            McpError::internal_error(format!("lock error: {e}"), None)
+        })?;
+
+        let mut all_tags = BTreeSet::new();
+        for entry in &idx.entries {
+            for tag in &entry.tags {
+                all_tags.insert(tag.clone());
+            }
+        }
+
+        audit::record_count(idx.entries.len());
+
+        let mut tag_summary = BTreeMap::new();
+        for (tag, indices) in &idx.tag_map {
+            tag_summary.insert(tag.clone(), indices.len());
+        }
+
+        let mut output = String::new();
+        output.push_str("## 📂 文件树\n\n```\n");
+        output.push_str(&tree);
+        output.push_str("\n```\n\n");
+
+        output.push_str(&format!(
+            "## 🏷️ 所有标签（共 {} 个）\n\n",
+            all_tags.len()
+        ));
+        output.push_str("| 标签 | 笔记数 |\n|------|--------|\n");
+        for (tag, count) in &tag_summary {
+            output.push_str(&format!("| `{tag}` | {count} |\n"));
+        }
+
+        output.push_str(&format!(
+            "\n## 📊 统计\n\n- 笔记总数：{}\n- 标签总数：{}\n",
+            idx.entries.len(),
+            all_tags.len()
+        ));
+
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        name = "write_note_tips",
+        description = "【重要】返回 Obsidian 知识库的完整写入规范文档。在你不确定如何正确写入笔记、不清楚目录结构、文件命名、Frontmatter 格式、Callout 用法、Wikilinks 规则时，必须先调用此工具查阅规范。这是你操作此知识库的权威参考手册。无需任何参数。"
+    )]
+    async fn write_note_tips(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<VaultParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        Ok(CallToolResult::success(vec![Content::text(
+            this.config.tips_with_settings(),
+        )]))
+    }
+
+    #[tool(
+        name = "list_vaults",
+        description = "列出本服务管理的所有知识库及其笔记数和分区目录。其他工具的可选参数 vault 取这里的名称，不传时使用第一个（默认）知识库；query_note 不传 vault 时搜索所有知识库。无需参数。"
+    )]
+    async fn list_vaults(&self) -> Result<CallToolResult, McpError> {
+        let mut output = format!("共 {} 个知识库：\n\n", self.vaults.len());
+        output.push_str("| 名称 | 笔记数 | 分区目录 | 默认 |\n");
+        output.push_str("|------|--------|----------|------|\n");
+        for (i, vault) in self.vaults.iter().enumerate() {
+            let view = self.view(i);
+            view.rebuild_index();
+            progress::check()?;
+            let notes = view.index.read().map(|idx| idx.entries.len()).map_err(|e| {
+                McpError::internal_error(format!("lock error: {e}"), None)
+            })?;
+            output.push_str(&format!(
+                "| `{}` | {} | {} | {} |\n",
+                vault.config.name,
+                notes,
+                vault.config.directory_names().join(", "),
+                if i == 0 { "✅" } else { "" },
+            ));
+        }
+        audit::record_count(self.vaults.len());
+
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        name = "query_note",
+        description = "搜索 Obsidian 知识库中的笔记。支持三种模式混合使用：1) 按标签过滤（多标签取交集）2) 精确文件名匹配 3) 模糊关键词搜索（匹配文件名、别名、标签）。至少提供一个查询参数。\n\n⚠️ 如果你还没有调用过 write_note_tips 了解本知识库的操作规范，请先调用它。\n\n调用示例：{\"tags\": [\"docker\"]} | {\"exact_name\": \"docker-guide\"} | {\"keyword\": \"Docker\"} | 混合: {\"tags\": [\"rust\"], \"keyword\": \"mcp\"}"
+    )]
+    async fn query_note(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<QueryNoteParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if params.tags.is_none() && params.exact_name.is_none() && params.keyword.is_none() {
+            return Err(McpError::invalid_params(
+                "至少提供 tags、exact_name 或 keyword 中的一个参数",
+                None,
+            ));
+        }
+
+        let views = match params.vault.as_deref() {
+            Some(name) => vec![self.in_vault(Some(name))?],
+            None => (0..self.vaults.len()).map(|i| self.view(i)).collect(),
+        };
+
+        // (score, vault, entry) across all searched vaults
+        let mut hits: Vec<(u32, usize, NoteEntry)> = Vec::new();
+        for (v, view) in views.iter().enumerate() {
+            view.rebuild_index();
+            progress::check()?;
+            let idx = view.index.read().map_err(|e| {
+                McpError::internal_error(format!("lock error: {e}"), None)
+            })?;
+            for (i, score) in search_index(&idx, &params) {
+                hits.push((score, v, idx.entries[i].clone()));
+            }
+        }
+        hits.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then_with(|| a.1.cmp(&b.1))
+                .then_with(|| a.2.rel_path.cmp(&b.2.rel_path))
+        });
+
+        audit::record_count(hits.len());
+        if hits.is_empty() {
             return Ok(CallToolResult::success(vec![Content::text(
                 "未找到匹配的笔记。".to_string(),
             )]));
         }
 
-        let mut output = format!("找到 {} 篇匹配笔记：\n\n", candidates.len());
-        output.push_str("| 文件 | 路径 | 标签 | 别名 | 状态 |\n");
-        output.push_str("|------|------|------|------|------|\n");
+        let multi = views.len() > 1;
+        let mut output = format!("找到 {} 篇匹配笔记：\n\n", hits.len());
+        if multi {
+            output.push_str("| 知识库 | 文件 | 路径 | 标签 | 别名 | 状态 |\n");
+            output.push_str("|--------|------|------|------|------|------|\n");
+        } else {
+            output.push_str("| 文件 | 路径 | 标签 | 别名 | 状态 |\n");
+            output.push_str("|------|------|------|------|------|\n");
+        }
 
-        for &i in &candidates {
-            let e = &idx.entries[i];
+        for (_, v, e) in &hits {
+            if multi {
+                output.push_str(&format!("| {} ", views[*v].config.name));
+            }
             output.push_str(&format!(
                 "| `{}` | `{}` | {} | {} | {} |\n",
                 e.title,
@@ -518,147 +1541,1484 @@ impl ObsidianMcp {
             ));
         }
 
-        Ok(CallToolResult::success(vec![Content::text(output)]))
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        name = "read_note",
+        description = "读取 Obsidian 知识库中某篇笔记的完整内容。传入笔记的相对路径（从 query_note 搜索结果或 note_index_tree 文件树中获取）。典型流程：先用 query_note 搜索找到目标笔记路径，再用 read_note 读取内容。\n\n长笔记可以只读一部分：section（标题路径）、block（块 ID）、start_line/end_line（行范围）或 frontmatter_only，四者选一；max_chars 限制返回长度并说明截掉的行。expand_embeds 为 true 时把 ![[笔记#章节]] 等嵌入展开为被嵌入的内容。\n\n调用示例：{\"path\": \"tech/docker-guide.md\"} | {\"path\": \"tech/docker-guide.md\", \"section\": \"## 部署/### Nginx\"} | {\"path\": \"ai/mcp-development.md\", \"block\": \"^abc123\"}"
+    )]
+    async fn read_note(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<ReadNoteParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let rel_path = sandbox::clean(&params.path)?;
+        this.check_read(&rel_path)?;
+
+        let file_path = this.vault_path(&rel_path)?;
+        if !file_path.exists() {
+            return Err(McpError::invalid_params(
+                format!("文件不存在: {rel_path}"),
+                None,
+            ));
+        }
+
+        let content = std::fs::read_to_string(&file_path).map_err(|e| {
+            McpError::internal_error(format!("读取文件失败: {e}"), None)
+        })?;
+
+        let selectors = [
+            params.section.is_some(),
+            params.block.is_some(),
+            params.start_line.is_some() || params.end_line.is_some(),
+            params.frontmatter_only,
+        ];
+        if selectors.iter().filter(|&&s| s).count() > 1 {
+            return Err(McpError::invalid_params(
+                "section、block、start_line/end_line 和 frontmatter_only 只能选择其一",
+                None,
+            ));
+        }
+        if params.max_chars == Some(0) {
+            return Err(McpError::invalid_params("max_chars 必须大于 0", None));
+        }
+        if params.embed_depth.is_some_and(|d| d == 0 || d > embeds::MAX_DEPTH) {
+            return Err(McpError::invalid_params(
+                format!("embed_depth 必须在 1 到 {} 之间", embeds::MAX_DEPTH),
+                None,
+            ));
+        }
+        if !selectors.contains(&true)
+            && !params.expand_embeds
+            && params.max_chars.is_none_or(|max| content.chars().count() <= max)
+        {
+            return Ok(CallToolResult::success(vec![Content::text(content)]));
+        }
+
+        let doc = markdown::Document::parse(&content);
+        let total = doc.lines.len();
+        let range = if let Some(section) = &params.section {
+            let i = doc.find_heading(section).ok_or_else(|| {
+                let available: Vec<String> = doc
+                    .headings
+                    .iter()
+                    .map(|h| format!("{} {}", "#".repeat(h.level), h.text))
+                    .collect();
+                McpError::invalid_params(
+                    if available.is_empty() {
+                        format!("未找到章节：{section}（该笔记没有标题）")
+                    } else {
+                        format!("未找到章节：{section}。可用的标题：{}", available.join("、"))
+                    },
+                    None,
+                )
+            })?;
+            doc.section_lines(i)
+        } else if let Some(block) = &params.block {
+            doc.find_block(block).ok_or_else(|| {
+                McpError::invalid_params(format!("未找到块：^{}", block.trim().trim_start_matches('^')), None)
+            })?
+        } else if params.frontmatter_only {
+            doc.frontmatter
+                .clone()
+                .ok_or_else(|| McpError::invalid_params(format!("笔记没有 frontmatter：{rel_path}"), None))?
+        } else {
+            let start = params.start_line.unwrap_or(1);
+            let end = params.end_line.unwrap_or(total).min(total);
+            if start == 0 || start > total || end < start {
+                return Err(McpError::invalid_params(
+                    format!("无效的行范围：{start}–{}，笔记共 {total} 行", params.end_line.unwrap_or(total)),
+                    None,
+                ));
+            }
+            start - 1..end
+        };
+        let mut range = range;
+        while range.end > range.start + 1 && doc.lines[range.end - 1].trim().is_empty() {
+            range.end -= 1;
+        }
+
+        let mut text = doc.lines[range.clone()].join("\n");
+        let lines = if range.end == range.start + 1 {
+            format!("第 {} 行", range.end)
+        } else {
+            format!("第 {}–{} 行", range.start + 1, range.end)
+        };
+        let mut notes = vec![format!("`{rel_path}` {lines}，共 {total} 行")];
+
+        let mut expanded = 0;
+        if params.expand_embeds {
+            this.rebuild_index();
+            progress::check()?;
+            let idx = this.index.read().map_err(|e| {
+                McpError::internal_error(format!("lock error: {e}"), None)
+            })?;
+            let load = |rel: &str| -> Result<String, McpError> {
+                this.check_read(rel)?;
+                std::fs::read_to_string(this.vault_path(rel)?)
+                    .map_err(|e| McpError::internal_error(format!("读取文件失败: {e}"), None))
+            };
+            let mut expander = embeds::Expander {
+                index: &idx,
+                load: &load,
+                max_depth: params.embed_depth.unwrap_or(embeds::DEFAULT_DEPTH),
+                expanded: 0,
+            };
+            text = expander.expand(&rel_path, &text);
+            expanded = expander.expanded;
+            notes.push(format!("已展开 {expanded} 处嵌入"));
+        }
+
+        if let Some(max) = params.max_chars {
+            let chars = text.chars().count();
+            if chars > max && expanded > 0 {
+                // line numbers no longer match the note once embeds are inlined
+                let keep = cut_point(&text, max);
+                notes.push(format!(
+                    "⚠️ 已截断：只显示了 {} / {chars} 个字符（含展开的嵌入内容）。可缩小范围或不展开嵌入后分段读取",
+                    text[..keep].chars().count(),
+                ));
+                text.truncate(keep);
+            } else if chars > max {
+                let keep = cut_point(&text, max);
+                let whole_lines = text[keep..].starts_with('\n');
+                let shown_lines = text[..keep].lines().count();
+                let last_shown = range.start + shown_lines;
+                let (next, omitted) = if whole_lines {
+                    (last_shown + 1, format!("第 {}–{} 行", last_shown + 1, range.end))
+                } else if last_shown < range.end {
+                    (last_shown, format!("第 {last_shown} 行的剩余部分及第 {}–{} 行", last_shown + 1, range.end))
+                } else {
+                    (last_shown, format!("第 {last_shown} 行的剩余部分"))
+                };
+                notes.push(format!(
+                    "⚠️ 已截断：只显示了 {} / {chars} 个字符，省略了{omitted}。可用 start_line={next} 继续读取",
+                    text[..keep].chars().count(),
+                ));
+                text.truncate(keep);
+            }
+        }
+        text.push_str("\n\n---\n");
+        text.push_str(&notes.join("\n"));
+
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
+
+    #[tool(
+        name = "read_notes",
+        description = "一次读取多篇笔记，省去多次调用 read_note。传入路径列表 paths，或像 query_note 一样按 tags / exact_name / keyword 查询（按相关度取前 limit 篇）。所有正文共享 max_chars 字符预算：短笔记完整返回，长笔记平分剩余额度并按行截断；末尾汇总每篇笔记返回了多少、截断或省略的原因。\n\n调用示例：{\"paths\": [\"tech/docker-guide.md\", \"tech/nginx-guide.md\"]} | {\"tags\": [\"docker\"], \"max_chars\": 10000}"
+    )]
+    async fn read_notes(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<ReadNotesParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let has_query = params.tags.is_some() || params.exact_name.is_some() || params.keyword.is_some();
+        let budget = params.max_chars.unwrap_or(READ_NOTES_BUDGET);
+        if budget == 0 {
+            return Err(McpError::invalid_params("max_chars 必须大于 0", None));
+        }
+
+        let paths: Vec<String> = match (&params.paths, has_query) {
+            (Some(_), true) | (None, false) => {
+                return Err(McpError::invalid_params(
+                    "paths 与 tags / exact_name / keyword 必须二选一",
+                    None,
+                ));
+            }
+            (Some(paths), false) => {
+                if paths.is_empty() {
+                    return Err(McpError::invalid_params("paths 不能为空", None));
+                }
+                if paths.len() > READ_NOTES_MAX {
+                    return Err(McpError::invalid_params(
+                        format!("一次最多读取 {READ_NOTES_MAX} 篇笔记"),
+                        None,
+                    ));
+                }
+                paths.clone()
+            }
+            (None, true) => {
+                let limit = params.limit.unwrap_or(10);
+                if limit == 0 || limit > READ_NOTES_MAX {
+                    return Err(McpError::invalid_params(
+                        format!("limit 必须在 1 到 {READ_NOTES_MAX} 之间"),
+                        None,
+                    ));
+                }
+                this.rebuild_index();
+                progress::check()?;
+                let idx = this.index.read().map_err(|e| {
+                    McpError::internal_error(format!("lock error: {e}"), None)
+                })?;
+                let query = QueryNoteParams {
+                    tags: params.tags.clone(),
+                    exact_name: params.exact_name.clone(),
+                    keyword: params.keyword.clone(),
+                    vault: None,
+                };
+                let mut hits = search_index(&idx, &query);
+                hits.sort_by(|a, b| {
+                    b.1.cmp(&a.1)
+                        .then_with(|| idx.entries[a.0].rel_path.cmp(&idx.entries[b.0].rel_path))
+                });
+                hits.iter().take(limit).map(|&(i, _)| idx.entries[i].rel_path.clone()).collect()
+            }
+        };
+        if paths.is_empty() {
+            audit::record_count(0);
+            return Ok(CallToolResult::success(vec![Content::text("未找到匹配的笔记。".to_string())]));
+        }
+
+        // (path, content or the reason it was left out)
+        let mut notes: Vec<(String, Result<String, String>)> = Vec::new();
+        for (i, path) in paths.iter().enumerate() {
+            progress::report(i, paths.len());
+            progress::check()?;
+            let loaded = sandbox::clean(path).and_then(|rel| {
+                this.check_read(&rel)?;
+                let file_path = this.vault_path(&rel)?;
+                let content = std::fs::read_to_string(&file_path)
+                    .map_err(|_| McpError::invalid_params(format!("文件不存在: {rel}"), None))?;
+                // one note with a secret should not sink the whole batch
+                let content = this.config.secrets.apply(&content)?.into_owned();
+                Ok((rel, content))
+            });
+            match loaded {
+                // the same note listed twice
+                Ok((rel, _)) if notes.iter().any(|(p, _)| *p == rel) => {}
+                Ok((rel, content)) => notes.push((rel, Ok(content))),
+                Err(e) => notes.push((path.clone(), Err(e.message.to_string()))),
+            }
+        }
+
+        let mut kept: Vec<usize> = (0..notes.len()).filter(|&i| notes[i].1.is_ok()).collect();
+        let mut lengths: Vec<usize> = kept
+            .iter()
+            .map(|&i| notes[i].1.as_ref().map_or(0, |c| c.chars().count()))
+            .collect();
+        // when the budget would leave only a few lines of each long note, drop
+        // the last of them rather than cut all of them down to nothing
+        let shares = loop {
+            let shares = fair_shares(&lengths, budget);
+            let starved = (0..kept.len())
+                .rev()
+                .find(|&k| shares[k] < lengths[k] && shares[k] < READ_NOTES_MIN_SHARE);
+            match starved {
+                Some(k) if kept.len() > 1 => {
+                    notes[kept.remove(k)].1 = Err("字符预算不足，未读取".to_string());
+                    lengths.remove(k);
+                }
+                _ => break shares,
+            }
+        };
+
+        let mut output = String::new();
+        let mut summary = Vec::new();
+        for (k, &i) in kept.iter().enumerate() {
+            let (rel, content) = &notes[i];
+            let Ok(content) = content else { continue };
+            let total_lines = content.lines().count();
+            let keep = cut_point(content, shares[k]);
+            output.push_str(&format!("## 📄 `{rel}`\n\n{}\n", fenced("markdown", &content[..keep])));
+            if keep < content.len() {
+                let shown_lines = content[..keep].lines().count();
+                let next = if content[keep..].starts_with('\n') { shown_lines + 1 } else { shown_lines };
+                output.push_str(&format!(
+                    "\n⚠️ 已截断：显示 {} / {} 个字符。可用 read_note 的 start_line={next} 继续读取\n",
+                    content[..keep].chars().count(),
+                    lengths[k],
+                ));
+                summary.push(format!(
+                    "| `{rel}` | {} / {} | 截断（显示到第 {shown_lines} 行，共 {total_lines} 行） |",
+                    content[..keep].chars().count(),
+                    lengths[k],
+                ));
+            } else {
+                summary.push(format!("| `{rel}` | {} | 完整 |", lengths[k]));
+            }
+            output.push('\n');
+        }
+        for (rel, content) in &notes {
+            if let Err(reason) = content {
+                summary.push(format!("| `{rel}` | 0 | 省略：{reason} |"));
+            }
+        }
+        audit::record_count(kept.len());
+
+        output.push_str(&format!(
+            "## 📊 汇总\n\n共 {} 篇，返回 {} 篇，字符预算 {budget}\n\n| 笔记 | 字符数 | 状态 |\n|------|--------|------|\n{}\n",
+            notes.len(),
+            kept.len(),
+            summary.join("\n"),
+        ));
+
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        name = "note_outline",
+        description = "查看一篇笔记的结构而不读取全文：标题树（行号、每个章节的字符数和链接数）、Callout（类型和标题）、代码块语言、块 ID 和链接统计。读长笔记前先用它定位，再用 read_note 的 section / block / start_line 只读需要的部分。\n\n调用示例：{\"path\": \"tech/docker-guide.md\"}"
+    )]
+    async fn note_outline(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<NoteOutlineParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let rel_path = sandbox::clean(&params.path)?;
+        this.check_read(&rel_path)?;
+
+        let file_path = this.vault_path(&rel_path)?;
+        if !file_path.is_file() {
+            return Err(McpError::invalid_params(format!("文件不存在: {rel_path}"), None));
+        }
+        let content = std::fs::read_to_string(&file_path).map_err(|e| {
+            McpError::internal_error(format!("读取文件失败: {e}"), None)
+        })?;
+        let doc = markdown::Document::parse(&content);
+
+        let mut output = format!(
+            "## 📑 `{rel_path}` 的结构\n\n- 共 {} 行，{} 个字符\n",
+            doc.lines.len(),
+            content.chars().count()
+        );
+        if let Some(fm) = &doc.frontmatter {
+            output.push_str(&format!("- Frontmatter：第 {}–{} 行\n", fm.start + 1, fm.end));
+        }
+
+        output.push_str(&format!("\n### 标题（共 {} 个）\n\n", doc.headings.len()));
+        if doc.headings.is_empty() {
+            output.push_str("无\n");
+        }
+        let min_level = doc.headings.iter().map(|h| h.level).min().unwrap_or(1);
+        for (i, h) in doc.headings.iter().enumerate() {
+            let section = doc.section_lines(i);
+            let links = doc.links.iter().filter(|l| section.contains(&(l.line - 1))).count();
+            output.push_str(&format!(
+                "{}- `{} {}` — 第 {}–{} 行，{} 字符{}\n",
+                "  ".repeat(h.level - min_level),
+                "#".repeat(h.level),
+                h.text,
+                section.start + 1,
+                section.end,
+                doc.chars_in(section.clone()),
+                if links > 0 { format!("，{links} 个链接") } else { String::new() },
+            ));
+        }
+
+        if !doc.callouts.is_empty() {
+            output.push_str(&format!("\n### Callout（共 {} 个）\n\n| 行 | 类型 | 标题 |\n|----|------|------|\n", doc.callouts.len()));
+            for c in &doc.callouts {
+                let fold = match c.fold {
+                    Some('+') => "（可折叠，默认展开）",
+                    Some(_) => "（可折叠，默认折叠）",
+                    None => "",
+                };
+                output.push_str(&format!("| {} | {}{fold} | {} |\n", c.line, c.kind, c.title));
+            }
+        }
+
+        if !doc.code_blocks.is_empty() {
+            output.push_str(&format!("\n### 代码块（共 {} 个）\n\n| 行 | 语言 |\n|----|------|\n", doc.code_blocks.len()));
+            for b in &doc.code_blocks {
+                let lines = if b.lines.len() == 1 {
+                    b.lines.end.to_string()
+                } else {
+                    format!("{}–{}", b.lines.start + 1, b.lines.end)
+                };
+                output.push_str(&format!("| {lines} | {} |\n", b.lang.as_deref().unwrap_or("（未标注）")));
+            }
+        }
+
+        if !doc.block_ids.is_empty() {
+            output.push_str(&format!("\n### 块 ID（共 {} 个）\n\n", doc.block_ids.len()));
+            for b in &doc.block_ids {
+                output.push_str(&format!("- `^{}` — 第 {} 行\n", b.id, b.line));
+            }
+        }
+
+        let count = |kind: links::LinkKind, external: bool| {
+            doc.links.iter().filter(|l| l.kind == kind && l.external == external).count()
+        };
+        let external = doc.links.iter().filter(|l| l.external).count();
+        output.push_str(&format!(
+            "\n### 链接（共 {} 个）\n\n- Wikilink：{}\n- 嵌入：{}\n- Markdown 链接：{}\n- 外部链接：{external}\n",
+            doc.links.len(),
+            count(links::LinkKind::Wiki, false),
+            count(links::LinkKind::Embed, false),
+            count(links::LinkKind::Markdown, false),
+        ));
+        audit::record_count(doc.headings.len());
+
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        name = "write_note",
+        description = "向 Obsidian 知识库写入笔记。自动生成 Frontmatter 头部。如果文件已存在则追加内容并更新 updated 日期。\n\n⚠️ 如果你还没有调用过 write_note_tips 了解本知识库的操作规范，请先调用它。\n\n所有 6 个内容参数必填；可选 dry_run（只预览写入后的完整文件，不落盘）和 diff（预览时返回 unified diff）。调用示例：{\"directory\": \"tech\", \"filename\": \"nginx-guide\", \"tags\": [\"nginx\"], \"aliases\": [\"Nginx 指南\"], \"status\": \"active\", \"content\": \"> [!abstract] 概述\\n> 内容\\n\\n## 相关笔记\\n\\n- [[docker-guide]]\"}"
+    )]
+    async fn write_note(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<WriteNoteParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let dir = this.config.validate_directory(&params.directory)?;
+        let filename = this.config.validate_filename(&params.filename)?;
+
+        this.config.validate_status(&params.status)?;
+
+        let rel_path = sandbox::clean(&format!("{dir}/{filename}.md"))?;
+        this.check_write(&rel_path)?;
+        let file_path = this.vault_path(&rel_path)?;
+        let target_dir = this.vault_path(dir)?;
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+
+        let existing = if file_path.exists() {
+            Some(std::fs::read_to_string(&file_path).map_err(|e| {
+                McpError::internal_error(format!("读取文件失败: {e}"), None)
+            })?)
+        } else {
+            None
+        };
+
+        let full_content = match &existing {
+            Some(existing) => {
+                if let Some(after_first) = existing.strip_prefix("---") {
+                    if let Some(end_pos) = after_first.find("\n---") {
+                        let fm = &existing[..end_pos + 3 + 4];
+                        let body = &existing[end_pos + 3 + 4..];
+                        let updated_fm = update_frontmatter_date(fm, &today);
+                        format!("{updated_fm}{body}\n\n{}", params.content)
+                    } else {
+                        format!("{existing}\n\n{}", params.content)
+                    }
+                } else {
+                    format!("{existing}\n\n{}", params.content)
+                }
+            }
+            None => {
+                let mut frontmatter = String::from("---\ntags:\n");
+                for tag in &params.tags {
+                    frontmatter.push_str(&format!("  - {tag}\n"));
+                }
+                frontmatter.push_str("aliases:\n");
+                for alias in &params.aliases {
+                    frontmatter.push_str(&format!("  - {alias}\n"));
+                }
+                frontmatter.push_str(&format!("created: {today}\n"));
+                frontmatter.push_str(&format!("updated: {today}\n"));
+                frontmatter.push_str(&format!("status: {}\n", params.status));
+                frontmatter.push_str("---\n\n");
+
+                format!("{frontmatter}{}", params.content)
+            }
+        };
+
+        if params.dry_run {
+            let action = if existing.is_some() { "追加内容到" } else { "创建笔记" };
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "【预览】将{action} `{rel_path}`，未写入磁盘。\n\n{}",
+                render_preview(
+                    &rel_path,
+                    &rel_path,
+                    existing.as_deref().unwrap_or(""),
+                    &full_content,
+                    params.diff,
+                )
+            ))]));
+        }
+
+        if let Some(existing) = &existing {
+            this.snapshot(&rel_path, existing)?;
+        }
+
+        if !target_dir.exists() {
+            std::fs::create_dir_all(&target_dir).map_err(|e| {
+                McpError::internal_error(format!("创建目录失败: {e}"), None)
+            })?;
+        }
+
+        std::fs::write(&file_path, &full_content).map_err(|e| {
+            McpError::internal_error(format!("写入文件失败: {e}"), None)
+        })?;
+
+        this.rebuild_index();
+        let committed = this.git_commit(&format!("write_note: {rel_path}"), &[&rel_path]);
+
+        if existing.is_some() {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "已追加内容到 `{rel_path}`，updated 日期已更新为 {today}。{committed}"
+            ))]));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "已创建笔记 `{rel_path}`。{committed}"
+        ))]))
+    }
+
+    #[tool(
+        name = "move_note",
+        description = "移动或重命名 Obsidian 知识库中的笔记，并自动改写所有指向它的 Wikilinks（含 ![[嵌入]]）和 Markdown 链接，保留别名和章节锚点；只读文件中的链接不会改写，会在结果中列出。目标目录和文件名遵循与 write_note 相同的规则。设置 dry_run 可先预览将要修改的所有文件，再加 diff 可查看每个文件的 unified diff。\n\n调用示例：{\"path\": \"tech/docker-guide.md\", \"directory\": \"tech\", \"filename\": \"docker-handbook\", \"dry_run\": true}"
+    )]
+    async fn move_note(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<MoveNoteParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let old_rel = sandbox::clean(&params.path)?;
+
+        let dir = this.config.validate_directory(&params.directory)?;
+        let filename = this.config.validate_filename(&params.filename)?;
+        let new_rel = sandbox::clean(&format!("{dir}/{filename}.md"))?;
+        this.check_write(&old_rel)?;
+        this.check_write(&new_rel)?;
+
+        if new_rel == old_rel {
+            return Err(McpError::invalid_params("目标路径与源路径相同", None));
+        }
+        let old_path = this.vault_path(&old_rel)?;
+        let new_path = this.vault_path(&new_rel)?;
+        if new_path.exists() {
+            return Err(McpError::invalid_params(
+                format!("目标文件已存在: {new_rel}"),
+                None,
+            ));
+        }
+
+        this.rebuild_index();
+
+        let idx = this.index.read().map_err(|e| {
+            McpError::internal_error(format!("lock error: {e}"), None)
+        })?;
+
+        if !idx.entries.iter().any(|e| e.rel_path == old_rel) {
+            return Err(McpError::invalid_params(
+                format!("文件不存在: {old_rel}"),
+                None,
+            ));
+        }
+
+        // (current path, original content, rewritten content, rewritten link count)
+        let mut changes: Vec<(String, String, String, usize)> = Vec::new();
+        for (i, entry) in idx.entries.iter().enumerate() {
+            progress::check()?;
+            progress::report(i, idx.entries.len());
+            let source_new = if entry.rel_path == old_rel {
+                new_rel.as_str()
+            } else {
+                entry.rel_path.as_str()
+            };
+            let content = std::fs::read_to_string(this.config.vault_root.join(&entry.rel_path))
+                .map_err(|e| {
+                    McpError::internal_error(format!("读取文件失败: {e}"), None)
+                })?;
+            let (updated, count) = links::rewrite_links(
+                &idx,
+                &this.config,
+                &content,
+                &entry.rel_path,
+                source_new,
+                &old_rel,
+                &new_rel,
+            );
+            if count > 0 {
+                changes.push((entry.rel_path.clone(), content, updated, count));
+            }
+        }
+        progress::report(idx.entries.len(), idx.entries.len());
+        for (canvas_rel, _) in idx.canvas_refs.get(&old_rel).map(Vec::as_slice).unwrap_or_default() {
+            let content = std::fs::read_to_string(this.config.vault_root.join(canvas_rel))
+                .map_err(|e| {
+                    McpError::internal_error(format!("读取文件失败: {e}"), None)
+                })?;
+            let rewrite = |text: &str| {
+                links::rewrite_links(&idx, &this.config, text, canvas_rel, canvas_rel, &old_rel, &new_rel)
+            };
+            if let Some((updated, count)) = canvas::retarget(&content, &old_rel, &new_rel, rewrite) {
+                changes.push((canvas_rel.clone(), content, updated, count));
+            }
+        }
+        let hidden = links::hidden_incoming(&idx, &this.config, &old_rel);
+        drop(idx);
+        progress::check()?;
+
+        // Referrers the session may read but not write keep their old links.
+        let (changes, read_only): (Vec<_>, Vec<_>) = changes
+            .into_iter()
+            .partition(|(rel, ..)| *rel == old_rel || this.check_write(rel).is_ok());
+        audit::record_count(changes.len());
+
+        let total_links: usize = changes.iter().map(|(_, _, _, n)| n).sum();
+        let mut touched = format!("- `{old_rel}` → `{new_rel}`（移动）\n");
+        for (rel, _, _, count) in &changes {
+            touched.push_str(&format!("- `{rel}`：{count} 处链接\n"));
+        }
+        let mut warning = String::new();
+        if !read_only.is_empty() {
+            warning.push_str(&format!(
+                "\n\n⚠️ 以下 {} 个文件为只读，其中的链接未改写，这些链接现已失效：\n\n",
+                read_only.len()
+            ));
+            for (rel, _, _, count) in &read_only {
+                warning.push_str(&format!("- `{rel}`：{count} 处链接\n"));
+            }
+        }
+        if hidden > 0 {
+            warning.push_str(&format!(
+                "\n\n⚠️ 另有 {hidden} 篇当前会话无权访问的笔记链接到它，其中的链接未改写，这些链接现已失效。\n"
+            ));
+        }
+
+        if params.dry_run {
+            let mut output = format!(
+                "【预览】将移动 `{old_rel}` → `{new_rel}`，并改写 {} 个文件中的 {total_links} 处链接。未写入磁盘。\n\n{touched}{}",
+                changes.len(),
+                warning.replace("现已失效", "将会失效")
+            );
+            if params.diff {
+                for (rel, old, new, _) in &changes {
+                    let target = if *rel == old_rel { new_rel.as_str() } else { rel.as_str() };
+                    output.push('\n');
+                    output.push_str(&render_preview(rel, target, old, new, true));
+                    output.push('\n');
+                }
+            }
+            return Ok(CallToolResult::success(vec![Content::text(output)]));
+        }
+
+        for (rel, old, _, _) in &changes {
+            this.snapshot(rel, old)?;
+        }
+
+        std::fs::create_dir_all(this.vault_path(dir)?).map_err(|e| {
+            McpError::internal_error(format!("创建目录失败: {e}"), None)
+        })?;
+
+        // Rewrite every file in place (the moved note still at its old path)
+        // and rename last, so a failure at any step can put back the
+        // original contents and leave the vault as it was.
+        let mut written: Vec<(PathBuf, &str)> = Vec::new();
+        let moved = (|| {
+            for (rel, old, content, _) in &changes {
+                let path = this.config.vault_root.join(rel);
+                written.push((path.clone(), old));
+                std::fs::write(&path, content)?;
+            }
+            std::fs::rename(&old_path, &new_path)
+        })();
+        if let Err(e) = moved {
+            for (path, old) in written.iter().rev() {
+                let _ = std::fs::write(path, old);
+            }
+            return Err(McpError::internal_error(
+                format!("移动文件失败，已撤销所有改动: {e}"),
+                None,
+            ));
+        }
+
+        this.rebuild_index();
+
+        let mut message = format!("move_note: {old_rel} -> {new_rel}");
+        let mut paths = vec![old_rel.as_str(), new_rel.as_str()];
+        if changes.iter().any(|(rel, ..)| *rel != old_rel) {
+            message.push_str("\n\nRewrote links in:\n");
+        }
+        for (rel, ..) in changes.iter().filter(|(rel, ..)| *rel != old_rel) {
+            message.push_str(&format!("- {rel}\n"));
+            paths.push(rel);
+        }
+        let committed = this.git_commit(&message, &paths);
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "已移动 `{old_rel}` → `{new_rel}`，改写了 {} 个文件中的 {total_links} 处链接。\n\n{touched}{committed}{warning}",
+            changes.len()
+        ))]))
+    }
+
+    #[tool(
+        name = "delete_note",
+        description = "删除 Obsidian 知识库中的笔记。笔记不会被彻底删除，而是带时间戳移入知识库的 .trash/ 回收站（Obsidian 约定），可用 restore_note 恢复。如果仍有其他笔记链接到它，会在结果中列出这些失效链接。设置 dry_run 可先预览。\n\n调用示例：{\"path\": \"tech/docker-guide.md\"}"
+    )]
+    async fn delete_note(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<DeleteNoteParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let rel_path = sandbox::clean(&params.path)?;
+        this.check_write(&rel_path)?;
+        let file_path = this.vault_path(&rel_path)?;
+
+        this.rebuild_index();
+
+        let (incoming, canvases) = {
+            let idx = this.index.read().map_err(|e| {
+                McpError::internal_error(format!("lock error: {e}"), None)
+            })?;
+            if !idx.entries.iter().any(|e| e.rel_path == rel_path) {
+                return Err(McpError::invalid_params(
+                    format!("文件不存在: {rel_path}"),
+                    None,
+                ));
+            }
+            (
+                links::incoming_links(&idx, &this.config.vault_root, &rel_path),
+                idx.canvas_refs.get(&rel_path).cloned().unwrap_or_default(),
+            )
+        };
+        progress::check()?;
+
+        let mut warning = String::new();
+        if !incoming.is_empty() {
+            warning.push_str(&format!(
+                "\n\n⚠️ 以下 {} 篇笔记仍链接到它，这些链接现已失效：\n\n",
+                incoming.len()
+            ));
+            for (rel, count) in &incoming {
+                warning.push_str(&format!("- `{rel}`：{count} 处链接\n"));
+            }
+        }
+        if !canvases.is_empty() {
+            warning.push_str(&format!(
+                "\n\n⚠️ 以下 {} 个画布仍引用它，这些引用现已失效：\n\n",
+                canvases.len()
+            ));
+            for (rel, count) in &canvases {
+                warning.push_str(&format!("- `{rel}`：{count} 处引用\n"));
+            }
+        }
+
+        if params.dry_run {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "【预览】将把 `{rel_path}` 移入回收站 `{TRASH_DIR}/`，未修改磁盘。{}",
+                warning.replace("现已失效", "将会失效")
+            ))]));
+        }
+
+        let content = std::fs::read_to_string(&file_path).map_err(|e| {
+            McpError::internal_error(format!("读取文件失败: {e}"), None)
+        })?;
+        this.snapshot(&rel_path, &content)?;
+
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut trash_rel = format!("{stamp}/{rel_path}");
+        let mut n = 1;
+        while this.config.vault_root.join(TRASH_DIR).join(&trash_rel).exists() {
+            trash_rel = format!("{stamp}-{n}/{rel_path}");
+            n += 1;
+        }
+        let trash_path = this.vault_path(&format!("{TRASH_DIR}/{trash_rel}"))?;
+        if let Some(parent) = trash_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                McpError::internal_error(format!("创建目录失败: {e}"), None)
+            })?;
+        }
+        std::fs::rename(&file_path, &trash_path).map_err(|e| {
+            McpError::internal_error(format!("移动文件失败: {e}"), None)
+        })?;
+
+        this.rebuild_index();
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "已将 `{rel_path}` 移入回收站 `{TRASH_DIR}/{trash_rel}`。可用 restore_note 恢复：{{\"trash_path\": \"{trash_rel}\"}}{warning}{}",
+            this.git_commit(&format!("delete_note: {rel_path}"), &[&rel_path])
+        ))]))
+    }
+
+    #[tool(
+        name = "restore_note",
+        description = "从 .trash/ 回收站恢复笔记到原路径。trash_path 从 list_trash 的结果中获取。如果原路径已存在同名文件则拒绝恢复。设置 dry_run 可先预览。\n\n调用示例：{\"trash_path\": \"20250101-120000/tech/docker-guide.md\"}"
+    )]
+    async fn restore_note(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<RestoreNoteParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let trash_rel = sandbox::clean(&params.trash_path)?;
+        let trash_rel = trash_rel.strip_prefix(&format!("{TRASH_DIR}/")).unwrap_or(&trash_rel);
+        let trash_root = this.config.vault_root.join(TRASH_DIR);
+        let src = this.vault_path(&format!("{TRASH_DIR}/{trash_rel}"))?;
+        if !src.is_file() {
+            return Err(McpError::invalid_params(
+                format!("回收站中不存在: {trash_rel}"),
+                None,
+            ));
+        }
+
+        let (original, _) = trash_origin(trash_rel);
+        let original = sandbox::clean(&original)?;
+        this.check_write(&original)?;
+        let dest = this.vault_path(&original)?;
+        if dest.exists() {
+            return Err(McpError::invalid_params(
+                format!("目标文件已存在: {original}"),
+                None,
+            ));
+        }
+
+        if params.dry_run {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "【预览】将把 `{TRASH_DIR}/{trash_rel}` 恢复到 `{original}`，未修改磁盘。"
+            ))]));
+        }
+
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                McpError::internal_error(format!("创建目录失败: {e}"), None)
+            })?;
+        }
+        std::fs::rename(&src, &dest).map_err(|e| {
+            McpError::internal_error(format!("移动文件失败: {e}"), None)
+        })?;
+
+        // drop the now-empty timestamp folders
+        let mut dir = src.parent();
+        while let Some(d) = dir {
+            if d == trash_root || std::fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+
+        this.rebuild_index();
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "已从回收站恢复 `{original}`。{}",
+            this.git_commit(&format!("restore_note: {original}"), &[&original])
+        ))]))
+    }
+
+    #[tool(
+        name = "list_trash",
+        description = "列出 .trash/ 回收站中的所有文件及其原路径和删除时间，用于配合 restore_note 恢复笔记。无需参数。"
+    )]
+    async fn list_trash(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<VaultParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let trash_root = this.config.vault_root.join(TRASH_DIR);
+
+        let mut items: Vec<(String, String, Option<chrono::NaiveDateTime>)> = WalkDir::new(&trash_root)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| {
+                let trash_rel = e
+                    .path()
+                    .strip_prefix(&trash_root)
+                    .unwrap_or(e.path())
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                let (original, deleted) = trash_origin(&trash_rel);
+                (trash_rel, original, deleted)
+            })
+            .filter(|(_, original, _)| this.check_read(original).is_ok())
+            .collect();
+        audit::record_count(items.len());
+
+        if items.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(
+                "回收站为空。".to_string(),
+            )]));
+        }
+
+        items.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+        let mut output = format!("回收站中共 {} 个文件：\n\n", items.len());
+        output.push_str("| 回收站路径 | 原路径 | 删除时间 |\n");
+        output.push_str("|------------|--------|----------|\n");
+        for (trash_rel, original, deleted) in &items {
+            let deleted = deleted
+                .map(|ts| ts.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| "-".to_string());
+            output.push_str(&format!("| `{trash_rel}` | `{original}` | {deleted} |\n"));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        name = "list_attachments",
+        description = "列出知识库中的附件（图片、PDF、Canvas 等非 Markdown 文件）及其类型、大小和引用它们的笔记。可按目录过滤、只看某篇笔记引用的附件，或用 unused=true 找出没有任何笔记引用的附件。\n\n调用示例：{} | {\"note\": \"tech/docker-guide.md\"} | {\"unused\": true}"
+    )]
+    async fn list_attachments(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<ListAttachmentsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let directory = params.directory.as_deref().map(sandbox::clean).transpose()?;
+        let note = params.note.as_deref().map(sandbox::clean).transpose()?;
+        if let Some(note) = &note {
+            this.check_read(note)?;
+        }
+
+        this.rebuild_index();
+        progress::check()?;
+        let idx = this.index.read().map_err(|e| {
+            McpError::internal_error(format!("lock error: {e}"), None)
+        })?;
+        if let Some(note) = &note {
+            if !idx.entries.iter().any(|e| e.rel_path == *note) {
+                return Err(McpError::invalid_params(format!("笔记不存在: {note}"), None));
+            }
+        }
+        let mut items: Vec<&Attachment> = idx
+            .attachments
+            .iter()
+            .filter(|a| directory.as_ref().is_none_or(|d| a.rel_path.starts_with(&format!("{d}/"))))
+            .filter(|a| note.as_ref().is_none_or(|n| a.referenced_by.contains(n)))
+            // a canvas is a document of its own, not something notes embed
+            .filter(|a| !params.unused || (a.referenced_by.is_empty() && !a.rel_path.ends_with(".canvas")))
+            .collect();
+        items.sort_by(|a, b| a.rel_path.cmp(&b.rel_path));
+        audit::record_count(items.len());
+
+        if items.is_empty() {
+            let text = if params.unused { "没有未被引用的附件。" } else { "未找到附件。" };
+            return Ok(CallToolResult::success(vec![Content::text(text.to_string())]));
+        }
+
+        let total: u64 = items.iter().map(|a| a.size).sum();
+        let mut output = format!(
+            "共 {} 个{}附件，合计 {}：\n\n| 路径 | 类型 | 大小 | 引用笔记 |\n|------|------|------|----------|\n",
+            items.len(),
+            if params.unused { "未被引用的" } else { "" },
+            human_size(total),
+        );
+        for a in &items {
+            let refs = if a.referenced_by.is_empty() {
+                "—".to_string()
+            } else {
+                a.referenced_by.iter().map(|r| format!("`{r}`")).collect::<Vec<_>>().join(", ")
+            };
+            output.push_str(&format!("| `{}` | {} | {} | {refs} |\n", a.rel_path, a.mime, human_size(a.size)));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        name = "add_attachment",
+        description = "把 Base64 编码的文件保存为附件，存放位置遵循 Obsidian 的附件设置（.obsidian/app.json），并返回可直接写入笔记的嵌入语法。重名时自动追加序号，与 Obsidian 一致。设置 dry_run 可先预览。\n\n调用示例：{\"filename\": \"nginx-architecture.png\", \"content_base64\": \"iVBORw0KGgo...\", \"note\": \"tech/nginx-guide.md\"}"
+    )]
+    async fn add_attachment(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<AddAttachmentParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let filename = params.filename.trim();
+        if filename.contains('/') || filename.contains('\\') {
+            return Err(McpError::invalid_params(
+                format!("filename 只能是文件名，不能包含目录：{filename}"),
+                None,
+            ));
+        }
+        let filename = sandbox::clean(filename)?;
+        let Some((stem, ext)) = filename.rsplit_once('.').filter(|(stem, ext)| !stem.is_empty() && !ext.is_empty()) else {
+            return Err(McpError::invalid_params(format!("文件名缺少扩展名：{filename}"), None));
+        };
+        if ext.eq_ignore_ascii_case("md") {
+            return Err(McpError::invalid_params("Markdown 笔记请使用 write_note 写入", None));
+        }
+        let note = params.note.as_deref().map(sandbox::clean).transpose()?;
+        if let Some(note) = &note {
+            this.check_read(note)?;
+        }
+
+        let data = params.content_base64.trim();
+        let data = match data.strip_prefix("data:") {
+            Some(url) => url.split_once(',').map_or(url, |(_, payload)| payload),
+            None => data,
+        };
+        let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&data)
+            .map_err(|e| McpError::invalid_params(format!("content_base64 不是有效的 Base64：{e}"), None))?;
+        if bytes.len() > MAX_ATTACHMENT_BYTES {
+            return Err(McpError::invalid_params(
+                format!("附件过大：{}，上限 {}", human_size(bytes.len() as u64), human_size(MAX_ATTACHMENT_BYTES as u64)),
+                None,
+            ));
+        }
+
+        let folder = this.config.attachment_folder(note.as_deref())?;
+        let in_folder = |name: &str| if folder.is_empty() { name.to_string() } else { format!("{folder}/{name}") };
+        // like Obsidian: "image.png", "image 1.png", "image 2.png", ...
+        let mut rel_path = in_folder(&filename);
+        let mut n = 1;
+        while this.vault_path(&rel_path)?.exists() {
+            rel_path = in_folder(&format!("{stem} {n}.{ext}"));
+            n += 1;
+        }
+        this.check_write(&rel_path)?;
+        let file_path = this.vault_path(&rel_path)?;
+
+        // the embed can use the bare name if no other attachment has it
+        this.rebuild_index();
+        progress::check()?;
+        let name = rel_path.rsplit('/').next().unwrap_or(&rel_path);
+        let unique = this.index.read().is_ok_and(|idx| {
+            !idx.attachments
+                .iter()
+                .any(|a| a.rel_path.rsplit('/').next().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+        });
+        let target = match (this.config.obsidian.link_format, &note) {
+            (Some(obsidian::LinkFormat::Absolute), _) => rel_path.clone(),
+            (Some(obsidian::LinkFormat::Relative), Some(note)) => links::relative_path(note, &rel_path),
+            (Some(obsidian::LinkFormat::Relative), None) => rel_path.clone(),
+            _ if unique => name.to_string(),
+            _ => rel_path.clone(),
+        };
+        let embed = fenced("markdown", &format!("![[{target}]]"));
+        let kind = resources::mime_for(&file_path);
+        let size = human_size(bytes.len() as u64);
+
+        if params.dry_run {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "【预览】将保存附件 `{rel_path}`（{kind}，{size}），未写入磁盘。\n\n在笔记中嵌入：\n\n{embed}"
+            ))]));
+        }
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                McpError::internal_error(format!("创建目录失败: {e}"), None)
+            })?;
+        }
+        std::fs::write(&file_path, &bytes).map_err(|e| {
+            McpError::internal_error(format!("写入附件失败: {e}"), None)
+        })?;
+        this.rebuild_index();
+        let committed = this.git_commit(&format!("add_attachment: {rel_path}"), &[&rel_path]);
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "✅ 已保存附件 `{rel_path}`（{kind}，{size}）\n\n在笔记中嵌入：\n\n{embed}{committed}"
+        ))]))
     }
 
     #[tool(
-        name = "read_note",
-        description = "读取 Obsidian 知识库中某篇笔记的完整内容。传入笔记的相对路径（从 query_note 搜索结果或 note_index_tree 文件树中获取）。典型流程：先用 query_note 搜索找到目标笔记路径，再用 read_note 读取内容。\n\n调用示例：{\"path\": \"tech/docker-guide.md\"} | {\"path\": \"ai/mcp-development.md\"}"
+        name = "read_canvas",
+        description = "读取 Obsidian 画布（.canvas，JSON Canvas 格式），以可读大纲返回：节点按分组嵌套、按位置从上到下排列，文本节点给出内容，文件节点给出引用的笔记，最后列出连线。节点 id 可用于 write_canvas 修改。不符合规范之处会一并列出。\n\n调用示例：{\"path\": \"projects/roadmap.canvas\"}"
     )]
-    async fn read_note(
+    async fn read_canvas(
         &self,
-        #[tool(aggr)] Parameters(params): Parameters<ReadNoteParams>,
+        #[tool(aggr)] Parameters(params): Parameters<ReadCanvasParams>,
     ) -> Result<CallToolResult, McpError> {
-        let rel_path = params.path.trim().trim_start_matches('/');
-        if rel_path.is_empty() {
-            return Err(McpError::invalid_params("路径不能为空", None));
-        }
-
-        if rel_path.contains("..") {
-            return Err(McpError::invalid_params("路径不能包含 ..", None));
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let rel_path = sandbox::clean(&params.path)?;
+        if !rel_path.ends_with(".canvas") {
+            return Err(McpError::invalid_params(format!("不是画布文件（.canvas）：{rel_path}"), None));
         }
-
-        let file_path = self.vault_root.join(rel_path);
-        if !file_path.exists() {
-            return Err(McpError::invalid_params(
-                format!("文件不存在: {rel_path}"),
-                None,
-            ));
+        this.check_read(&rel_path)?;
+        let file_path = this.vault_path(&rel_path)?;
+        if !file_path.is_file() {
+            return Err(McpError::invalid_params(format!("文件不存在: {rel_path}"), None));
         }
-
         let content = std::fs::read_to_string(&file_path).map_err(|e| {
             McpError::internal_error(format!("读取文件失败: {e}"), None)
         })?;
-
-        Ok(CallToolResult::success(vec![Content::text(content)]))
+        let parsed = canvas::Canvas::parse(&content)
+            .map_err(|e| McpError::invalid_params(format!("{rel_path} {e}"), None))?;
+        audit::record_count(parsed.nodes.len());
+
+        let mut output = parsed.outline(&rel_path);
+        let problems = this.canvas_problems(&parsed);
+        if !problems.is_empty() {
+            output.push_str("\n### ⚠️ 问题\n\n");
+            for p in &problems {
+                output.push_str(&format!("- {p}\n"));
+            }
+        }
+        Ok(CallToolResult::success(vec![Content::text(output)]))
     }
 
     #[tool(
-        name = "write_note",
-        description = "向 Obsidian 知识库写入笔记。自动生成 Frontmatter 头部。如果文件已存在则追加内容并更新 updated 日期。\n\n⚠️ 如果你还没有调用过 write_note_tips 了解本知识库的操作规范，请先调用它。\n\n所有 6 个参数必填。调用示例：{\"directory\": \"tech\", \"filename\": \"nginx-guide\", \"tags\": [\"nginx\"], \"aliases\": [\"Nginx 指南\"], \"status\": \"active\", \"content\": \"> [!abstract] 概述\\n> 内容\\n\\n## 相关笔记\\n\\n- [[docker-guide]]\"}"
+        name = "write_canvas",
+        description = "创建或修改 Obsidian 画布（.canvas，JSON Canvas 格式）。画布不存在时创建；已存在时按 id 合并：nodes / edges 中 id 已存在的项整体替换，新 id 追加，remove 删除节点或连线（删除节点时连到它的连线一并删除），replace=true 则丢弃原有内容。写入前按规范校验：id 唯一、连线两端节点存在、颜色为 \"1\"–\"6\" 或 #RRGGBB、宽高为正数。画布中其他未改动的字段原样保留。设置 dry_run 可先预览 diff。\n\n调用示例：{\"path\": \"projects/roadmap.canvas\", \"nodes\": [{\"id\": \"n1\", \"type\": \"text\", \"text\": \"# 目标\", \"x\": 0, \"y\": 0, \"width\": 250, \"height\": 120}, {\"id\": \"n2\", \"type\": \"file\", \"file\": \"tech/docker-guide.md\", \"x\": 300, \"y\": 0, \"width\": 400, \"height\": 400}], \"edges\": [{\"id\": \"e1\", \"fromNode\": \"n1\", \"toNode\": \"n2\", \"label\": \"参考\"}]}"
     )]
-    async fn write_note(
+    async fn write_canvas(
         &self,
-        #[tool(aggr)] Parameters(params): Parameters<WriteNoteParams>,
+        #[tool(aggr)] Parameters(params): Parameters<WriteCanvasParams>,
     ) -> Result<CallToolResult, McpError> {
-        let dir = params.directory.trim().trim_matches('/');
-        if !VALID_DIRS.contains(&dir) {
-            return Err(McpError::invalid_params(
-                format!(
-                    "无效的目录 '{dir}'，必须是以下之一：{}",
-                    VALID_DIRS.join(", ")
-                ),
-                None,
-            ));
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let rel_path = sandbox::clean(&params.path)?;
+        let rel_path = if rel_path.ends_with(".canvas") { rel_path } else { format!("{rel_path}.canvas") };
+        this.check_write(&rel_path)?;
+        let file_path = this.vault_path(&rel_path)?;
+
+        let existing = if file_path.is_file() {
+            Some(std::fs::read_to_string(&file_path).map_err(|e| {
+                McpError::internal_error(format!("读取文件失败: {e}"), None)
+            })?)
+        } else {
+            None
+        };
+        if existing.is_none() && params.nodes.is_empty() && params.edges.is_empty() {
+            return Err(McpError::invalid_params(format!("画布不存在：{rel_path}，创建时需提供 nodes"), None));
         }
 
-        let filename = params.filename.trim().trim_end_matches(".md");
-        if filename.is_empty() {
-            return Err(McpError::invalid_params("文件名不能为空", None));
+        // merge on the raw JSON so fields beyond the spec survive
+        let mut value = match existing.as_deref().map(str::trim) {
+            Some(text) if !text.is_empty() && !params.replace => serde_json::from_str::<serde_json::Value>(text)
+                .ok()
+                .filter(serde_json::Value::is_object)
+                .ok_or_else(|| McpError::invalid_params(format!("{rel_path} 不是有效的 JSON Canvas，可用 replace=true 覆盖"), None))?,
+            _ => serde_json::json!({}),
+        };
+        let map = value.as_object_mut().expect("checked above");
+        let mut stats = [0usize; 3]; // added, replaced, removed
+        for (key, items) in [
+            ("nodes", params.nodes.iter().map(serde_json::to_value).collect::<Result<Vec<_>, _>>()),
+            ("edges", params.edges.iter().map(serde_json::to_value).collect::<Result<Vec<_>, _>>()),
+        ] {
+            let items = items.map_err(|e| McpError::internal_error(format!("序列化失败: {e}"), None))?;
+            let list = map
+                .entry(key)
+                .or_insert_with(|| serde_json::Value::Array(Vec::new()))
+                .as_array_mut()
+                .ok_or_else(|| McpError::invalid_params(format!("{rel_path} 的 {key} 不是数组"), None))?;
+            for item in items {
+                match list.iter_mut().find(|v| v.get("id") == item.get("id")) {
+                    Some(slot) => {
+                        *slot = item;
+                        stats[1] += 1;
+                    }
+                    None => {
+                        list.push(item);
+                        stats[0] += 1;
+                    }
+                }
+            }
         }
-        if filename.contains(' ') || filename.chars().any(|c| c > '\x7f') {
-            return Err(McpError::invalid_params(
-                "文件名必须是英文小写+短横线，不能包含空格或中文",
-                None,
-            ));
+        if !params.remove.is_empty() {
+            let id_of = |v: &serde_json::Value| v.get("id").and_then(serde_json::Value::as_str).map(str::to_string);
+            let remove: HashSet<&str> = params.remove.iter().map(String::as_str).collect();
+            let mut found: HashSet<String> = HashSet::new();
+            let before = canvas::item_count(map);
+            if let Some(nodes) = map.get_mut("nodes").and_then(serde_json::Value::as_array_mut) {
+                nodes.retain(|n| {
+                    let hit = id_of(n).filter(|id| remove.contains(id.as_str()));
+                    found.extend(hit.clone());
+                    hit.is_none()
+                });
+            }
+            if let Some(edges) = map.get_mut("edges").and_then(serde_json::Value::as_array_mut) {
+                edges.retain(|e| {
+                    let hit = id_of(e).filter(|id| remove.contains(id.as_str()));
+                    let dangling = ["fromNode", "toNode"].iter().any(|end| {
+                        e.get(*end).and_then(serde_json::Value::as_str).is_some_and(|n| found.contains(n))
+                    });
+                    let gone = hit.is_some() || dangling;
+                    found.extend(hit);
+                    !gone
+                });
+            }
+            let missing: Vec<&str> = params.remove.iter().map(String::as_str).filter(|id| !found.contains(*id)).collect();
+            if !missing.is_empty() {
+                return Err(McpError::invalid_params(format!("画布中没有这些 id：{}", missing.join(", ")), None));
+            }
+            stats[2] = before - canvas::item_count(map);
         }
-        if filename != filename.to_lowercase() {
-            return Err(McpError::invalid_params("文件名必须全部小写", None));
+        for key in ["nodes", "edges"] {
+            map.entry(key).or_insert_with(|| serde_json::Value::Array(Vec::new()));
         }
 
-        let valid_statuses = ["active", "archived", "draft"];
-        if !valid_statuses.contains(&params.status.as_str()) {
+        let parsed: canvas::Canvas = serde_json::from_value(value.clone())
+            .map_err(|e| McpError::invalid_params(format!("修改后的画布不符合 JSON Canvas 规范：{e}"), None))?;
+        let errors = parsed.validate();
+        if !errors.is_empty() {
             return Err(McpError::invalid_params(
-                format!(
-                    "无效的状态 '{}'，必须是：active, archived, draft",
-                    params.status
-                ),
+                format!("画布不符合 JSON Canvas 规范：\n- {}", errors.join("\n- ")),
                 None,
             ));
         }
+        for node in &parsed.nodes {
+            if let canvas::NodeKind::File { file, .. } = &node.kind {
+                sandbox::clean(file)?;
+            }
+        }
+        let warnings = this.canvas_problems(&parsed);
+        let content = canvas::to_string(&value);
+
+        let [added, replaced, removed] = stats;
+        let mut summary = format!(
+            "{} 个节点、{} 条连线（新增 {added}，替换 {replaced}，删除 {removed}）",
+            parsed.nodes.len(),
+            parsed.edges.len()
+        );
+        for w in &warnings {
+            summary.push_str(&format!("\n- ⚠️ {w}"));
+        }
+        let action = if existing.is_some() { "修改画布" } else { "创建画布" };
 
-        let target_dir = self.vault_root.join(dir);
-        if !target_dir.exists() {
-            std::fs::create_dir_all(&target_dir).map_err(|e| {
+        if params.dry_run {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "【预览】将{action} `{rel_path}`，未写入磁盘。\n\n{summary}\n\n{}",
+                render_preview(&rel_path, &rel_path, existing.as_deref().unwrap_or(""), &content, true)
+            ))]));
+        }
+
+        if let Some(existing) = &existing {
+            this.snapshot(&rel_path, existing)?;
+        }
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
                 McpError::internal_error(format!("创建目录失败: {e}"), None)
             })?;
         }
+        std::fs::write(&file_path, &content).map_err(|e| {
+            McpError::internal_error(format!("写入文件失败: {e}"), None)
+        })?;
+        this.rebuild_index();
+        let committed = this.git_commit(&format!("write_canvas: {rel_path}"), &[&rel_path]);
 
-        let file_path = target_dir.join(format!("{filename}.md"));
-        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "✅ 已{action} `{rel_path}`：{summary}{committed}"
+        ))]))
+    }
 
-        if file_path.exists() {
-            let existing = std::fs::read_to_string(&file_path).map_err(|e| {
-                McpError::internal_error(format!("读取文件失败: {e}"), None)
-            })?;
+    #[tool(
+        name = "note_history",
+        description = "查看笔记的历史版本。服务每次修改、移动链接或删除笔记前都会自动保存旧内容。只传 path 列出所有版本；传 version 返回该版本完整内容；再加 diff 返回该版本到当前内容的 unified diff。\n\n调用示例：{\"path\": \"tech/docker-guide.md\"} | {\"path\": \"tech/docker-guide.md\", \"version\": \"20250101-120000-000\", \"diff\": true}"
+    )]
+    async fn note_history(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<NoteHistoryParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let rel_path = sandbox::clean(&params.path)?;
+        this.check_read(&rel_path)?;
+
+        let Some(version) = params.version.as_deref().map(str::trim) else {
+            let versions = history::list_versions(&this.config.vault_root, &rel_path);
+            audit::record_count(versions.len());
+            if versions.is_empty() {
+                return Ok(CallToolResult::success(vec![Content::text(format!(
+                    "`{rel_path}` 没有历史版本。"
+                ))]));
+            }
 
-            let updated_content = if existing.starts_with("---") {
-                if let Some(end_pos) = existing[3..].find("\n---") {
-                    let fm = &existing[..end_pos + 3 + 4];
-                    let body = &existing[end_pos + 3 + 4..];
-                    let updated_fm = update_frontmatter_date(fm, &today);
-                    format!("{updated_fm}{body}\n\n{}", params.content)
-                } else {
-                    format!("{existing}\n\n{}", params.content)
-                }
-            } else {
-                format!("{existing}\n\n{}", params.content)
-            };
+            let mut output = format!("`{rel_path}` 共 {} 个历史版本（最新在前）：\n\n", versions.len());
+            output.push_str("| 版本 | 保存时间 | 大小 |\n");
+            output.push_str("|------|----------|------|\n");
+            for v in &versions {
+                output.push_str(&format!(
+                    "| `{}` | {} | {} B |\n",
+                    v.id,
+                    v.time.format("%Y-%m-%d %H:%M:%S"),
+                    v.size
+                ));
+            }
+            return Ok(CallToolResult::success(vec![Content::text(output)]));
+        };
 
-            std::fs::write(&file_path, &updated_content).map_err(|e| {
-                McpError::internal_error(format!("写入文件失败: {e}"), None)
-            })?;
+        let old = history::read_version(&this.config.vault_root, &rel_path, version).map_err(|_| {
+            McpError::invalid_params(format!("版本不存在: {rel_path}@{version}"), None)
+        })?;
 
-            self.rebuild_index();
+        if !params.diff {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "`{rel_path}` 版本 `{version}` 的内容：\n\n{}",
+                fenced("markdown", &old)
+            ))]));
+        }
+
+        let current = std::fs::read_to_string(this.vault_path(&rel_path)?).unwrap_or_default();
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "`{rel_path}` 版本 `{version}` → 当前内容：\n\n{}",
+            render_preview(&format!("{rel_path}@{version}"), &rel_path, &old, &current, true)
+        ))]))
+    }
+
+    #[tool(
+        name = "restore_note_version",
+        description = "将笔记回滚到某个历史版本（版本号从 note_history 获取）。回滚前会先把当前内容保存为新的历史版本，因此回滚本身也可撤销。已删除的笔记也可以用此工具按原路径重建。设置 dry_run 可先查看 diff。\n\n调用示例：{\"path\": \"tech/docker-guide.md\", \"version\": \"20250101-120000-000\"}"
+    )]
+    async fn restore_note_version(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<RestoreNoteVersionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let rel_path = sandbox::clean(&params.path)?;
+        this.check_write(&rel_path)?;
+        let version = params.version.trim();
+
+        let restored = history::read_version(&this.config.vault_root, &rel_path, version).map_err(|_| {
+            McpError::invalid_params(format!("版本不存在: {rel_path}@{version}"), None)
+        })?;
+
+        let file_path = this.vault_path(&rel_path)?;
+        let current = if file_path.exists() {
+            Some(std::fs::read_to_string(&file_path).map_err(|e| {
+                McpError::internal_error(format!("读取文件失败: {e}"), None)
+            })?)
+        } else {
+            None
+        };
 
+        if params.dry_run {
             return Ok(CallToolResult::success(vec![Content::text(format!(
-                "已追加内容到 `{dir}/{filename}.md`，updated 日期已更新为 {today}。"
+                "【预览】将把 `{rel_path}` 回滚到版本 `{version}`，未写入磁盘。\n\n{}",
+                render_preview(&rel_path, &rel_path, current.as_deref().unwrap_or(""), &restored, true)
             ))]));
         }
 
-        let mut frontmatter = String::from("---\ntags:\n");
-        for tag in &params.tags {
-            frontmatter.push_str(&format!("  - {tag}\n"));
+        if let Some(current) = &current {
+            this.snapshot(&rel_path, current)?;
         }
-        frontmatter.push_str("aliases:\n");
-        for alias in &params.aliases {
-            frontmatter.push_str(&format!("  - {alias}\n"));
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                McpError::internal_error(format!("创建目录失败: {e}"), None)
+            })?;
         }
-        frontmatter.push_str(&format!("created: {today}\n"));
-        frontmatter.push_str(&format!("updated: {today}\n"));
-        frontmatter.push_str(&format!("status: {}\n", params.status));
-        frontmatter.push_str("---\n\n");
+        std::fs::write(&file_path, &restored).map_err(|e| {
+            McpError::internal_error(format!("写入文件失败: {e}"), None)
+        })?;
 
-        let full_content = format!("{frontmatter}{}", params.content);
+        this.rebuild_index();
 
-        std::fs::write(&file_path, &full_content).map_err(|e| {
-            McpError::internal_error(format!("写入文件失败: {e}"), None)
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "已将 `{rel_path}` 回滚到版本 `{version}`。回滚前的内容已保存为新的历史版本。{}",
+            this.git_commit(&format!("restore_note_version: {rel_path} @ {version}"), &[&rel_path])
+        ))]))
+    }
+
+    #[tool(
+        name = "vault_log",
+        description = "查看知识库的 git 提交历史（知识库需位于 git 仓库中），只包含修改过知识库内文件的提交。传 path 时只列出修改过该笔记的提交。\n\n调用示例：{} | {\"path\": \"tech/docker-guide.md\", \"limit\": 10}"
+    )]
+    async fn vault_log(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<VaultLogParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let rel_path = params
+            .path
+            .as_deref()
+            .filter(|p| !p.trim().is_empty())
+            .map(sandbox::clean)
+            .transpose()?;
+        match rel_path.as_deref() {
+            Some(p) => this.check_read(p)?,
+            // commit summaries name notes anywhere in the vault
+            None if this.scope.is_restricted() => {
+                return Err(McpError::invalid_request(
+                    format!("权限不足：令牌 '{}' 只能查看指定笔记的历史，请提供 path", this.scope.name),
+                    None,
+                ));
+            }
+            None => {}
+        }
+
+        let commits = git::log(&this.config.vault_root, rel_path.as_deref(), params.limit.unwrap_or(20))
+            .map_err(|e| McpError::invalid_params(format!("读取 git 历史失败: {}", e.message()), None))?;
+        audit::record_count(commits.len());
+
+        if commits.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(
+                "没有找到提交记录。".to_string(),
+            )]));
+        }
+
+        let mut output = match &rel_path {
+            Some(p) => format!("`{p}` 的提交历史（{} 条，最新在前）：\n\n", commits.len()),
+            None => format!("知识库提交历史（{} 条，最新在前）：\n\n", commits.len()),
+        };
+        output.push_str("| 提交 | 时间 | 作者 | 说明 |\n");
+        output.push_str("|------|------|------|------|\n");
+        for c in &commits {
+            output.push_str(&format!(
+                "| `{}` | {} | {} | {} |\n",
+                c.id,
+                c.time.format("%Y-%m-%d %H:%M"),
+                c.author,
+                c.summary.replace('|', "\\|"),
+            ));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        name = "note_diff",
+        description = "比较笔记在两个 git 版本之间的差异，返回 unified diff。from 默认 HEAD，不传 to 时与当前磁盘内容比较。\n\n调用示例：{\"path\": \"tech/docker-guide.md\"} | {\"path\": \"tech/docker-guide.md\", \"from\": \"HEAD~3\", \"to\": \"HEAD\"}"
+    )]
+    async fn note_diff(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<NoteDiffParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let rel_path = sandbox::clean(&params.path)?;
+        this.check_read(&rel_path)?;
+
+        let git_err = |e: git2::Error| {
+            McpError::invalid_params(format!("读取 git 版本失败: {}", e.message()), None)
+        };
+        let from = params.from.as_deref().map(str::trim).unwrap_or("HEAD");
+        let old = git::read_at(&this.config.vault_root, &rel_path, from).map_err(git_err)?;
+        let (to_label, new) = match params.to.as_deref().map(str::trim) {
+            Some(to) => (to.to_string(), git::read_at(&this.config.vault_root, &rel_path, to).map_err(git_err)?),
+            None => (
+                "工作区".to_string(),
+                std::fs::read_to_string(this.vault_path(&rel_path)?).ok(),
+            ),
+        };
+
+        if old.is_none() && new.is_none() {
+            return Err(McpError::invalid_params(
+                format!("`{rel_path}` 在 {from} 和 {to_label} 中都不存在"),
+                None,
+            ));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "`{rel_path}`：{from} → {to_label}\n\n{}",
+            render_preview(
+                &format!("{rel_path}@{from}"),
+                &format!("{rel_path}@{to_label}"),
+                old.as_deref().unwrap_or(""),
+                new.as_deref().unwrap_or(""),
+                true,
+            )
+        ))]))
+    }
+
+    #[tool(
+        name = "note_blame",
+        description = "查看笔记每一行最后一次被哪个 git 提交修改（git blame），用于了解笔记的演变过程。rev 默认 HEAD。\n\n调用示例：{\"path\": \"tech/docker-guide.md\"}"
+    )]
+    async fn note_blame(
+        &self,
+        #[tool(aggr)] Parameters(params): Parameters<NoteBlameParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let this = &self.in_vault(params.vault.as_deref())?;
+        let rel_path = sandbox::clean(&params.path)?;
+        this.check_read(&rel_path)?;
+        let rev = params.rev.as_deref().map(str::trim).unwrap_or("HEAD");
+
+        let lines = git::blame(&this.config.vault_root, &rel_path, rev).map_err(|e| {
+            McpError::invalid_params(format!("git blame 失败: {}", e.message()), None)
         })?;
 
-        self.rebuild_index();
+        let width = lines.iter().map(|l| l.author.chars().count()).max().unwrap_or(0);
+        let body = lines
+            .iter()
+            .enumerate()
+            .map(|(i, l)| {
+                format!(
+                    "{} {} {:<width$} {:>4}| {}",
+                    l.id,
+                    l.time.format("%Y-%m-%d"),
+                    l.author,
+                    i + 1,
+                    l.line,
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
 
         Ok(CallToolResult::success(vec![Content::text(format!(
-            "已创建笔记 `{dir}/{filename}.md`。"
+            "`{rel_path}` @ {rev}：\n\n{}",
+            fenced("", &body)
         ))]))
     }
 }
@@ -667,28 +3027,256 @@ impl ObsidianMcp {
     tool_box!(ObsidianMcp {
         note_index_tree,
         write_note_tips,
+        list_vaults,
         query_note,
         read_note,
-        write_note
+        read_notes,
+        note_outline,
+        write_note,
+        move_note,
+        delete_note,
+        restore_note,
+        list_trash,
+        list_attachments,
+        add_attachment,
+        read_canvas,
+        write_canvas,
+        note_history,
+        restore_note_version,
+        vault_log,
+        note_diff,
+        note_blame
     });
 }
 
 impl ServerHandler for ObsidianMcp {
-    tool_box!(@derive);
+    async fn list_tools(
+        &self,
+        _: PaginatedRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult {
+            next_cursor: None,
+            tools: Self::tool_box()
+                .list()
+                .into_iter()
+                .filter(|t| self.tools.allows(&t.name))
+                .collect(),
+        })
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let tool = request.name.to_string();
+        if !self.tools.allows(&tool) {
+            let reason = if self.tools.is_read_only() && toolset::MUTATING_TOOLS.contains(&tool.as_str()) {
+                "服务以只读模式运行"
+            } else {
+                "未启用或不存在"
+            };
+            return Err(McpError::invalid_params(format!("工具 '{tool}' 不可用：{reason}"), None));
+        }
+        let vault = request
+            .arguments
+            .as_ref()
+            .and_then(|a| a.get("vault"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        let config = match self.in_vault(vault.as_deref()) {
+            Ok(view) => view.config,
+            Err(_) => self.config.clone(),
+        };
+        let params = audit::summarize_params(request.arguments.as_ref(), &config.secrets);
+        let peer = context.peer.clone();
+        let started = std::time::Instant::now();
+        let call = rmcp::handler::server::tool::ToolCallContext::new(self, request, context.clone());
+        let call = progress::run(&self.progress_tokens, &context, Self::tool_box().call(call));
+        let (result, count) = audit::with_count(call).await;
+        let result = result.and_then(|r| self.guard_secrets(vault.as_deref(), r));
+
+        let client = peer.peer_info().client_info.name.clone();
+        let event = audit::ToolEvent::from_result(
+            &client,
+            &self.scope.name,
+            &tool,
+            params,
+            started.elapsed(),
+            count,
+            &result,
+        );
+        self.log_tool_call(&peer, &event);
+        result
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        *self
+            .log_level
+            .write()
+            .map_err(|e| McpError::internal_error(format!("lock error: {e}"), None))? = Some(request.level);
+        Ok(())
+    }
+
+    async fn list_resources(
+        &self,
+        request: PaginatedRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        progress::run(&self.progress_tokens, &context, async {
+            self.rebuild_index();
+            progress::check()?;
+            let idx = self.index.read().map_err(|e| {
+                McpError::internal_error(format!("lock error: {e}"), None)
+            })?;
+            let cursor = request.as_ref().and_then(|r| r.cursor.as_deref());
+            resources::list(&idx, &self.config, &self.scope, cursor)
+        })
+        .await
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _: PaginatedRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListResourceTemplatesResult, McpError> {
+        Ok(ListResourceTemplatesResult {
+            next_cursor: None,
+            resource_templates: resources::templates(),
+        })
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        progress::run(&self.progress_tokens, &context, async {
+            self.rebuild_index();
+            progress::check()?;
+            let idx = self.index.read().map_err(|e| {
+                McpError::internal_error(format!("lock error: {e}"), None)
+            })?;
+            resources::read(&idx, &self.config, &self.scope, &request.uri)
+        })
+        .await
+    }
+
+    async fn list_prompts(
+        &self,
+        _: PaginatedRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        Ok(ListPromptsResult {
+            next_cursor: None,
+            prompts: prompts::list(&self.config),
+        })
+    }
+
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        progress::run(&self.progress_tokens, &context, async {
+            self.rebuild_index();
+            progress::check()?;
+            let idx = self.index.read().map_err(|e| {
+                McpError::internal_error(format!("lock error: {e}"), None)
+            })?;
+            prompts::get(&idx, &self.config, &self.scope, self.can_write(), &request)
+        })
+        .await
+    }
+
+    async fn complete(
+        &self,
+        request: CompleteRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<CompleteResult, McpError> {
+        self.rebuild_index();
+        let idx = self.index.read().map_err(|e| {
+            McpError::internal_error(format!("lock error: {e}"), None)
+        })?;
+        Ok(completion::complete(&idx, &self.config, &self.scope, &request))
+    }
+
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let uri = request.uri;
+        if ![resources::NOTE_PREFIX, resources::TAG_PREFIX, resources::DIR_PREFIX]
+            .iter()
+            .any(|p| uri.starts_with(p))
+        {
+            return Err(McpError::invalid_params(format!("不支持的资源 URI: {uri}"), None));
+        }
+        let mut subs = self
+            .subscriptions
+            .write()
+            .map_err(|e| McpError::internal_error(format!("lock error: {e}"), None))?;
+        if subs.is_empty() {
+            self.start_polling();
+        }
+        subs.insert(uri);
+        Ok(())
+    }
+
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let mut subs = self
+            .subscriptions
+            .write()
+            .map_err(|e| McpError::internal_error(format!("lock error: {e}"), None))?;
+        if subs.remove(&request.uri) && subs.is_empty() {
+            self.stop_polling();
+        }
+        Ok(())
+    }
+
+    fn get_peer(&self) -> Option<Peer<RoleServer>> {
+        self.peer.clone()
+    }
+
+    fn set_peer(&mut self, peer: Peer<RoleServer>) {
+        self.peer = Some(peer);
+        let server = self.clone();
+        tokio::spawn(async move {
+            server.shutdown.cancelled().await;
+            server.stop_polling();
+        });
+    }
 
     fn get_info(&self) -> InitializeResult {
         InitializeResult {
             protocol_version: ProtocolVersion::LATEST,
             capabilities: ServerCapabilities::builder()
                 .enable_tools()
+                .enable_resources()
+                .enable_resources_subscribe()
+                .enable_resources_list_changed()
+                .enable_prompts()
+                .enable_logging()
                 .build(),
             server_info: Implementation {
                 name: "obsidian-mcp".into(),
                 version: "0.1.0".into(),
             },
-            instructions: Some(
-                "Obsidian 知识库 MCP 服务。提供笔记索引、查询、写入功能。首次使用请先调用 write_note_tips 查阅操作规范。".to_string(),
-            ),
+            instructions: Some(if self.tools.is_read_only() {
+                "Obsidian 知识库 MCP 服务（只读模式）。提供笔记索引、查询、阅读功能，不能修改知识库。".to_string()
+            } else {
+                "Obsidian 知识库 MCP 服务。提供笔记索引、查询、写入功能。首次使用请先调用 write_note_tips 查阅操作规范。".to_string()
+            }),
         }
     }
 }
@@ -701,9 +3289,320 @@ async fn main() -> Result<()> {
         .with_ansi(false)
         .init();
 
-    tracing::info!("Obsidian MCP Server starting, vault: {}", get_vault_root());
+    let mut transport = std::env::var("OBSIDIAN_TRANSPORT").unwrap_or_else(|_| "stdio".to_string());
+    let mut bind = http::bind_from_env();
+    let mut tokens_file = std::env::var("OBSIDIAN_TOKENS_FILE").ok();
+    let mut read_only = false;
+    let mut config_file = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--transport" => transport = args.next().unwrap_or_default(),
+            "--bind" => bind = args.next().unwrap_or_default(),
+            "--tokens" => tokens_file = args.next(),
+            "--read-only" => read_only = true,
+            "--config" => config_file = args.next().map(PathBuf::from),
+            "-h" | "--help" => {
+                println!("Usage: obsidian-mcp [--transport stdio|http] [--bind 127.0.0.1:3000] [--tokens tokens.toml] [--read-only] [--config obsidian-mcp.toml]");
+                return Ok(());
+            }
+            other => anyhow::bail!("unknown argument: {other} (see --help)"),
+        }
+    }
+
+    let vaults = config::Config::load(config_file.as_deref())?;
+    let tools = toolset::ToolSet::from_env(&ObsidianMcp::tool_names(), read_only, vaults[0].enabled_tools.clone())?;
+
+    tracing::info!("Obsidian MCP Server starting");
+    for vault in &vaults {
+        match &vault.source {
+            Some(source) => tracing::info!(
+                "vault '{}': {} (config file {})",
+                vault.name,
+                vault.vault_root.display(),
+                source.display()
+            ),
+            None => tracing::info!("vault '{}': {}", vault.name, vault.vault_root.display()),
+        }
+    }
 
-    let service = ObsidianMcp::new().serve(stdio()).await?;
-    service.waiting().await?;
+    match transport.as_str() {
+        "stdio" => {
+            let server = ObsidianMcp::new(vaults, tools);
+            let transport = progress::stdio(server.progress_tokens());
+            let service = server.serve(transport).await?;
+            service.waiting().await?;
+        }
+        "http" => {
+            let bind = bind
+                .parse()
+                .map_err(|e| anyhow::anyhow!("invalid bind address '{bind}': {e}"))?;
+            let tokens = tokens_file
+                .map(|path| auth::TokenStore::load(Path::new(&path)))
+                .transpose()?;
+            http::serve(ObsidianMcp::new(vaults, tools), bind, tokens).await?;
+        }
+        other => anyhow::bail!("unknown transport: {other} (expected stdio or http)"),
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::TempVault;
+
+    /// A server over a fresh vault holding `files`, configured by `toml`.
+    /// The vault is removed when the returned `TempVault` is dropped.
+    fn server(files: &[(&str, &str)], toml: &str) -> (TempVault, ObsidianMcp) {
+        let root = TempVault::new(files);
+        let config = config::Config::from_toml(&root, toml).unwrap();
+        let tools = toolset::ToolSet::new(&ObsidianMcp::tool_names(), false, None).unwrap();
+        (root, ObsidianMcp::new(vec![config], tools))
+    }
+
+    /// A session of `server` whose token may only touch `directories`.
+    fn scoped(server: &ObsidianMcp, directories: &[&str]) -> ObsidianMcp {
+        let scope = auth::Scope {
+            name: "test".to_string(),
+            write: true,
+            directories: directories.iter().map(|d| d.to_string()).collect(),
+        };
+        server.for_session(CancellationToken::new(), scope)
+    }
+
+    fn params<T: serde::de::DeserializeOwned>(value: serde_json::Value) -> Parameters<T> {
+        Parameters(serde_json::from_value(value).unwrap())
+    }
+
+    fn write_params(directory: &str, filename: &str) -> Parameters<WriteNoteParams> {
+        params(serde_json::json!({
+            "directory": directory,
+            "filename": filename,
+            "tags": [],
+            "aliases": [],
+            "status": "active",
+            "content": "text",
+        }))
+    }
+
+    #[tokio::test]
+    async fn write_note_rejects_filenames_leaving_the_directory() {
+        let (_vault, server) = server(&[("cheatsheet/api-keys.md", "key")], "");
+        let tech = scoped(&server, &["tech"]);
+        assert!(tech.write_note(write_params("tech", "../cheatsheet/api-keys")).await.is_err());
+        assert!(tech.write_note(write_params("tech", "sub/note")).await.is_err());
+        let keys = server.config.vault_root.join("cheatsheet/api-keys.md");
+        assert_eq!(std::fs::read_to_string(keys).unwrap(), "key");
+        assert!(tech.write_note(write_params("tech", "note")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn write_note_refuses_protected_paths() {
+        let toml = "[protected_paths]\nunreadable = [\"cheatsheet/api-keys.md\"]\nread_only = [\"ideas/\"]\n";
+        let (_vault, server) = server(&[("cheatsheet/api-keys.md", "key"), ("ideas/plan.md", "plan")], toml);
+        let mut dry_run = write_params("cheatsheet", "api-keys");
+        dry_run.0.dry_run = true;
+        dry_run.0.diff = true;
+        assert!(server.write_note(dry_run).await.is_err());
+        assert!(server.write_note(write_params("ideas", "plan")).await.is_err());
+        assert!(server.write_note(write_params("ideas", "other")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn move_note_rejects_filenames_leaving_the_directory() {
+        let (_vault, server) = server(&[("tech/a.md", "a")], "[protected_paths]\nread_only = [\"ideas/\"]\n");
+        let tech = scoped(&server, &["tech"]);
+        let escape = |filename: &str| {
+            params::<MoveNoteParams>(serde_json::json!({
+                "path": "tech/a.md",
+                "directory": "tech",
+                "filename": filename,
+            }))
+        };
+        assert!(tech.move_note(escape("../ideas/a")).await.is_err());
+        assert!(tech.move_note(escape("../../outside")).await.is_err());
+        assert!(server.move_note(escape("../ideas/a")).await.is_err());
+        let root = &server.config.vault_root;
+        assert!(root.join("tech/a.md").is_file());
+        assert!(!root.join("ideas/a.md").exists());
+
+        let into_read_only = params::<MoveNoteParams>(serde_json::json!({
+            "path": "tech/a.md",
+            "directory": "ideas",
+            "filename": "a",
+        }));
+        assert!(server.move_note(into_read_only).await.is_err());
+        assert!(tech.move_note(escape("b")).await.is_ok());
+        assert!(root.join("tech/b.md").is_file());
+    }
+
+    #[tokio::test]
+    async fn move_note_leaves_read_only_referrers_untouched() {
+        let (_vault, server) = server(
+            &[("tech/a.md", "a"), ("tech/b.md", "see [[a]]"), ("ideas/x.md", "see [[a]]")],
+            "[protected_paths]\nread_only = [\"ideas/\"]\n",
+        );
+        let moved = params::<MoveNoteParams>(serde_json::json!({
+            "path": "tech/a.md",
+            "directory": "tech",
+            "filename": "c",
+        }));
+        let result = server.move_note(moved).await.unwrap();
+        let text = serde_json::to_string(&result).unwrap();
+        assert!(text.contains("1 个文件为只读"), "{text}");
+        assert!(text.contains("`ideas/x.md`：1 处链接"), "{text}");
+        let root = &server.config.vault_root;
+        assert_eq!(std::fs::read_to_string(root.join("ideas/x.md")).unwrap(), "see [[a]]");
+        assert_eq!(std::fs::read_to_string(root.join("tech/b.md")).unwrap(), "see [[c]]");
+        assert!(root.join("tech/c.md").is_file());
+    }
+
+    #[tokio::test]
+    async fn move_note_counts_referrers_the_session_cannot_see() {
+        let (_vault, server) = server(
+            &[
+                ("tech/a.md", "a"),
+                ("ideas/x.md", "see [[a]]"),
+                ("private/y.md", "see [a](../tech/a.md)"),
+                ("private/z.md", "no links"),
+            ],
+            "[protected_paths]\nunreadable = [\"private/\"]\n",
+        );
+        let tech = scoped(&server, &["tech"]);
+        let moved = params::<MoveNoteParams>(serde_json::json!({
+            "path": "tech/a.md",
+            "directory": "tech",
+            "filename": "c",
+        }));
+        let result = tech.move_note(moved).await.unwrap();
+        let text = serde_json::to_string(&result).unwrap();
+        assert!(text.contains("另有 2 篇"), "{text}");
+        assert!(!text.contains("ideas/x.md") && !text.contains("private/y.md"), "{text}");
+        let root = &server.config.vault_root;
+        assert_eq!(std::fs::read_to_string(root.join("ideas/x.md")).unwrap(), "see [[a]]");
+        assert_eq!(std::fs::read_to_string(root.join("private/y.md")).unwrap(), "see [a](../tech/a.md)");
+    }
+
+    #[test]
+    fn trash_origin_accepts_same_second_suffixes() {
+        let ts = chrono::NaiveDateTime::parse_from_str("20240501-120000", "%Y%m%d-%H%M%S").ok();
+        assert_eq!(trash_origin("20240501-120000/tech/a.md"), ("tech/a.md".to_string(), ts));
+        assert_eq!(trash_origin("20240501-120000-2/tech/a.md"), ("tech/a.md".to_string(), ts));
+        assert_eq!(trash_origin("20240501-120000-x/a.md"), ("20240501-120000-x/a.md".to_string(), None));
+        assert_eq!(trash_origin("20240501-120000-/a.md"), ("20240501-120000-/a.md".to_string(), None));
+        assert_eq!(trash_origin("tech/a.md"), ("tech/a.md".to_string(), None));
+    }
+
+    #[tokio::test]
+    async fn delete_note_keeps_same_second_deletes_apart() {
+        let (_vault, server) = server(&[("tech/a.md", "one")], "");
+        let delete = || params::<DeleteNoteParams>(serde_json::json!({ "path": "tech/a.md" }));
+        server.delete_note(delete()).await.unwrap();
+        std::fs::write(server.config.vault_root.join("tech/a.md"), "two").unwrap();
+        server.delete_note(delete()).await.unwrap();
+
+        let trash = server.config.vault_root.join(TRASH_DIR);
+        let mut contents: Vec<String> = WalkDir::new(&trash)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| std::fs::read_to_string(e.path()).unwrap())
+            .collect();
+        contents.sort();
+        assert_eq!(contents, ["one", "two"]);
+    }
+
+    #[tokio::test]
+    async fn list_trash_hides_entries_the_session_cannot_read() {
+        let (_vault, server) = server(
+            &[
+                (".trash/20240501-120000/tech/a.md", "a"),
+                (".trash/20240501-120000/private/b.md", "b"),
+                (".trash/20240501-120000/ideas/c.md", "c"),
+            ],
+            "[protected_paths]\nunreadable = [\"private/\"]\n",
+        );
+        let list = |session: &ObsidianMcp| {
+            let session = session.clone();
+            async move {
+                let result = session.list_trash(params(serde_json::json!({}))).await.unwrap();
+                serde_json::to_string(&result).unwrap()
+            }
+        };
+        let all = list(&server).await;
+        assert!(all.contains("tech/a.md") && all.contains("ideas/c.md"), "{all}");
+        assert!(!all.contains("private/b.md"), "{all}");
+        let tech = list(&scoped(&server, &["tech"])).await;
+        assert!(tech.contains("tech/a.md") && !tech.contains("ideas/c.md"), "{tech}");
+    }
+
+    #[test]
+    fn restricted_sessions_index_other_vaults_on_first_use() {
+        let first = TempVault::new(&[("tech/a.md", "a")]);
+        let second = TempVault::new(&[("tech/b.md", "b"), ("ideas/c.md", "c")]);
+        let mut named = config::Config::from_toml(&second, "").unwrap();
+        named.name = "second".to_string();
+        let configs = vec![config::Config::from_toml(&first, "").unwrap(), named];
+        let tools = toolset::ToolSet::new(&ObsidianMcp::tool_names(), false, None).unwrap();
+        let server = ObsidianMcp::new(configs, tools);
+        let tech = scoped(&server, &["tech"]);
+        assert!(tech.vaults[1].index.read().unwrap().built.is_none());
+
+        let view = tech.in_vault(Some("second")).unwrap();
+        let idx = view.index.read().unwrap();
+        let paths: Vec<&str> = idx.entries.iter().map(|e| e.rel_path.as_str()).collect();
+        assert_eq!(paths, ["tech/b.md"]);
+    }
+
+    #[tokio::test]
+    async fn reads_refuse_paths_left_out_of_the_index() {
+        let (_vault, server) = server(
+            &[
+                ("a.md", "a"),
+                (".obsidian/app.json", "{}"),
+                (".git/config", "[core]"),
+                (".obsidian-mcp/history/a.md/1.md", "old"),
+                ("drafts/b.md", "b"),
+            ],
+            "ignore = [\"drafts/\"]\n",
+        );
+        for path in [".obsidian/app.json", ".git/config", ".obsidian-mcp/history/a.md/1.md", "drafts/b.md"] {
+            let read = params::<ReadNoteParams>(serde_json::json!({ "path": path }));
+            assert!(server.read_note(read).await.is_err(), "{path}");
+            let history = params::<NoteHistoryParams>(serde_json::json!({ "path": path }));
+            assert!(server.note_history(history).await.is_err(), "{path}");
+            let idx = server.index.read().unwrap();
+            assert!(resources::read(&idx, &server.config, &server.scope, &resources::note_uri(path)).is_err());
+        }
+        let read = params::<ReadNoteParams>(serde_json::json!({ "path": "a.md" }));
+        assert!(server.read_note(read).await.is_ok());
+    }
+
+    #[test]
+    fn fair_shares_give_short_texts_all_they_need() {
+        assert_eq!(fair_shares(&[10, 20], 100), [10, 20]);
+        assert_eq!(fair_shares(&[100, 10, 100], 90), [40, 10, 40]);
+        assert_eq!(fair_shares(&[7, 7, 7], 10), [3, 3, 4]);
+        assert_eq!(fair_shares(&[5, 5], 0), [0, 0]);
+        assert!(fair_shares(&[], 10).is_empty());
+        let lengths = [3, 1000, 17, 250, 40];
+        for budget in [0, 1, 99, 300, 5000] {
+            let shares = fair_shares(&lengths, budget);
+            assert!(shares.iter().sum::<usize>() <= budget);
+            assert!(shares.iter().zip(&lengths).all(|(s, l)| s <= l));
+        }
+    }
+
+    #[test]
+    fn cut_point_prefers_whole_lines_and_char_boundaries() {
+        assert_eq!(cut_point("abc", 10), 3);
+        assert_eq!(cut_point("abc", 3), 3);
+        assert_eq!(cut_point("line1\nline2\nline3", 8), 5);
+        assert_eq!(cut_point("abcdef", 3), 3);
+        assert_eq!(cut_point("\nabcdef", 3), 3);
+        assert_eq!(cut_point("日本語テキスト", 2), 6);
+        assert_eq!(cut_point("日本\n語テキスト", 4), 6);
+        assert_eq!(cut_point("", 0), 0);
+    }
+}
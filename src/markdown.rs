@@ -0,0 +1,516 @@
+// ---------------------------------------------------------------------------
+// Markdown structure
+// ---------------------------------------------------------------------------
+//
+// One parse of a note, shared by the tools that look inside notes. Lines are
+// numbered from 1 over the whole file, frontmatter included, so numbers match
+// what an editor shows. The body is parsed with pulldown-cmark, which knows
+// about fenced / indented code, setext headings and the like; Obsidian-only
+// syntax (callouts `> [!type] title`, block ids `^abc123`) is picked up
+// from the lines on top of that. Wikilinks and embeds come from the parser's
+// wikilink extension; this is the one link grammar of the server (see
+// `links::scan_links`). Wikilinks in the frontmatter count as links too, as
+// they do for Obsidian's properties.
+//
+// Selecting part of a note:
+//   heading path   "## 部署/### Nginx", "部署/Nginx" — `#` marks are optional
+//                  and intermediate levels may be skipped
+//   block id       "^abc123" — the paragraph, list item or block it ends
+
+use crate::links::LinkKind;
+use pulldown_cmark::{CodeBlockKind, Event, LinkType, Options, Parser, Tag, TagEnd};
+use std::ops::Range;
+
+#[derive(Debug, Clone)]
+pub struct Heading {
+    pub level: usize,
+    pub text: String,
+    /// 1-based line number.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Callout {
+    pub kind: String,
+    pub title: String,
+    /// `+` / `-` for foldable callouts.
+    pub fold: Option<char>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct CodeBlock {
+    /// Info string language, if any; indented blocks have none.
+    pub lang: Option<String>,
+    /// 0-based line range, fences included.
+    pub lines: Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockId {
+    pub id: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkRef {
+    pub kind: LinkKind,
+    /// `[[...]]` / `![[...]]` syntax rather than markdown `[..](..)`.
+    pub wiki: bool,
+    /// Destination as written, anchor included, alias dropped.
+    pub dest: String,
+    /// Byte range of the whole link in the content.
+    pub span: Range<usize>,
+    /// Byte range of the target path as written (anchor and alias left out),
+    /// or `None` when the link does not spell out its destination itself
+    /// (reference-style links, autolinks).
+    pub target: Option<Range<usize>>,
+    /// Points outside the vault (`https://`, `mailto:`, ...).
+    pub external: bool,
+    pub line: usize,
+}
+
+#[derive(Debug)]
+pub struct Document<'a> {
+    pub lines: Vec<&'a str>,
+    /// 0-based line range of the frontmatter, `---` delimiters included.
+    pub frontmatter: Option<Range<usize>>,
+    pub headings: Vec<Heading>,
+    pub callouts: Vec<Callout>,
+    pub code_blocks: Vec<CodeBlock>,
+    pub block_ids: Vec<BlockId>,
+    pub links: Vec<LinkRef>,
+}
+
+impl<'a> Document<'a> {
+    pub fn parse(content: &'a str) -> Self {
+        let lines: Vec<&str> = content.lines().collect();
+        let frontmatter = frontmatter_lines(&lines);
+
+        // byte offset of each line start, to map parser offsets to lines
+        let mut starts = Vec::with_capacity(lines.len());
+        let mut offset = 0;
+        for line in content.split_inclusive('\n') {
+            starts.push(offset);
+            offset += line.len();
+        }
+        let line_of = |byte: usize| starts.partition_point(|&s| s <= byte).saturating_sub(1);
+
+        let body_start = frontmatter.as_ref().map_or(0, |f| starts.get(f.end).copied().unwrap_or(content.len()));
+        let body = &content[body_start..];
+        let mut headings = Vec::new();
+        let mut callouts: Vec<Callout> = Vec::new();
+        let mut code_blocks = Vec::new();
+        let mut links = Vec::new();
+
+        // wikilinks in property values, e.g. `related: "[[note]]"`
+        if let Some(fm) = &frontmatter {
+            let fm_start = starts.get(1).copied().unwrap_or(body_start).min(body_start);
+            let fm_end = starts.get(fm.end - 1).copied().unwrap_or(body_start).max(fm_start);
+            let text = &content[fm_start..fm_end];
+            for (event, range) in Parser::new_ext(text, Options::ENABLE_WIKILINKS).into_offset_iter() {
+                if let Event::Start(Tag::Link { link_type: link_type @ LinkType::WikiLink { .. }, dest_url, .. }) = event {
+                    let span = fm_start + range.start..fm_start + range.end;
+                    links.push(LinkRef {
+                        kind: LinkKind::Wiki,
+                        wiki: true,
+                        external: is_external(&dest_url),
+                        dest: dest_url.to_string(),
+                        target: target_range(content, &span, link_type),
+                        line: line_of(span.start) + 1,
+                        span,
+                    });
+                }
+            }
+        }
+        let mut current: Option<Heading> = None;
+        for (event, range) in Parser::new_ext(body, Options::all()).into_offset_iter() {
+            let start = body_start + range.start;
+            match event {
+                Event::Start(Tag::Heading { level, .. }) => {
+                    current = Some(Heading {
+                        level: level as usize,
+                        text: String::new(),
+                        line: line_of(start) + 1,
+                    });
+                }
+                Event::End(TagEnd::Heading(_)) => {
+                    if let Some(mut h) = current.take() {
+                        h.text = h.text.trim().to_string();
+                        headings.push(h);
+                    }
+                }
+                Event::Text(t) | Event::Code(t) => {
+                    if let Some(h) = &mut current {
+                        h.text.push_str(&t);
+                    }
+                }
+                Event::Start(Tag::CodeBlock(kind)) => {
+                    let end = body_start + range.end.max(range.start + 1) - 1;
+                    let lang = match kind {
+                        CodeBlockKind::Fenced(info) => info
+                            .split(|c: char| c.is_whitespace() || c == ',' || c == '{')
+                            .next()
+                            .filter(|l| !l.is_empty())
+                            .map(str::to_string),
+                        CodeBlockKind::Indented => None,
+                    };
+                    code_blocks.push(CodeBlock {
+                        lang,
+                        lines: line_of(start)..line_of(end) + 1,
+                    });
+                }
+                Event::Start(Tag::BlockQuote(_)) => {
+                    let line = line_of(start);
+                    if callouts.last().is_none_or(|c| c.line != line + 1) {
+                        callouts.extend(parse_callout(lines[line], line + 1));
+                    }
+                }
+                Event::Start(Tag::Link { link_type, dest_url, .. }) => {
+                    let span = start..body_start + range.end;
+                    let wiki = matches!(link_type, LinkType::WikiLink { .. });
+                    links.push(LinkRef {
+                        kind: if wiki { LinkKind::Wiki } else { LinkKind::Markdown },
+                        wiki,
+                        external: is_external(&dest_url),
+                        dest: dest_url.to_string(),
+                        target: target_range(content, &span, link_type),
+                        span,
+                        line: line_of(start) + 1,
+                    });
+                }
+                Event::Start(Tag::Image { link_type, dest_url, .. }) => {
+                    let span = start..body_start + range.end;
+                    links.push(LinkRef {
+                        kind: LinkKind::Embed,
+                        wiki: matches!(link_type, LinkType::WikiLink { .. }),
+                        external: is_external(&dest_url),
+                        dest: dest_url.to_string(),
+                        target: target_range(content, &span, link_type),
+                        span,
+                        line: line_of(start) + 1,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let mut doc = Self {
+            lines,
+            frontmatter,
+            headings,
+            callouts,
+            code_blocks,
+            block_ids: Vec::new(),
+            links,
+        };
+        doc.block_ids = (0..doc.lines.len())
+            .filter(|&n| !doc.is_code(n) && !doc.is_frontmatter(n))
+            .filter_map(|n| {
+                block_id(doc.lines[n]).map(|id| BlockId {
+                    id: id.to_string(),
+                    line: n + 1,
+                })
+            })
+            .collect();
+        doc
+    }
+
+    /// 0-based line range of the section under `headings[i]`: the heading line
+    /// up to the next heading of the same or a higher level.
+    pub fn section_lines(&self, i: usize) -> Range<usize> {
+        let heading = &self.headings[i];
+        let end = self.headings[i + 1..]
+            .iter()
+            .find(|h| h.level <= heading.level)
+            .map_or(self.lines.len(), |h| h.line - 1);
+        heading.line - 1..end
+    }
+
+    /// Index of the first heading matching a heading path such as
+    /// "## 部署/### Nginx" or "部署/Nginx".
+    pub fn find_heading(&self, path: &str) -> Option<usize> {
+        let segments: Vec<&str> = path.split('/').collect();
+        (0..self.headings.len()).find(|&i| {
+            let chain = self.ancestors(i);
+            chain_matches(&chain, &segments)
+        })
+    }
+
+    /// `headings[i]` preceded by the headings it is nested under.
+    fn ancestors(&self, i: usize) -> Vec<&Heading> {
+        let mut chain = vec![&self.headings[i]];
+        let mut level = self.headings[i].level;
+        for h in self.headings[..i].iter().rev() {
+            if h.level < level {
+                chain.push(h);
+                level = h.level;
+            }
+        }
+        chain.reverse();
+        chain
+    }
+
+    /// 0-based line range of the block carrying `^id`.
+    pub fn find_block(&self, id: &str) -> Option<Range<usize>> {
+        let id = id.trim().trim_start_matches('^');
+        let marker = format!("^{id}");
+        let at = self.block_ids.iter().find(|b| b.id == id)?.line - 1;
+
+        let line = self.lines[at].trim();
+        let stops = |n: usize| {
+            let l = self.lines[n].trim();
+            l.is_empty() || l.starts_with('#') || self.is_frontmatter(n)
+        };
+        if line == marker {
+            // on its own line: the block right above it, blank lines between
+            // the two allowed
+            let mut end = at;
+            while end > 0 && self.lines[end - 1].trim().is_empty() {
+                end -= 1;
+            }
+            let mut start = end;
+            while start > 0 && !stops(start - 1) {
+                start -= 1;
+            }
+            return Some(start.min(end.saturating_sub(1))..at + 1);
+        }
+        if is_list_item(line) {
+            return Some(at..at + 1);
+        }
+        let mut start = at;
+        while start > 0 && !stops(start - 1) && !is_list_item(self.lines[start - 1].trim()) {
+            start -= 1;
+        }
+        Some(start..at + 1)
+    }
+
+    /// Characters in a 0-based line range, line breaks included.
+    pub fn chars_in(&self, range: Range<usize>) -> usize {
+        self.lines[range].iter().map(|l| l.chars().count() + 1).sum()
+    }
+
+    fn is_code(&self, n: usize) -> bool {
+        self.code_blocks.iter().any(|b| b.lines.contains(&n))
+    }
+
+    fn is_frontmatter(&self, n: usize) -> bool {
+        self.frontmatter.as_ref().is_some_and(|r| r.contains(&n))
+    }
+}
+
+fn frontmatter_lines(lines: &[&str]) -> Option<Range<usize>> {
+    if lines.first()?.trim_start_matches('\u{feff}').trim_end() != "---" {
+        return None;
+    }
+    let end = lines[1..].iter().position(|l| l.trim_end() == "---")?;
+    Some(0..end + 2)
+}
+
+/// The id of a `^id` marker ending `line`.
+fn block_id(line: &str) -> Option<&str> {
+    let line = line.trim_end();
+    let caret = line.rfind('^')?;
+    let id = &line[caret + 1..];
+    let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    let separated = line[..caret].chars().next_back().is_none_or(char::is_whitespace);
+    (valid && separated).then_some(id)
+}
+
+/// A callout header such as `> [!warning]- 注意` (1-based `line`).
+fn parse_callout(line: &str, line_no: usize) -> Option<Callout> {
+    let rest = line.trim_start().trim_start_matches(|c: char| c == '>' || c.is_whitespace());
+    let rest = rest.strip_prefix("[!")?;
+    let close = rest.find(']')?;
+    let kind = rest[..close].trim();
+    if kind.is_empty() {
+        return None;
+    }
+    let rest = &rest[close + 1..];
+    let fold = rest.chars().next().filter(|c| matches!(c, '+' | '-'));
+    let title = rest[fold.map_or(0, |_| 1)..].trim();
+    Some(Callout {
+        kind: kind.to_lowercase(),
+        title: title.to_string(),
+        fold,
+        line: line_no,
+    })
+}
+
+fn is_external(dest: &str) -> bool {
+    dest.contains("://") || dest.starts_with("mailto:") || dest.starts_with("obsidian:")
+}
+
+/// Where the target path of the link at `span` is written: `x` in
+/// `[[x#h|alias]]` / `![[x]]`, `dir/x.md` in `[text](dir/x.md#h "title")` or
+/// `[text](<dir/x.md>)`.
+fn target_range(content: &str, span: &Range<usize>, link_type: LinkType) -> Option<Range<usize>> {
+    let text = &content[span.clone()];
+    let (start, inner) = match link_type {
+        LinkType::WikiLink { .. } => {
+            let open = text.find("[[")? + 2;
+            let close = text.rfind("]]").filter(|&c| c >= open)?;
+            let inner = &text[open..close];
+            let len = inner.find(['#', '|']).unwrap_or(inner.len());
+            // `[[note\|alias]]` inside tables
+            (open, inner[..len].trim_end_matches('\\'))
+        }
+        LinkType::Inline => {
+            // the link text cannot hold an unescaped "](" of its own
+            let mut open = text.find("](")?;
+            while open > 0 && text.as_bytes()[open - 1] == b'\\' {
+                open += 2 + text[open + 2..].find("](")?;
+            }
+            let rest = &text[open + 2..];
+            let skipped = rest.len() - rest.trim_start().len();
+            let rest = &rest[skipped..];
+            let (shift, dest) = match rest.strip_prefix('<') {
+                Some(angled) => (1, &angled[..angled.find('>')?]),
+                None => {
+                    // up to whitespace or the ')' closing the link
+                    let mut depth = 0usize;
+                    let end = rest
+                        .char_indices()
+                        .find(|&(_, c)| match c {
+                            '(' => {
+                                depth += 1;
+                                false
+                            }
+                            ')' if depth == 0 => true,
+                            ')' => {
+                                depth -= 1;
+                                false
+                            }
+                            c => c.is_whitespace(),
+                        })
+                        .map_or(rest.len(), |(i, _)| i);
+                    (0, &rest[..end])
+                }
+            };
+            let len = dest.find('#').unwrap_or(dest.len());
+            (open + 2 + skipped + shift, &dest[..len])
+        }
+        _ => return None,
+    };
+    let start = span.start + start;
+    Some(start..start + inner.len())
+}
+
+fn is_list_item(line: &str) -> bool {
+    if line.starts_with("- ") || line.starts_with("* ") || line.starts_with("+ ") {
+        return true;
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    digits > 0 && (line[digits..].starts_with(". ") || line[digits..].starts_with(") "))
+}
+
+/// Whether `segments` (a heading path split on '/') names `chain.last()`,
+/// each group of segments matching one heading of `chain` in order. A group
+/// may span several segments because heading texts can contain '/'.
+fn chain_matches(chain: &[&Heading], segments: &[&str]) -> bool {
+    let Some((last, above)) = chain.split_last() else {
+        return false;
+    };
+    (1..=segments.len()).any(|k| {
+        let (rest, group) = segments.split_at(segments.len() - k);
+        heading_matches(last, &group.join("/"))
+            && (rest.is_empty() || (0..above.len()).rev().any(|j| chain_matches(&above[..=j], rest)))
+    })
+}
+
+fn heading_matches(heading: &Heading, spec: &str) -> bool {
+    let spec = spec.trim();
+    let level = spec.chars().take_while(|&c| c == '#').count();
+    let text = spec[level..].trim();
+    (level == 0 || level == heading.level) && !text.is_empty() && text.to_lowercase() == heading.text.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_finds_structure_outside_code() {
+        let content = "---\ntags: [a]\n---\n# Title\n\n> [!warning]- 注意\n> body\n\n```rust\n# not a heading\n```\n\n## Sub\ntext ^abc\n";
+        let doc = Document::parse(content);
+        assert_eq!(doc.frontmatter, Some(0..3));
+        let headings: Vec<_> = doc.headings.iter().map(|h| (h.level, h.text.as_str(), h.line)).collect();
+        assert_eq!(headings, [(1, "Title", 4), (2, "Sub", 13)]);
+        assert_eq!(doc.callouts.len(), 1);
+        assert_eq!((doc.callouts[0].kind.as_str(), doc.callouts[0].title.as_str()), ("warning", "注意"));
+        assert_eq!(doc.callouts[0].fold, Some('-'));
+        assert_eq!(doc.code_blocks.len(), 1);
+        assert_eq!(doc.code_blocks[0].lang.as_deref(), Some("rust"));
+        assert_eq!(doc.code_blocks[0].lines, 8..11);
+        assert_eq!(doc.block_ids.len(), 1);
+        assert_eq!((doc.block_ids[0].id.as_str(), doc.block_ids[0].line), ("abc", 14));
+    }
+
+    #[test]
+    fn link_targets_point_at_the_written_path() {
+        let content = "[[a#h|A]] ![[img.png|100]] [t](<a b.md#x> \"t\") [u](dir/c.md) [r][ref] <https://x.y>\n\n[ref]: z.md\n";
+        let doc = Document::parse(content);
+        let targets: Vec<_> = doc
+            .links
+            .iter()
+            .map(|l| (l.kind, l.wiki, l.target.clone().map(|t| &content[t]), l.external))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                (LinkKind::Wiki, true, Some("a"), false),
+                (LinkKind::Embed, true, Some("img.png"), false),
+                (LinkKind::Markdown, false, Some("a b.md"), false),
+                (LinkKind::Markdown, false, Some("dir/c.md"), false),
+                (LinkKind::Markdown, false, None, false),
+                (LinkKind::Markdown, false, None, true),
+            ]
+        );
+        assert_eq!(doc.links[0].dest, "a#h");
+        assert_eq!(&content[doc.links[1].span.clone()], "![[img.png|100]]");
+    }
+
+    #[test]
+    fn frontmatter_wikilinks_are_links() {
+        let content = "---\nup: \"[[parent]]\"\nimg: \"![[x.png]]\"\n---\nbody [[child]]\n";
+        let doc = Document::parse(content);
+        let dests: Vec<_> = doc.links.iter().map(|l| (l.dest.as_str(), l.line)).collect();
+        assert_eq!(dests, [("parent", 2), ("child", 5)]);
+    }
+
+    const GUIDE: &str = "# Guide\nintro\n## Deploy\ntext\n### Nginx\nconf\n## Nginx\nother\n## A/B\nslash\n";
+
+    #[test]
+    fn find_heading_follows_heading_paths() {
+        let doc = Document::parse(GUIDE);
+        assert_eq!(doc.find_heading("Deploy/Nginx"), Some(2));
+        assert_eq!(doc.find_heading("Nginx"), Some(2));
+        assert_eq!(doc.find_heading("## Nginx"), Some(3));
+        assert_eq!(doc.find_heading("Guide/## Nginx"), Some(3));
+        assert_eq!(doc.find_heading("guide/deploy/### NGINX"), Some(2));
+        assert_eq!(doc.find_heading("A/B"), Some(4));
+        assert_eq!(doc.find_heading("Guide/A/B"), Some(4));
+        assert_eq!(doc.find_heading("### Deploy"), None);
+        assert_eq!(doc.find_heading("Deploy/A/B"), None);
+        assert_eq!(doc.find_heading(""), None);
+    }
+
+    #[test]
+    fn section_lines_stop_at_the_next_heading_of_the_same_level() {
+        let doc = Document::parse(GUIDE);
+        let sections: Vec<_> = (0..doc.headings.len()).map(|i| doc.section_lines(i)).collect();
+        assert_eq!(sections, [0..10, 2..6, 4..6, 6..8, 8..10]);
+        assert_eq!(doc.chars_in(sections[2].clone()), "### Nginx\nconf\n".chars().count());
+    }
+
+    #[test]
+    fn find_block_covers_the_marked_block() {
+        let content = "para one\npara two ^p\n\n- item a\n- item b ^li\n\n> quote\n\n^own\n\n## Next\n^top\n";
+        let doc = Document::parse(content);
+        assert_eq!(doc.find_block("p"), Some(0..2));
+        assert_eq!(doc.find_block("^li"), Some(4..5));
+        assert_eq!(doc.find_block(" own "), Some(6..9));
+        assert_eq!(doc.find_block("top"), Some(10..12));
+        assert_eq!(doc.find_block("missing"), None);
+    }
+}
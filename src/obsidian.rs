@@ -0,0 +1,105 @@
+// ---------------------------------------------------------------------------
+// Obsidian app settings
+// ---------------------------------------------------------------------------
+//
+// The parts of <vault>/.obsidian/ the server follows:
+//   app.json        userIgnoreFilters  ("Excluded files")
+//                   attachmentFolderPath
+//                   newLinkFormat      (shortest | relative | absolute)
+//   templates.json  folder             (core Templates plugin)
+// A missing file means Obsidian's defaults. The files are read at startup.
+
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkFormat {
+    Shortest,
+    Relative,
+    Absolute,
+}
+
+impl LinkFormat {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "shortest" => Some(Self::Shortest),
+            "relative" => Some(Self::Relative),
+            "absolute" => Some(Self::Absolute),
+            _ => None,
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Shortest => "尽可能简短（仅文件名，重名时带路径）",
+            Self::Relative => "相对于当前笔记的路径",
+            Self::Absolute => "从知识库根目录开始的完整路径",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AppSettings {
+    /// Path prefixes, or regexes written as `/.../`.
+    pub ignore_filters: Vec<String>,
+    /// `/` = vault root, `./` = next to the note, `./x` = subfolder of the
+    /// note's folder, anything else = that vault folder.
+    pub attachment_folder: Option<String>,
+    pub link_format: Option<LinkFormat>,
+    pub templates_folder: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct AppJson {
+    user_ignore_filters: Vec<String>,
+    attachment_folder_path: Option<String>,
+    new_link_format: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TemplatesJson {
+    folder: Option<String>,
+}
+
+impl AppSettings {
+    pub fn load(vault_root: &Path) -> Self {
+        let dir = vault_root.join(".obsidian");
+        let app: AppJson = read_json(&dir.join("app.json"));
+        let templates: TemplatesJson = read_json(&dir.join("templates.json"));
+
+        let link_format = app.new_link_format.as_deref().and_then(|v| {
+            let format = LinkFormat::parse(v);
+            if format.is_none() {
+                tracing::warn!("ignoring unknown newLinkFormat '{v}' in .obsidian/app.json");
+            }
+            format
+        });
+        let folder = |f: Option<String>| f.map(|f| f.trim().to_string()).filter(|f| !f.is_empty());
+
+        Self {
+            ignore_filters: app
+                .user_ignore_filters
+                .into_iter()
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+                .collect(),
+            attachment_folder: folder(app.attachment_folder_path),
+            link_format,
+            templates_folder: folder(templates.folder).map(|f| f.trim_matches('/').to_string()),
+        }
+    }
+}
+
+/// Obsidian owns these files, so a broken one is reported and skipped
+/// rather than stopping the server.
+fn read_json<T: Default + for<'de> Deserialize<'de>>(path: &Path) -> T {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return T::default();
+    };
+    serde_json::from_str(&text).unwrap_or_else(|e| {
+        tracing::warn!("ignoring invalid {}: {e}", path.display());
+        T::default()
+    })
+}
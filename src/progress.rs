@@ -0,0 +1,205 @@
+// ---------------------------------------------------------------------------
+// Progress notifications & cancellation
+// ---------------------------------------------------------------------------
+//
+// rmcp drops `_meta` when it decodes request params, so the transports read
+// the progress token from the raw message (`Tokens::decode`) and the handler
+// looks it up by request id. While a request runs, long loops (index
+// rebuilds, link scans) call `progress::report` and `progress::is_cancelled`;
+// outside a request both are no-ops.
+//
+// A request may run several loops in a row (rebuild, then scan). Each one is
+// appended to the previous, so the reported progress keeps increasing.
+
+use futures::{Sink, Stream};
+use rmcp::model::{ClientJsonRpcMessage, ErrorData, JsonRpcMessage, ProgressNotificationParam, ProgressToken, RequestId, ServerJsonRpcMessage};
+use rmcp::service::{Peer, RequestContext, RoleServer};
+use serde_json::Value;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::runtime::RuntimeFlavor;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// Minimum time between two progress notifications of one loop.
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Progress tokens of the requests in flight on one connection.
+#[derive(Clone, Default)]
+pub struct Tokens(Arc<Mutex<HashMap<RequestId, ProgressToken>>>);
+
+impl Tokens {
+    /// Decode a client message, remembering its progress token.
+    pub fn decode(&self, value: Value) -> serde_json::Result<ClientJsonRpcMessage> {
+        if value.get("method").is_some() {
+            let id = value.get("id").cloned().map(serde_json::from_value::<RequestId>);
+            let token = value
+                .pointer("/params/_meta/progressToken")
+                .cloned()
+                .map(serde_json::from_value::<ProgressToken>);
+            if let (Some(Ok(id)), Some(Ok(token))) = (id, token) {
+                if let Ok(mut tokens) = self.0.lock() {
+                    tokens.insert(id, token);
+                }
+            }
+        }
+        serde_json::from_value(value)
+    }
+
+    fn get(&self, id: &RequestId) -> Option<ProgressToken> {
+        self.0.lock().ok()?.get(id).cloned()
+    }
+
+    /// Forget a request's token once its response goes out.
+    pub fn finish(&self, message: &ServerJsonRpcMessage) {
+        let id = match message {
+            JsonRpcMessage::Response(r) => &r.id,
+            JsonRpcMessage::Error(e) => &e.id,
+            _ => return,
+        };
+        if let Ok(mut tokens) = self.0.lock() {
+            tokens.remove(id);
+        }
+    }
+}
+
+/// Newline-delimited JSON over stdin / stdout, like rmcp's stdio transport,
+/// but decoding through `tokens`.
+pub fn stdio(
+    tokens: Tokens,
+) -> (
+    impl Sink<ServerJsonRpcMessage, Error = std::io::Error> + Send + 'static,
+    impl Stream<Item = ClientJsonRpcMessage> + Send + 'static,
+) {
+    let finished = tokens.clone();
+    let sink = futures::sink::unfold(tokio::io::stdout(), move |mut out, message: ServerJsonRpcMessage| {
+        finished.finish(&message);
+        async move {
+            let mut line = serde_json::to_vec(&message).map_err(std::io::Error::other)?;
+            line.push(b'\n');
+            out.write_all(&line).await?;
+            out.flush().await?;
+            Ok(out)
+        }
+    });
+
+    let lines = tokio::io::AsyncBufReadExt::lines(tokio::io::BufReader::new(tokio::io::stdin()));
+    let stream = futures::stream::unfold((lines, tokens), |(mut lines, tokens)| async move {
+        loop {
+            let line = match lines.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => return None,
+                Err(e) => {
+                    tracing::error!("failed to read stdin: {e}");
+                    return None;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line).and_then(|value| tokens.decode(value)) {
+                Ok(message) => return Some((message, (lines, tokens))),
+                Err(e) => tracing::warn!("ignoring invalid message: {e}"),
+            }
+        }
+    });
+    (sink, stream)
+}
+
+struct Progress {
+    token: Option<ProgressToken>,
+    ct: CancellationToken,
+    tx: Option<mpsc::UnboundedSender<ProgressNotificationParam>>,
+    /// Progress at the end of the previous loop.
+    base: Cell<u32>,
+    last_sent: Cell<Option<Instant>>,
+}
+
+tokio::task_local! {
+    static CURRENT: Progress;
+}
+
+/// Run a request handler with progress reporting and cancellation checks.
+pub async fn run<F: std::future::Future>(tokens: &Tokens, context: &RequestContext<RoleServer>, fut: F) -> F::Output {
+    let token = tokens.get(&context.id);
+    let (tx, forwarder) = match &token {
+        Some(_) => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (Some(tx), Some(tokio::spawn(forward(context.peer.clone(), rx))))
+        }
+        None => (None, None),
+    };
+    let progress = Progress {
+        token,
+        ct: context.ct.clone(),
+        tx,
+        base: Cell::new(0),
+        last_sent: Cell::new(None),
+    };
+    // Handlers read and write files synchronously. Without `block_in_place`
+    // they would hold this worker thread, and the tasks queued behind them
+    // (the service loop that delivers `notifications/cancelled`, the
+    // progress forwarder) would only run once the handler is done.
+    // `block_in_place` panics on a current-thread runtime; there the handler
+    // simply runs inline, and cancellation and progress arrive late.
+    let handle = tokio::runtime::Handle::current();
+    let out = match handle.runtime_flavor() {
+        RuntimeFlavor::CurrentThread => CURRENT.scope(progress, fut).await,
+        _ => tokio::task::block_in_place(|| handle.block_on(CURRENT.scope(progress, fut))),
+    };
+    // let the last notifications out before the response
+    if let Some(forwarder) = forwarder {
+        let _ = forwarder.await;
+    }
+    out
+}
+
+async fn forward(peer: Peer<RoleServer>, mut rx: mpsc::UnboundedReceiver<ProgressNotificationParam>) {
+    while let Some(param) = rx.recv().await {
+        if let Err(e) = peer.notify_progress(param).await {
+            tracing::warn!("failed to send progress: {e}");
+        }
+    }
+}
+
+/// `done` of `total` items of the current loop are finished.
+pub fn report(done: usize, total: usize) {
+    let _ = CURRENT.try_with(|p| {
+        let (Some(token), Some(tx)) = (&p.token, &p.tx) else {
+            return;
+        };
+        let done = u32::try_from(done).unwrap_or(u32::MAX);
+        let total = u32::try_from(total).unwrap_or(u32::MAX);
+        let base = p.base.get();
+        let finished = done >= total;
+        if finished {
+            p.base.set(base.saturating_add(total));
+        }
+        let now = Instant::now();
+        if !finished && p.last_sent.get().is_some_and(|t| now - t < MIN_INTERVAL) {
+            return;
+        }
+        p.last_sent.set(Some(now));
+        let _ = tx.send(ProgressNotificationParam {
+            progress_token: token.clone(),
+            progress: base.saturating_add(done),
+            total: Some(base.saturating_add(total)),
+        });
+    });
+}
+
+/// Whether the client cancelled the current request.
+pub fn is_cancelled() -> bool {
+    CURRENT.try_with(|p| p.ct.is_cancelled()).unwrap_or(false)
+}
+
+/// Fail if the client cancelled the current request; call before writing.
+pub fn check() -> Result<(), ErrorData> {
+    if is_cancelled() {
+        return Err(ErrorData::invalid_request("请求已被客户端取消", None));
+    }
+    Ok(())
+}
@@ -0,0 +1,342 @@
+// ---------------------------------------------------------------------------
+// MCP prompts
+// ---------------------------------------------------------------------------
+//
+// Parameterized prompts for common vault workflows. Every prompt embeds the
+// vault conventions (the `write_note_tips` document), so the model does not have to
+// remember to call `write_note_tips` first, plus the note content it needs.
+// When the client cannot write (read-only mode, a tool set without
+// `write_note`, or a read-only token), prompts ask for the note as text
+// instead of a `write_note` call.
+
+use crate::auth::Scope;
+use crate::links::{self, LinkKind};
+use crate::config::Config;
+use crate::{fenced, sandbox, VaultIndex};
+use rmcp::model::*;
+
+fn argument(name: &str, description: &str, required: bool) -> PromptArgument {
+    PromptArgument {
+        name: name.to_string(),
+        description: Some(description.to_string()),
+        required: Some(required),
+    }
+}
+
+pub fn list(config: &Config) -> Vec<Prompt> {
+    let mut prompts = vec![
+        Prompt::new(
+            "summarize_note",
+            Some("总结一篇笔记的要点"),
+            Some(vec![
+                argument("path", "笔记相对路径，如 tech/docker-guide.md", true),
+                argument("focus", "可选，总结时侧重的方面", false),
+            ]),
+        ),
+        Prompt::new(
+            "create_note",
+            Some("按知识库规范新建一篇笔记（会先列出可能重复的已有笔记）"),
+            Some(vec![
+                argument("topic", "笔记主题", true),
+                argument("directory", "可选，目标目录（tech/ai/projects/...）", false),
+                argument("material", "可选，要整理进笔记的原始材料", false),
+            ]),
+        ),
+        Prompt::new(
+            "review_note",
+            Some("检查一篇笔记是否违反知识库规范，并给出修改建议"),
+            Some(vec![argument("path", "笔记相对路径，如 tech/docker-guide.md", true)]),
+        ),
+    ];
+    if let Some(dir) = &config.journal_directory {
+        prompts.insert(
+            2,
+            Prompt::new(
+                "daily_journal",
+                Some(format!("按 {dir}/ 规范撰写当天的工作日志")),
+                Some(vec![
+                    argument("topic", "日志主题（英文小写+短横线，用于文件名）", true),
+                    argument("date", "可选，日期 YYYY-MM-DD，默认今天", false),
+                    argument("material", "可选，今天做了什么的原始记录", false),
+                ]),
+            ),
+        );
+    }
+    prompts
+}
+
+fn arg(args: &Option<JsonObject>, name: &str) -> Option<String> {
+    args.as_ref()?
+        .get(name)?
+        .as_str()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+fn required_arg(args: &Option<JsonObject>, name: &str) -> Result<String, ErrorData> {
+    arg(args, name).ok_or_else(|| ErrorData::invalid_params(format!("缺少参数: {name}"), None))
+}
+
+fn read_note(config: &Config, scope: &Scope, path: &str) -> Result<(String, String), ErrorData> {
+    let rel_path = sandbox::clean(path)?;
+    if !scope.allows(&rel_path) || config.is_ignored(&rel_path, false) {
+        return Err(ErrorData::invalid_request(format!("权限不足：无权访问 {rel_path}"), None));
+    }
+    let content = std::fs::read_to_string(sandbox::resolve(&config.vault_root, &rel_path)?)
+        .map_err(|_| ErrorData::invalid_params(format!("文件不存在: {rel_path}"), None))?;
+    let content = config.secrets.apply(&content)?.into_owned();
+    Ok((rel_path, content))
+}
+
+fn tips_message(config: &Config) -> PromptMessage {
+    PromptMessage::new_text(
+        PromptMessageRole::User,
+        format!("以下是本知识库的操作规范，请严格遵守：\n\n{}", config.tips_with_settings()),
+    )
+}
+
+fn note_block(rel_path: &str, content: &str) -> String {
+    format!("笔记 `{rel_path}` 的内容：\n\n{}", fenced("markdown", content))
+}
+
+/// `can_write`: whether the client may call `write_note`.
+pub fn get(
+    index: &VaultIndex,
+    config: &Config,
+    scope: &Scope,
+    can_write: bool,
+    request: &GetPromptRequestParam,
+) -> Result<GetPromptResult, ErrorData> {
+    let args = &request.arguments;
+    let (description, task) = match request.name.as_str() {
+        "summarize_note" => {
+            let (rel_path, content) = read_note(config, scope, &required_arg(args, "path")?)?;
+            let focus = arg(args, "focus")
+                .map(|f| format!("，重点关注：{f}"))
+                .unwrap_or_default();
+            (
+                format!("总结 {rel_path}"),
+                format!(
+                    "{}\n\n请用中文总结这篇笔记的要点{focus}。先用一两句话概括主题，再分条列出关键结论、命令或配置，最后列出它链接到的相关笔记。",
+                    note_block(&rel_path, &content)
+                ),
+            )
+        }
+        "create_note" => {
+            let topic = required_arg(args, "topic")?;
+            let directory = match arg(args, "directory") {
+                Some(dir) => format!("目标目录：`{}/`。", config.validate_directory(&dir)?),
+                None => {
+                    let dirs: Vec<String> = config
+                        .directories
+                        .iter()
+                        .map(|d| format!("- `{}/`：{}", d.name, d.description))
+                        .collect();
+                    format!("请根据分区职责选择目录：\n{}", dirs.join("\n"))
+                }
+            };
+            let similar = similar_notes(index, &topic);
+            let similar = if similar.is_empty() {
+                "知识库中没有找到主题相近的笔记。".to_string()
+            } else {
+                format!(
+                    "以下已有笔记可能与该主题重复，如确属同一主题请追加到已有笔记而不是新建：\n{}",
+                    similar.join("\n")
+                )
+            };
+            let material = arg(args, "material")
+                .map(|m| format!("\n\n原始材料：\n\n{}", fenced("", &m)))
+                .unwrap_or_default();
+            let output = if can_write {
+                "用 write_note 写入：文件名英文小写+短横线，content 不含 frontmatter，以 `> [!abstract] 概述` 开头，以 `## 相关笔记` 结尾。"
+            } else {
+                "当前无法写入知识库，请直接输出完整的笔记（包括 frontmatter），并给出保存路径：文件名英文小写+短横线，正文以 `> [!abstract] 概述` 开头，以 `## 相关笔记` 结尾。"
+            };
+            (
+                format!("新建笔记：{topic}"),
+                format!("请为主题「{topic}」新建一篇笔记。{directory}\n\n{similar}{material}\n\n{output}"),
+            )
+        }
+        "daily_journal" => {
+            let Some(dir) = &config.journal_directory else {
+                return Err(ErrorData::invalid_params(
+                    "未配置日志目录（journal_directory），无法使用 daily_journal",
+                    None,
+                ));
+            };
+            let topic = required_arg(args, "topic")?;
+            config.validate_filename(&topic)?;
+            let date = match arg(args, "date") {
+                Some(d) => chrono::NaiveDate::parse_from_str(&d, "%Y-%m-%d")
+                    .map_err(|_| ErrorData::invalid_params(format!("无效的日期: {d}，应为 YYYY-MM-DD"), None))?,
+                None => chrono::Local::now().date_naive(),
+            };
+            let date = date.format("%Y-%m-%d").to_string();
+
+            let prefix = format!("{dir}/");
+            let mut existing = String::new();
+            for e in index
+                .entries
+                .iter()
+                .filter(|e| e.rel_path.starts_with(&prefix) && e.title.starts_with(&date))
+            {
+                if let Ok((rel_path, content)) = read_note(config, scope, &e.rel_path) {
+                    existing.push_str(&format!("\n\n{}", note_block(&rel_path, &content)));
+                }
+            }
+            let existing = if existing.is_empty() {
+                format!("{date} 还没有日志。")
+            } else {
+                format!("{date} 已有以下日志，同一主题请追加而不是新建：{existing}")
+            };
+            let material = arg(args, "material")
+                .map(|m| format!("\n\n今天的原始记录：\n\n{}", fenced("", &m)))
+                .unwrap_or_default();
+            let output = if can_write {
+                format!("用 write_note 写入，directory 为 `{dir}`，filename 为 `{date}-{topic}`，tags 包含 `journal`。")
+            } else {
+                format!("当前无法写入知识库，请直接输出完整的日志（包括 frontmatter，tags 包含 `journal`），供保存为 `{dir}/{date}-{topic}.md`。")
+            };
+            (
+                format!("{date} 工作日志：{topic}"),
+                format!(
+                    "请撰写 {date} 的工作日志，主题 `{topic}`。\n\n{existing}{material}\n\n{output}内容以 `> [!abstract] 概述` 开头，分「完成事项」「遇到的问题」「明日计划」等章节，以 `## 相关笔记` 结尾。"
+                ),
+            )
+        }
+        "review_note" => {
+            let (rel_path, content) = read_note(config, scope, &required_arg(args, "path")?)?;
+            let findings = review(config, &rel_path, &content);
+            let findings = if findings.is_empty() {
+                "自动检查未发现问题。".to_string()
+            } else {
+                format!("自动检查发现：\n{}", findings.join("\n"))
+            };
+            (
+                format!("检查 {rel_path}"),
+                format!(
+                    "{}\n\n{findings}\n\n请对照规范逐项检查这篇笔记（命名、目录、frontmatter、Callout、Wikilinks、相关笔记章节、内容精简原则），列出所有违规之处并给出具体修改建议。",
+                    note_block(&rel_path, &content)
+                ),
+            )
+        }
+        other => {
+            return Err(ErrorData::invalid_params(format!("未知的 prompt: {other}"), None));
+        }
+    };
+
+    Ok(GetPromptResult {
+        description: Some(description),
+        messages: vec![tips_message(config), PromptMessage::new_text(PromptMessageRole::User, task)],
+    })
+}
+
+/// Notes whose filename, aliases or tags mention a word of `topic`.
+fn similar_notes(index: &VaultIndex, topic: &str) -> Vec<String> {
+    let words: Vec<String> = topic
+        .split(|c: char| c.is_whitespace() || c == '-' || c == '/')
+        .filter(|w| w.chars().count() >= 2)
+        .map(str::to_lowercase)
+        .collect();
+    index
+        .entries
+        .iter()
+        .filter(|e| {
+            let haystack = format!("{} {} {}", e.title, e.aliases.join(" "), e.tags.join(" ")).to_lowercase();
+            words.iter().any(|w| haystack.contains(w.as_str()))
+        })
+        .take(10)
+        .map(|e| format!("- `{}`（{}）", e.rel_path, e.aliases.join(", ")))
+        .collect()
+}
+
+/// Mechanical convention checks; the model does the rest.
+fn review(config: &Config, rel_path: &str, content: &str) -> Vec<String> {
+    let mut findings = Vec::new();
+
+    let top = rel_path.split('/').next().unwrap_or("");
+    if !rel_path.contains('/') || !config.is_directory(top) {
+        findings.push(format!("- 笔记不在规定的分区目录中（{}）", config.directory_names().join(", ")));
+    }
+    let stem = rel_path.rsplit('/').next().unwrap_or(rel_path);
+    if let Err(e) = config.validate_filename(stem) {
+        findings.push(format!("- 文件名不规范：{}", e.message));
+    }
+
+    let body = content.trim_start_matches('\u{feff}');
+    match body.strip_prefix("---").and_then(|rest| rest.find("\n---").map(|end| &rest[..end])) {
+        Some(fm) => {
+            for field in ["tags:", "aliases:", "created:", "updated:", "status:"] {
+                if !fm.lines().any(|l| l.trim_start().starts_with(field)) {
+                    findings.push(format!("- frontmatter 缺少 `{}` 字段", field.trim_end_matches(':')));
+                }
+            }
+        }
+        None => findings.push("- 缺少 frontmatter".to_string()),
+    }
+    if !content.contains("> [!abstract]") {
+        findings.push("- 缺少 `> [!abstract] 概述` Callout".to_string());
+    }
+    if !content.lines().any(|l| l.trim() == "## 相关笔记") {
+        findings.push("- 缺少 `## 相关笔记` 章节".to_string());
+    }
+    let md_links = links::scan_links(content)
+        .iter()
+        .filter(|l| l.kind == LinkKind::Markdown && content[l.target.clone()].ends_with(".md"))
+        .count();
+    if md_links > 0 {
+        findings.push(format!("- 有 {md_links} 处 Markdown 链接引用库内文件，应改用 Wikilinks"));
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempVault;
+
+    fn journal_request() -> GetPromptRequestParam {
+        GetPromptRequestParam {
+            name: "daily_journal".to_string(),
+            arguments: serde_json::json!({ "topic": "deploy", "date": "2024-05-01" }).as_object().cloned(),
+        }
+    }
+
+    fn task(result: &GetPromptResult) -> String {
+        match &result.messages[1].content {
+            PromptMessageContent::Text { text } => text.clone(),
+            other => panic!("unexpected content {other:?}"),
+        }
+    }
+
+    #[test]
+    fn daily_journal_uses_the_configured_directory() {
+        let root = TempVault::new(&[]);
+        let toml = "journal_directory = \"log\"\n[[directories]]\nname = \"log\"\n";
+        let config = Config::from_toml(&root, toml).unwrap();
+        let index = VaultIndex::default();
+        let text = task(&get(&index, &config, &Scope::full(), true, &journal_request()).unwrap());
+        assert!(text.contains("directory 为 `log`"));
+        assert!(!text.contains("journal/"));
+        assert!(list(&config).iter().any(|p| p.description.as_deref() == Some("按 log/ 规范撰写当天的工作日志")));
+    }
+
+    #[test]
+    fn prompts_skip_write_note_when_the_client_cannot_write() {
+        let root = TempVault::new(&[]);
+        let config = Config::from_toml(&root, "").unwrap();
+        let index = VaultIndex::default();
+        let text = task(&get(&index, &config, &Scope::full(), false, &journal_request()).unwrap());
+        assert!(!text.contains("write_note"));
+        assert!(text.contains("`journal/2024-05-01-deploy.md`"));
+    }
+
+    #[test]
+    fn daily_journal_needs_a_journal_directory() {
+        let root = TempVault::new(&[]);
+        let config = Config::from_toml(&root, "[[directories]]\nname = \"tech\"\n").unwrap();
+        assert!(list(&config).iter().all(|p| p.name != "daily_journal"));
+        assert!(get(&VaultIndex::default(), &config, &Scope::full(), true, &journal_request()).is_err());
+    }
+}
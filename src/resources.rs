@@ -0,0 +1,266 @@
+// ---------------------------------------------------------------------------
+// MCP resources
+// ---------------------------------------------------------------------------
+//
+// Every file in the vault is exposed as a resource:
+//   obsidian://note/{path}       note or attachment content
+//   obsidian://tag/{tag}         markdown listing of the notes carrying a tag
+//   obsidian://dir/{directory}   markdown listing of the notes in a directory
+//
+// Markdown and other text files are served as text, everything else (images,
+// PDFs, ...) as base64 blobs.
+
+use crate::auth::Scope;
+use crate::config::Config;
+use crate::{sandbox, VaultIndex};
+use base64::Engine;
+use rmcp::model::*;
+use std::path::Path;
+
+pub const NOTE_PREFIX: &str = "obsidian://note/";
+pub const TAG_PREFIX: &str = "obsidian://tag/";
+pub const DIR_PREFIX: &str = "obsidian://dir/";
+
+const PAGE_SIZE: usize = 100;
+
+/// Percent-encode a vault path for use in a URI, keeping `/` separators.
+pub fn encode(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+pub fn decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+pub fn note_uri(rel_path: &str) -> String {
+    format!("{NOTE_PREFIX}{}", encode(rel_path))
+}
+
+pub fn mime_for(path: &Path) -> String {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+        Some("md") => "text/markdown".to_string(),
+        // JSON Canvas
+        Some("canvas") => "application/json".to_string(),
+        _ => mime_guess::from_path(path).first_or_octet_stream().to_string(),
+    }
+}
+
+fn is_text_mime(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/json" | "application/xml" | "application/javascript" | "application/x-yaml" | "application/toml"
+        )
+}
+
+/// Non-markdown files in the vault within `scope`, filtered like the index
+/// and the file tree (hidden folders, ignore rules, ...).
+pub fn attachment_paths(config: &Config, scope: &Scope) -> Vec<String> {
+    config
+        .files()
+        .into_iter()
+        .map(|(_, rel)| rel)
+        .filter(|rel| !rel.ends_with(".md") && scope.allows(rel))
+        .collect()
+}
+
+fn size_of(path: &Path) -> Option<u32> {
+    std::fs::metadata(path).ok().and_then(|m| u32::try_from(m.len()).ok())
+}
+
+pub fn list(index: &VaultIndex, config: &Config, scope: &Scope, cursor: Option<&str>) -> Result<ListResourcesResult, ErrorData> {
+    let root = &config.vault_root;
+    let offset = match cursor {
+        Some(c) => c
+            .parse::<usize>()
+            .map_err(|_| ErrorData::invalid_params(format!("无效的分页游标: {c}"), None))?,
+        None => 0,
+    };
+
+    let mut notes: Vec<_> = index.entries.iter().collect();
+    notes.sort_by(|a, b| a.rel_path.cmp(&b.rel_path));
+    let mut attachments = attachment_paths(config, scope);
+    attachments.sort();
+    let total = notes.len() + attachments.len();
+
+    let resources = notes
+        .iter()
+        .map(|e| {
+            let mut description = String::new();
+            if !e.aliases.is_empty() {
+                description.push_str(&format!("别名：{}", e.aliases.join(", ")));
+            }
+            if !e.tags.is_empty() {
+                if !description.is_empty() {
+                    description.push('；');
+                }
+                description.push_str(&format!("标签：{}", e.tags.join(", ")));
+            }
+            let mut raw = RawResource::new(note_uri(&e.rel_path), e.title.clone());
+            raw.description = (!description.is_empty()).then_some(description);
+            raw.mime_type = Some("text/markdown".to_string());
+            raw.size = size_of(&root.join(&e.rel_path));
+            raw.no_annotation()
+        })
+        .chain(attachments.iter().map(|rel| {
+            let path = root.join(rel);
+            let mut raw = RawResource::new(
+                note_uri(rel),
+                rel.rsplit('/').next().unwrap_or(rel).to_string(),
+            );
+            raw.mime_type = Some(mime_for(&path));
+            raw.size = size_of(&path);
+            raw.no_annotation()
+        }))
+        .skip(offset)
+        .take(PAGE_SIZE)
+        .collect();
+
+    Ok(ListResourcesResult {
+        next_cursor: (offset + PAGE_SIZE < total).then(|| (offset + PAGE_SIZE).to_string()),
+        resources,
+    })
+}
+
+pub fn templates() -> Vec<ResourceTemplate> {
+    let template = |uri: &str, name: &str, description: &str, mime: &str| {
+        RawResourceTemplate {
+            uri_template: uri.to_string(),
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            mime_type: Some(mime.to_string()),
+        }
+        .no_annotation()
+    };
+    vec![
+        template(
+            &format!("{NOTE_PREFIX}{{path}}"),
+            "笔记或附件",
+            "按相对路径读取笔记（text/markdown）或附件（按文件类型返回 MIME，二进制以 blob 返回），如 obsidian://note/tech/docker-guide.md",
+            "text/markdown",
+        ),
+        template(
+            &format!("{TAG_PREFIX}{{tag}}"),
+            "标签下的笔记",
+            "列出带有指定标签的所有笔记及其资源 URI，如 obsidian://tag/docker",
+            "text/markdown",
+        ),
+        template(
+            &format!("{DIR_PREFIX}{{directory}}"),
+            "目录下的笔记",
+            "列出指定目录（含子目录）下的所有笔记及其资源 URI，如 obsidian://dir/tech",
+            "text/markdown",
+        ),
+    ]
+}
+
+fn listing(title: &str, index: &VaultIndex, indices: &[usize]) -> String {
+    let mut out = format!("# {title}\n\n共 {} 篇笔记：\n\n", indices.len());
+    for &i in indices {
+        let e = &index.entries[i];
+        out.push_str(&format!("- [{}]({}) — `{}`", e.title, note_uri(&e.rel_path), e.rel_path));
+        if !e.tags.is_empty() {
+            out.push_str(&format!("（{}）", e.tags.join(", ")));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub fn read(index: &VaultIndex, config: &Config, scope: &Scope, uri: &str) -> Result<ReadResourceResult, ErrorData> {
+    let root = &config.vault_root;
+    let decoded = |rest: &str| {
+        decode(rest)
+            .map(|s| s.trim_matches('/').to_string())
+            .ok_or_else(|| ErrorData::invalid_params(format!("无效的资源 URI: {uri}"), None))
+    };
+
+    if let Some(rest) = uri.strip_prefix(NOTE_PREFIX) {
+        let rel_path = sandbox::clean(&decoded(rest)?)?;
+        if !scope.allows(&rel_path) || config.is_ignored(&rel_path, false) {
+            return Err(ErrorData::invalid_request(format!("权限不足：无权访问 {rel_path}"), None));
+        }
+        let path = sandbox::resolve(root, &rel_path)?;
+        if !path.is_file() {
+            return Err(ErrorData::resource_not_found(format!("文件不存在: {rel_path}"), None));
+        }
+        let bytes = std::fs::read(&path)
+            .map_err(|e| ErrorData::internal_error(format!("读取文件失败: {e}"), None))?;
+        let mime = mime_for(&path);
+
+        let contents = if is_text_mime(&mime) {
+            ResourceContents::TextResourceContents {
+                uri: uri.to_string(),
+                mime_type: Some(mime),
+                text: config.secrets.apply(&String::from_utf8_lossy(&bytes))?.into_owned(),
+            }
+        } else {
+            ResourceContents::BlobResourceContents {
+                uri: uri.to_string(),
+                mime_type: Some(mime),
+                blob: base64::engine::general_purpose::STANDARD.encode(&bytes),
+            }
+        };
+        return Ok(ReadResourceResult { contents: vec![contents] });
+    }
+
+    let text = if let Some(rest) = uri.strip_prefix(TAG_PREFIX) {
+        let tag = decoded(rest)?;
+        let indices = index
+            .tag_map
+            .get(&tag.to_lowercase())
+            .ok_or_else(|| ErrorData::resource_not_found(format!("标签不存在: {tag}"), None))?;
+        listing(&format!("标签 `{tag}`"), index, indices)
+    } else if let Some(rest) = uri.strip_prefix(DIR_PREFIX) {
+        let dir = decoded(rest)?;
+        // an empty directory is the vault root
+        let dir = if dir.is_empty() { dir } else { sandbox::clean(&dir)? };
+        if !scope.allows_prefix(&dir) {
+            return Err(ErrorData::invalid_request(format!("权限不足：无权访问 {dir}/"), None));
+        }
+        if !sandbox::resolve(root, &dir)?.is_dir() {
+            return Err(ErrorData::resource_not_found(format!("目录不存在: {dir}"), None));
+        }
+        let prefix = format!("{dir}/");
+        let mut indices: Vec<usize> = (0..index.entries.len())
+            .filter(|&i| dir.is_empty() || index.entries[i].rel_path.starts_with(&prefix))
+            .collect();
+        indices.sort_by(|&a, &b| index.entries[a].rel_path.cmp(&index.entries[b].rel_path));
+        listing(&format!("目录 `{dir}/`"), index, &indices)
+    } else {
+        return Err(ErrorData::invalid_params(
+            format!("不支持的资源 URI: {uri}，应以 {NOTE_PREFIX}、{TAG_PREFIX} 或 {DIR_PREFIX} 开头"),
+            None,
+        ));
+    };
+
+    Ok(ReadResourceResult {
+        contents: vec![ResourceContents::TextResourceContents {
+            uri: uri.to_string(),
+            mime_type: Some("text/markdown".to_string()),
+            text,
+        }],
+    })
+}
@@ -0,0 +1,211 @@
+// ---------------------------------------------------------------------------
+// Path sandboxing
+// ---------------------------------------------------------------------------
+//
+// Every path a client passes in goes through two checks before it touches
+// the disk:
+//   1. `clean` normalizes it lexically: `\` becomes `/`, `.` and `a/..` are
+//      collapsed, and climbing above the vault root, drive letters
+//      (`C:\...`), UNC paths (`\\server\share`) and NUL / `:` are refused.
+//      A single leading `/` means the vault root, as in Obsidian links.
+//      Names that merely contain `..` (`v1..v2.md`) are fine.
+//   2. `resolve` canonicalizes the deepest existing part of the path, which
+//      follows every symlink on the way, and refuses the path unless the
+//      result is still inside the (canonical) vault root. Symlinks are thus
+//      followed only when their target stays inside the vault; dangling ones
+//      are refused.
+
+use rmcp::model::ErrorData;
+use std::path::{Path, PathBuf};
+
+/// Normalize a client-supplied vault-relative path.
+pub fn clean(input: &str) -> Result<String, ErrorData> {
+    let path = input.trim().replace('\\', "/");
+    if path.is_empty() {
+        return Err(ErrorData::invalid_params("路径不能为空", None));
+    }
+    if path.contains('\0') {
+        return Err(ErrorData::invalid_params(format!("路径包含非法字符：{input}"), None));
+    }
+    let bytes = path.as_bytes();
+    if path.starts_with("//") || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':') {
+        return Err(ErrorData::invalid_params(
+            format!("路径必须是知识库内的相对路径，不能带盘符或网络路径：{input}"),
+            None,
+        ));
+    }
+
+    let mut parts: Vec<&str> = Vec::new();
+    for seg in path.split('/') {
+        match seg {
+            "" | "." => {}
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(ErrorData::invalid_params(format!("路径超出知识库范围：{input}"), None));
+                }
+            }
+            s if s.contains(':') => {
+                return Err(ErrorData::invalid_params(format!("路径包含非法字符 ':'：{input}"), None));
+            }
+            s => parts.push(s),
+        }
+    }
+    if parts.is_empty() {
+        return Err(ErrorData::invalid_params("路径不能为空", None));
+    }
+    Ok(parts.join("/"))
+}
+
+/// The location of `rel` (a path from `clean`) under `root`, after checking
+/// that no symlink on the way leads out of the vault. The path itself need
+/// not exist yet.
+pub fn resolve(root: &Path, rel: &str) -> Result<PathBuf, ErrorData> {
+    let canonical_root = root
+        .canonicalize()
+        .map_err(|e| ErrorData::internal_error(format!("无法访问知识库目录: {e}"), None))?;
+    let path = root.join(rel);
+
+    // Below the deepest existing entry nothing exists yet, so nothing there
+    // can be a link.
+    let mut existing = path.as_path();
+    while existing.symlink_metadata().is_err() {
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => break,
+        }
+    }
+    let real = existing.canonicalize().map_err(|_| {
+        ErrorData::invalid_params(format!("路径包含无法解析的符号链接：{rel}"), None)
+    })?;
+    if !real.starts_with(&canonical_root) {
+        return Err(ErrorData::invalid_params(
+            format!("路径通过符号链接指向知识库之外：{rel}"),
+            None,
+        ));
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempVault;
+
+    /// A fresh directory with a `vault/` and an `outside/` next to it.
+    fn fixture() -> (TempVault, PathBuf, PathBuf) {
+        let base = TempVault::new(&[("vault/tech/note.md", "inside"), ("outside/secret.md", "outside")]);
+        let (vault, outside) = (base.join("vault"), base.join("outside"));
+        (base, vault, outside)
+    }
+
+    #[test]
+    fn clean_normalizes_relative_paths() {
+        assert_eq!(clean("tech/note.md").unwrap(), "tech/note.md");
+        assert_eq!(clean(" /tech/./note.md ").unwrap(), "tech/note.md");
+        assert_eq!(clean("tech\\note.md").unwrap(), "tech/note.md");
+        assert_eq!(clean("tech/drafts/../note.md").unwrap(), "tech/note.md");
+    }
+
+    #[test]
+    fn clean_accepts_names_containing_dots() {
+        assert_eq!(clean("tech/v1..v2.md").unwrap(), "tech/v1..v2.md");
+        assert_eq!(clean("notes../a.md").unwrap(), "notes../a.md");
+        assert_eq!(clean("tech/...md").unwrap(), "tech/...md");
+    }
+
+    #[test]
+    fn clean_rejects_parent_traversal() {
+        assert!(clean("../secret.md").is_err());
+        assert!(clean("tech/../../secret.md").is_err());
+        assert!(clean("tech\\..\\..\\secret.md").is_err());
+        assert!(clean("./..").is_err());
+    }
+
+    #[test]
+    fn clean_keeps_absolute_paths_inside_the_vault() {
+        // a leading '/' is the vault root, never the filesystem root
+        assert_eq!(clean("/etc/passwd").unwrap(), "etc/passwd");
+        assert!(clean("/../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn clean_rejects_windows_prefixes() {
+        assert!(clean("C:\\Windows\\win.ini").is_err());
+        assert!(clean("c:/Windows/win.ini").is_err());
+        assert!(clean("C:secret.md").is_err());
+        assert!(clean("\\\\server\\share\\secret.md").is_err());
+        assert!(clean("//server/share/secret.md").is_err());
+        assert!(clean("tech/note.md:stream").is_err());
+    }
+
+    #[test]
+    fn clean_rejects_empty_and_nul() {
+        assert!(clean("").is_err());
+        assert!(clean("  ").is_err());
+        assert!(clean("/").is_err());
+        assert!(clean("tech/..").is_err());
+        assert!(clean("tech/note\0.md").is_err());
+    }
+
+    #[test]
+    fn resolve_allows_plain_and_new_paths() {
+        let (_base, vault, _) = fixture();
+        assert_eq!(resolve(&vault, "tech/note.md").unwrap(), vault.join("tech/note.md"));
+        assert_eq!(resolve(&vault, "new/dir/note.md").unwrap(), vault.join("new/dir/note.md"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_rejects_symlinked_file_outside() {
+        let (_base, vault, outside) = fixture();
+        std::os::unix::fs::symlink(outside.join("secret.md"), vault.join("tech/link.md")).unwrap();
+        assert!(resolve(&vault, "tech/link.md").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_rejects_paths_through_symlinked_dir_outside() {
+        let (_base, vault, outside) = fixture();
+        std::os::unix::fs::symlink(&outside, vault.join("escape")).unwrap();
+        assert!(resolve(&vault, "escape/secret.md").is_err());
+        // creating a file through the link is refused as well
+        assert!(resolve(&vault, "escape/new.md").is_err());
+        assert!(resolve(&vault, "escape/sub/new.md").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_rejects_relative_symlink_climbing_out() {
+        let (_base, vault, _) = fixture();
+        std::os::unix::fs::symlink("../../outside/secret.md", vault.join("tech/up.md")).unwrap();
+        assert!(resolve(&vault, "tech/up.md").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_rejects_dangling_symlink() {
+        let (_base, vault, _) = fixture();
+        std::os::unix::fs::symlink("/nonexistent/obsidian-mcp", vault.join("tech/dangling.md")).unwrap();
+        assert!(resolve(&vault, "tech/dangling.md").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_follows_symlinks_inside_the_vault() {
+        let (_base, vault, _) = fixture();
+        std::os::unix::fs::symlink(vault.join("tech/note.md"), vault.join("alias.md")).unwrap();
+        std::os::unix::fs::symlink("tech", vault.join("t")).unwrap();
+        assert!(resolve(&vault, "alias.md").is_ok());
+        assert!(resolve(&vault, "t/note.md").is_ok());
+        assert!(resolve(&vault, "t/new.md").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_accepts_a_symlinked_vault_root() {
+        let (_base, vault, _) = fixture();
+        let link = vault.parent().unwrap().join("vault-link");
+        std::os::unix::fs::symlink(&vault, &link).unwrap();
+        assert!(resolve(&link, "tech/note.md").is_ok());
+    }
+}
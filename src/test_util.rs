@@ -0,0 +1,53 @@
+// ---------------------------------------------------------------------------
+// Test helpers shared by the unit tests of every module.
+// ---------------------------------------------------------------------------
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A fresh directory under the system temp dir, removed again on drop.
+/// Derefs to its path so it can stand in wherever a vault root is expected.
+pub struct TempVault {
+    root: PathBuf,
+}
+
+impl TempVault {
+    /// A new directory holding `files`, given as (relative path, content).
+    pub fn new(files: &[(&str, &str)]) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "obsidian-mcp-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let vault = TempVault { root };
+        for (rel, content) in files {
+            vault.write(rel, content);
+        }
+        vault
+    }
+
+    /// Write `content` to `rel`, creating parent directories as needed.
+    pub fn write(&self, rel: &str, content: &str) {
+        let path = self.root.join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+}
+
+impl Deref for TempVault {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Drop for TempVault {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
@@ -0,0 +1,88 @@
+// ---------------------------------------------------------------------------
+// Enabled tools
+// ---------------------------------------------------------------------------
+//
+// Which tools the client sees is decided at startup:
+//   --read-only / OBSIDIAN_READ_ONLY=1   drop every tool that modifies the vault
+//   OBSIDIAN_ENABLED_TOOLS=a,b,c         only register these tools
+//   enabled_tools = [...]                the same in the config file; the
+//                                        environment variable wins if set
+// Disabled tools are left out of `tools/list` and refused by `tools/call`.
+
+use std::collections::BTreeSet;
+
+/// Tools that change files in the vault.
+pub const MUTATING_TOOLS: &[&str] = &[
+    "write_note",
+    "move_note",
+    "delete_note",
+    "restore_note",
+    "restore_note_version",
+    "add_attachment",
+    "write_canvas",
+];
+
+#[derive(Debug, Clone)]
+pub struct ToolSet {
+    enabled: BTreeSet<String>,
+    read_only: bool,
+}
+
+impl ToolSet {
+    /// `enabled` (default: all of `all`), minus the mutating tools when
+    /// `read_only`. Unknown names are an error, and so is asking for a
+    /// mutating tool in read-only mode.
+    pub fn new(all: &[String], read_only: bool, enabled: Option<Vec<String>>) -> anyhow::Result<Self> {
+        let enabled: BTreeSet<String> = match enabled {
+            Some(names) => {
+                let names: BTreeSet<String> = names
+                    .iter()
+                    .map(|n| n.trim().to_string())
+                    .filter(|n| !n.is_empty())
+                    .collect();
+                if let Some(unknown) = names.iter().find(|n| !all.contains(n)) {
+                    anyhow::bail!("unknown tool '{unknown}' in enabled tools (available: {})", all.join(", "));
+                }
+                if read_only {
+                    if let Some(name) = names.iter().find(|n| MUTATING_TOOLS.contains(&n.as_str())) {
+                        anyhow::bail!("tool '{name}' modifies the vault and cannot be enabled in read-only mode");
+                    }
+                }
+                names
+            }
+            None => all
+                .iter()
+                .filter(|n| !read_only || !MUTATING_TOOLS.contains(&n.as_str()))
+                .cloned()
+                .collect(),
+        };
+        if enabled.is_empty() {
+            anyhow::bail!("no tools enabled");
+        }
+        Ok(Self { enabled, read_only })
+    }
+
+    /// Read `OBSIDIAN_ENABLED_TOOLS` (comma-separated, overriding `configured`
+    /// from the config file) and, unless the CLI already asked for it,
+    /// `OBSIDIAN_READ_ONLY`.
+    pub fn from_env(all: &[String], read_only: bool, configured: Option<Vec<String>>) -> anyhow::Result<Self> {
+        let read_only = read_only
+            || std::env::var("OBSIDIAN_READ_ONLY")
+                .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+                .unwrap_or(false);
+        let enabled = std::env::var("OBSIDIAN_ENABLED_TOOLS")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.split(',').map(str::to_string).collect())
+            .or(configured);
+        Self::new(all, read_only, enabled)
+    }
+
+    pub fn allows(&self, tool: &str) -> bool {
+        self.enabled.contains(tool)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+}
@@ -0,0 +1,245 @@
+// ---------------------------------------------------------------------------
+// Resource change detection
+// ---------------------------------------------------------------------------
+//
+// Each index rebuild (after a tool call, or from the background poll) takes
+// a cheap fingerprint of the vault: mtime + size of every file, and the notes
+// carrying each tag. Comparing it with the previous fingerprint tells which
+// subscribed resources changed and whether the resource list itself changed.
+//
+// The background poll catches edits made outside the server. There is one
+// `Poller` per vault, shared by all sessions, and it only runs while some
+// session has subscriptions.
+
+use crate::auth::Scope;
+use crate::config::Config;
+use crate::resources::{self, DIR_PREFIX, NOTE_PREFIX, TAG_PREFIX};
+use crate::VaultIndex;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Default)]
+pub struct VaultState {
+    files: HashMap<String, (Option<SystemTime>, u64)>,
+    tags: HashMap<String, BTreeSet<String>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// Subscribed URIs whose content changed.
+    pub updated: Vec<String>,
+    /// Files were added or removed.
+    pub list_changed: bool,
+}
+
+/// Read `OBSIDIAN_WATCH_INTERVAL` (seconds between polls, default 5,
+/// `0` = only detect changes when a request rebuilds the index).
+pub fn interval_from_env() -> Option<Duration> {
+    let secs: u64 = std::env::var("OBSIDIAN_WATCH_INTERVAL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+type Listener = Arc<dyn Fn() + Send + Sync>;
+
+#[derive(Default)]
+struct PollerState {
+    /// By session id.
+    listeners: HashMap<u64, Listener>,
+    /// Stops the running poll task.
+    task: Option<CancellationToken>,
+}
+
+/// Calls the listening sessions every interval, from one task per vault that
+/// runs only while there are listeners.
+pub struct Poller {
+    interval: Option<Duration>,
+    state: Arc<Mutex<PollerState>>,
+}
+
+impl Poller {
+    pub fn new(interval: Option<Duration>) -> Self {
+        Self {
+            interval,
+            state: Arc::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(interval_from_env())
+    }
+
+    /// `None` when polling is disabled.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// Call `poll` for session `id` on every tick, starting the task if it is
+    /// the first listener. Must be called inside a tokio runtime.
+    pub fn add(&self, id: u64, poll: impl Fn() + Send + Sync + 'static) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.listeners.insert(id, Arc::new(poll));
+        if state.task.is_some() {
+            return;
+        }
+        let ct = CancellationToken::new();
+        state.task = Some(ct.clone());
+        drop(state);
+
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            ticker.tick().await;
+            loop {
+                tokio::select! {
+                    _ = ct.cancelled() => break,
+                    _ = ticker.tick() => {}
+                }
+                let listeners: Vec<Listener> = match state.lock() {
+                    Ok(state) => state.listeners.values().cloned().collect(),
+                    Err(_) => break,
+                };
+                let _ = tokio::task::spawn_blocking(move || {
+                    for poll in listeners {
+                        poll();
+                    }
+                })
+                .await;
+            }
+        });
+    }
+
+    /// Stop calling session `id`; the task ends with the last listener.
+    pub fn remove(&self, id: u64) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.listeners.remove(&id);
+        if state.listeners.is_empty() {
+            if let Some(ct) = state.task.take() {
+                ct.cancel();
+            }
+        }
+    }
+}
+
+pub fn scan(index: &VaultIndex, config: &Config, scope: &Scope) -> VaultState {
+    let root = &config.vault_root;
+    let fingerprint = |rel: &str| {
+        let meta = std::fs::metadata(root.join(rel)).ok();
+        (
+            meta.as_ref().and_then(|m| m.modified().ok()),
+            meta.map(|m| m.len()).unwrap_or(0),
+        )
+    };
+
+    let mut files: HashMap<_, _> = index
+        .entries
+        .iter()
+        .map(|e| (e.rel_path.clone(), fingerprint(&e.rel_path)))
+        .collect();
+    for rel in resources::attachment_paths(config, scope) {
+        let fp = fingerprint(&rel);
+        files.insert(rel, fp);
+    }
+
+    let tags = index
+        .tag_map
+        .iter()
+        .map(|(tag, indices)| {
+            let paths = indices.iter().map(|&i| index.entries[i].rel_path.clone()).collect();
+            (tag.clone(), paths)
+        })
+        .collect();
+
+    VaultState { files, tags }
+}
+
+pub fn diff(old: &VaultState, new: &VaultState, subscriptions: &HashSet<String>) -> Changes {
+    let list_changed = old.files.len() != new.files.len()
+        || old.files.keys().any(|k| !new.files.contains_key(k));
+
+    let changed: Vec<&String> = old
+        .files
+        .keys()
+        .chain(new.files.keys())
+        .filter(|k| old.files.get(*k) != new.files.get(*k))
+        .collect();
+
+    let mut updated: Vec<String> = subscriptions
+        .iter()
+        .filter(|uri| {
+            if let Some(rest) = uri.strip_prefix(NOTE_PREFIX) {
+                let rel = resources::decode(rest).unwrap_or_default();
+                let rel = rel.trim_matches('/');
+                old.files.get(rel) != new.files.get(rel)
+            } else if let Some(rest) = uri.strip_prefix(TAG_PREFIX) {
+                let tag = resources::decode(rest).unwrap_or_default().to_lowercase();
+                let notes = |s: &VaultState| s.tags.get(&tag).cloned().unwrap_or_default();
+                let (before, after) = (notes(old), notes(new));
+                // membership changed, or a listed note was edited (title / tags)
+                before != after || changed.iter().any(|p| after.contains(*p))
+            } else if let Some(rest) = uri.strip_prefix(DIR_PREFIX) {
+                let dir = resources::decode(rest).unwrap_or_default();
+                let dir = dir.trim_matches('/');
+                let prefix = format!("{dir}/");
+                changed
+                    .iter()
+                    .any(|p| p.ends_with(".md") && (dir.is_empty() || p.starts_with(&prefix)))
+            } else {
+                false
+            }
+        })
+        .cloned()
+        .collect();
+    updated.sort();
+
+    Changes { updated, list_changed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn poller_runs_only_while_someone_listens() {
+        let poller = Poller::new(Some(Duration::from_millis(10)));
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let counter = ticks.clone();
+        poller.add(1, move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        let counter = ticks.clone();
+        poller.add(2, move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(ticks.load(Ordering::Relaxed) >= 2);
+
+        poller.remove(1);
+        poller.remove(2);
+        assert!(poller.state.lock().unwrap().task.is_none());
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let stopped = ticks.load(Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(ticks.load(Ordering::Relaxed), stopped);
+    }
+
+    #[test]
+    fn disabled_poller_never_starts() {
+        let poller = Poller::new(None);
+        poller.add(1, || {});
+        assert!(poller.state.lock().unwrap().listeners.is_empty());
+    }
+}